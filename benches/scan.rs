@@ -0,0 +1,56 @@
+//! Temps du parcours de l'arborescence, séquentiel puis parallèle, sur un arbre
+//! de 100 000 fichiers créé dans le répertoire temporaire.
+//!
+//! ```bash
+//! cargo bench --bench scan
+//! ```
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::TempDir;
+use gitignore_tui::{build_full_tree, ScanOptions};
+use std::fs;
+use std::time::{Duration, Instant};
+
+const DIRS: usize = 100;
+const SUBDIRS: usize = 10;
+const FILES: usize = 100;
+const RUNS: usize = 3;
+
+/// Meilleur temps sur RUNS parcours, et le nombre de nodes obtenus
+fn time_scan(root: &std::path::Path, threads: usize) -> (Duration, usize) {
+    let opts = ScanOptions { threads, ..Default::default() };
+    let mut best = Duration::MAX;
+    let mut count = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let nodes = build_full_tree(root, &opts, &mut Vec::new()).unwrap();
+        best = best.min(start.elapsed());
+        count = nodes.len();
+    }
+    (best, count)
+}
+
+fn main() {
+    let tmp = TempDir::new("bench");
+    let root = tmp.path();
+    for d in 0..DIRS {
+        for s in 0..SUBDIRS {
+            let dir = root.join(format!("d{d:03}/s{s:02}"));
+            fs::create_dir_all(&dir).unwrap();
+            for f in 0..FILES {
+                fs::write(dir.join(format!("f{f:03}.txt")), "").unwrap();
+            }
+        }
+    }
+
+    let (sequential, count) = time_scan(root, 1);
+    let (parallel, parallel_count) = time_scan(root, 0);
+    assert_eq!(count, parallel_count);
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    println!("{} nodes, best of {} runs", count, RUNS);
+    println!("sequential:            {:>8.1} ms", sequential.as_secs_f64() * 1000.0);
+    println!("parallel ({:>2} threads): {:>8.1} ms", threads, parallel.as_secs_f64() * 1000.0);
+}
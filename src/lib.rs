@@ -0,0 +1,838 @@
+//! Moteur de gitignore-tui, sans interface : lecture des règles simples du .gitignore,
+//! arbre complet du projet en pré-ordre, et état ignoré / exception de chaque entrée.
+//!
+//! ```no_run
+//! use gitignore_tui::{apply_rules_to_nodes, build_full_tree, parse_gitignore, ScanOptions};
+//! use std::path::Path;
+//!
+//! let root = Path::new(".");
+//! let rules = parse_gitignore(root)?;
+//! let mut nodes = build_full_tree(root, &ScanOptions::default(), &mut Vec::new())?;
+//! apply_rules_to_nodes(&mut nodes, &rules, false);
+//! for n in nodes.iter().filter(|n| n.mark) {
+//!     println!("{}", n.rel);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use log::debug;
+
+/// État d'une entrée vis-à-vis des règles du .gitignore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    C, // Règle classique dans .gitignore
+    E, // Exception (!...)
+    N, // Normal (aucune règle)
+}
+
+/// Entrée de l'arbre (voir build_full_tree), avec son état d'ignore
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub path: PathBuf,
+    /// Chemin relatif à la racine, séparé par "/" ("" pour le noeud racine)
+    pub rel: String,
+    pub name: String,
+    pub is_dir: bool,
+    pub depth: usize,
+    pub expanded: bool,
+    pub mode: Mode,
+    pub mark: bool,
+    pub cpt_exception: usize,
+    pub cpt_mixed_marks: usize,
+    pub generic_mark: bool, // NEW : fichier marqué par une règle générique (*.png, etc.)
+    /// Ligne saisie à la main, écrite telle quelle à la place des règles générées
+    pub override_line: Option<String>,
+    /// Date de modification (fichiers seulement ; None si illisible)
+    pub mtime: Option<SystemTime>,
+    /// Bits de permission Unix, lus au parcours (None si illisible ou hors Unix)
+    pub permissions: Option<u32>,
+    /// Taille en octets (fichiers seulement ; 0 pour un répertoire ou si illisible)
+    pub size: u64,
+    /// Répertoire dont le .gitignore reçoit la règle du node (None = .gitignore racine)
+    pub target: Option<PathBuf>,
+    /// Épinglé (touche !) : jamais ignoré, les bascules récursives en font une exception
+    pub pinned: bool,
+    /// Ignoré par un fichier secondaire (--also) : indice du fichier dans ScanOptions::also.
+    /// Affichage seulement, ces règles ne sont ni modifiables ni enregistrées.
+    pub also: Option<usize>,
+    /// Ignoré par le fichier d'exclusions global de git (voir ScanOptions::excludes_file).
+    /// Affichage seulement : rien n'en est écrit dans le .gitignore du dépôt.
+    pub global: bool,
+    /// Répertoire qu'on n'a pas pu lire (permissions) : gardé dans l'arbre, sans contenu
+    pub unreadable: bool,
+    /// Contenu parcouru ; faux pour un répertoire laissé de côté par --lazy (voir load_children)
+    pub loaded: bool,
+}
+
+/// Règle simple du .gitignore (voir parse_gitignore_content)
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub pattern: String, // chemin relatif normalisé "target/flycheck0"
+    pub mode: Mode,      // C ou E
+    pub line: String,    // ligne d'origine, telle qu'écrite dans le .gitignore
+    pub dir_only: bool,  // ligne terminée par "/" : ne porte que sur un répertoire
+    /// Répertoire du .gitignore imbriqué d'où vient la règle (None = .gitignore racine)
+    pub source: Option<PathBuf>,
+}
+
+/// Pattern interne d'une règle qui porte sur toute l'arborescence ("*" ou "/*")
+pub const ROOT_PATTERN: &str = "*";
+
+/// Texte significatif d'une ligne de .gitignore, comme git le lit : les espaces
+/// de fin sont ignorés sauf s'ils sont échappés ("\ "), qui restent tels quels ici.
+/// Les blancs de début sont aussi retirés.
+pub fn gitignore_line(line: &str) -> &str {
+    let line = line.trim_start().trim_end_matches('\r');
+    let bytes = line.as_bytes();
+    let mut end = line.len();
+    while end > 0 && bytes[end - 1] == b' ' {
+        // Espace échappé : précédé d'un nombre impair de "\"
+        let backslashes = bytes[..end - 1].iter().rev().take_while(|&&b| b == b'\\').count();
+        if backslashes % 2 == 1 {
+            break;
+        }
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// Parsing du .gitignore de `root` (voir parse_gitignore_content).
+pub fn parse_gitignore(root: &Path) -> Result<Vec<Rule>> {
+    let gitignore_path = root.join(".gitignore");
+    if !gitignore_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&gitignore_path)
+        .context("Reading existing .gitignore")?;
+
+    Ok(parse_gitignore_content(&content))
+}
+
+/// Parsing du contenu d'un .gitignore :
+/// - on garde uniquement les règles SANS wildcard compliqué (* ? [)
+///   sauf "*" ou "/*" que l'on accepte comme "tout le repo"
+/// - "/" et "." ne désignent aucun chemin pour git : ils sont ignorés (et conservés tels quels)
+/// - on reconnaît "dir/*" comme "dir"
+/// - on accepte les règles avec ou sans "/" en tête, mais on normalise sans "/"
+/// - on distingue C (ligne normale) et E (ligne commençant par !)
+/// - on retourne une liste ordonnée de règles
+pub fn parse_gitignore_content(content: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+
+    for line in content.lines() {
+        let trimmed = gitignore_line(line);
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let mut pattern = trimmed;
+        let mut mode = Mode::C;
+
+        // Exception ?
+        if pattern.starts_with('!') {
+            mode = Mode::E;
+            pattern = &pattern[1..];
+        }
+
+        // On enlève un éventuel "/" au début (on normalise les chemins sans "/")
+        if pattern.starts_with('/') {
+            pattern = &pattern[1..];
+        }
+
+        // Cas spécial : "*" ou "/*" = tout le repo
+        if pattern == "*" {
+            debug!("rule parsed: {:?} -> {:?} root", trimmed, mode);
+            rules.push(Rule {
+                pattern: ROOT_PATTERN.to_string(), // on encode le "tout" avec "*"
+                mode,
+                line: trimmed.to_string(),
+                dir_only: false,
+                source: None,
+            });
+            continue;
+        }
+
+        // "/" (vide une fois le "/" retiré), "." ou "./" ne correspondent à aucun
+        // chemin pour git : ce ne sont PAS des règles sur la racine.
+        if pattern.is_empty() || pattern.trim_end_matches('/') == "." {
+            debug!("rule skipped (matches nothing): {:?}", trimmed);
+            continue;
+        }
+
+        // On traite "xxx/*" comme "xxx" (répertoire)
+        if let Some(stripped) = pattern.strip_suffix("/*") {
+            pattern = stripped;
+        }
+
+        // On enlève un éventuel "/" final, en retenant que la règle ne vise qu'un répertoire
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+
+        if pattern.is_empty() {
+            debug!("rule skipped (empty pattern): {:?}", trimmed);
+            continue;
+        }
+
+        // On ignore les règles trop génériques avec wildcard,
+        // sauf celles déjà gérées ci-dessus.
+        if pattern.contains('*') || pattern.contains('?') || pattern.contains('[') {
+            debug!("rule skipped (wildcard, handled as generic): {:?}", trimmed);
+            continue;
+        }
+
+        // "\ " : espace littéral (significatif en fin de ligne)
+        let normalized = pattern.replace("\\ ", " ").replace("\\", "/");
+        debug!("rule parsed: {:?} -> {:?} {}", trimmed, mode, normalized);
+
+        rules.push(Rule {
+            pattern: normalized,
+            mode,
+            line: trimmed.to_string(),
+            dir_only,
+            source: None,
+        });
+    }
+
+    rules
+}
+
+/// .gitignore d'un sous-répertoire, trouvé dans l'arbre
+#[derive(Debug, Clone)]
+pub struct NestedGitignore {
+    /// Répertoire qui contient le fichier (même forme que Node::path)
+    pub dir: PathBuf,
+    /// Chemin relatif de ce répertoire
+    pub rel: String,
+    pub content: String,
+}
+
+impl NestedGitignore {
+    /// Règles simples du fichier, ramenées à la racine : "build" dans sub/.gitignore
+    /// devient "sub/build" et ne porte donc que sous sub. Un "*" (tout le contenu du
+    /// répertoire, pas une entrée) n'est pas repris : il reste tel quel dans le fichier.
+    pub fn rules(&self) -> Vec<Rule> {
+        parse_gitignore_content(&self.content)
+            .into_iter()
+            .filter(|rule| rule.pattern != ROOT_PATTERN)
+            .map(|rule| Rule {
+                pattern: format!("{}/{}", self.rel, rule.pattern),
+                source: Some(self.dir.clone()),
+                ..rule
+            })
+            .collect()
+    }
+}
+
+/// Les .gitignore des sous-répertoires de l'arbre, un fichier plus profond après ceux
+/// de ses parents : ajoutées dans cet ordre après celles de la racine, ses règles
+/// l'emportent, comme avec git. Un fichier illisible est signalé dans `log` et ignoré.
+pub fn read_nested_gitignores(nodes: &[Node], log: &mut Vec<String>) -> Vec<NestedGitignore> {
+    let mut files: Vec<&Node> = nodes
+        .iter()
+        .filter(|n| !n.is_dir && n.depth > 1 && n.name == ".gitignore")
+        .collect();
+    files.sort_by_key(|n| n.depth);
+
+    files
+        .into_iter()
+        .filter_map(|n| {
+            let content = match fs::read_to_string(&n.path) {
+                Ok(content) => content,
+                Err(e) => {
+                    log.push(format!("Skipped {}: {}", n.rel, e));
+                    return None;
+                }
+            };
+            Some(NestedGitignore {
+                dir: n.path.parent()?.to_path_buf(),
+                rel: n.rel.strip_suffix("/.gitignore")?.to_string(),
+                content,
+            })
+        })
+        .collect()
+}
+
+/// Relation entre un chemin relatif et le pattern (normalisé) d'une règle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleMatch {
+    None,
+    /// La règle porte exactement sur ce chemin
+    Exact,
+    /// Le chemin est sous le chemin de la règle
+    Descendant,
+}
+
+/// Source unique de la correspondance règle/chemin, partagée par
+/// apply_rules_to_nodes et should_be_ignored.
+/// La règle racine ("*") porte exactement sur la racine virtuelle (chemin vide)
+/// et englobe tout le reste de l'arborescence.
+pub fn match_rule(rel: &str, pattern: &str) -> RuleMatch {
+    if pattern == ROOT_PATTERN {
+        return if rel.is_empty() {
+            RuleMatch::Exact
+        } else {
+            RuleMatch::Descendant
+        };
+    }
+
+    if rel == pattern {
+        RuleMatch::Exact
+    } else if rel.starts_with(pattern)
+        && rel.len() > pattern.len()
+        && rel.as_bytes()[pattern.len()] == b'/'
+    {
+        RuleMatch::Descendant
+    } else {
+        RuleMatch::None
+    }
+}
+
+/// Options du parcours de l'arborescence
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// --all : on montre tous les fichiers, quelle que soit la source d'ignore.
+    /// Prioritaire sur toutes les passes qui masquent ou élaguent des entrées.
+    pub all: bool,
+    /// --ignore-case : les règles du .gitignore ignorent la casse.
+    /// Modifiable à l'exécution (touche I), d'où sa place dans l'état.
+    pub ignore_case: bool,
+    /// --strict : tout le .gitignore est évalué par le matcher du crate `ignore`, comme git
+    /// (ancrage, "/" final, ordre des négations), au prix du modèle C/E : lecture seule.
+    pub strict: bool,
+    /// Levé par Ctrl+C pendant le parcours initial (voir ScanCancel) :
+    /// le parcours s'arrête au répertoire suivant avec l'erreur ScanCancelled.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// --also : fichiers d'ignore secondaires, appliqués en lecture seule par-dessus l'arbre
+    pub also: Vec<PathBuf>,
+    /// Fichier d'exclusions global de git (core.excludesFile ou ~/.config/git/ignore),
+    /// signalé dans l'arbre sans être enregistré
+    pub excludes_file: Option<PathBuf>,
+    /// --exclude : fichier lu et écrit à la place du .gitignore racine (.git/info/exclude)
+    pub exclude_file: Option<PathBuf>,
+    /// --no-hidden : pas de fichiers ni de répertoires dont le nom commence par "."
+    pub no_hidden: bool,
+    /// Threads du parcours de l'arborescence (0 : un par cœur, 1 : séquentiel)
+    pub threads: usize,
+    /// --lazy : on ne lit que les entrées de la racine, le contenu d'un répertoire
+    /// l'est à sa première ouverture (voir load_children)
+    pub lazy: bool,
+}
+
+/// Répertoires des VCS, écartés du parcours sauf avec --all
+pub const VCS_DIRS: &[&str] = &[".git", ".jj"];
+
+impl ScanOptions {
+    /// Vrai si l'entrée `name` doit être écartée du parcours : répertoires des VCS et
+    /// --no-hidden. Toute passe de masquage doit passer par ici pour que --all la désactive
+    /// (les entrées ignorées et celles des exclusions globales ne sont jamais masquées).
+    pub fn hides(&self, name: &str) -> bool {
+        if self.all {
+            return false;
+        }
+        // Les données internes du VCS ne sont jamais à ignorer
+        if VCS_DIRS.contains(&name) {
+            return true;
+        }
+        // Les .gitignore restent : ce sont eux qu'on lit et qu'on écrit
+        self.no_hidden && name.starts_with('.') && name != ".gitignore"
+    }
+}
+
+/// Parcours interrompu par Ctrl+C : l'arbre partiel est abandonné
+#[derive(Debug)]
+pub struct ScanCancelled;
+
+impl std::fmt::Display for ScanCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "scan cancelled")
+    }
+}
+
+impl std::error::Error for ScanCancelled {}
+
+/// Bits de permission d'une entrée (Unix seulement)
+#[cfg(unix)]
+fn unix_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Sous-arbre confié à un autre thread (qui commence par le node du répertoire),
+/// suivi des entrées parcourues après lui par le thread courant.
+struct Part<'scope> {
+    running: std::thread::ScopedJoinHandle<'scope, Result<(Vec<Node>, Vec<String>)>>,
+    nodes: Vec<Node>,
+    log: Vec<String>,
+}
+
+/// Où ajouter la suite du parcours : directement dans `nodes` tant qu'aucun
+/// sous-arbre n'est parti sur un autre thread, sinon après le dernier parti.
+fn sink<'a>(
+    parts: &'a mut [Part<'_>],
+    nodes: &'a mut Vec<Node>,
+    log: &'a mut Vec<String>,
+) -> (&'a mut Vec<Node>, &'a mut Vec<String>) {
+    match parts.last_mut() {
+        Some(part) => (&mut part.nodes, &mut part.log),
+        None => (nodes, log),
+    }
+}
+
+/// Threads disponibles pour le parcours, en plus de celui qui l'a lancé.
+/// On ne passe pas par ignore::WalkBuilder::build_parallel : ses entrées arrivent
+/// dans le désordre, alors que l'arbre est un pré-ordre trié (répertoires d'abord)
+/// avec un journal identique à celui d'un parcours séquentiel. Ici chaque thread
+/// produit directement le bloc de son sous-arbre, recollé à sa place sans tri.
+struct Workers(AtomicUsize);
+
+impl Workers {
+    fn new(threads: usize) -> Self {
+        let threads = match threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        Workers(AtomicUsize::new(threads - 1))
+    }
+
+    /// Réserve un thread s'il en reste un (à rendre avec release)
+    fn take(&self) -> bool {
+        self.0.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1)).is_ok()
+    }
+
+    fn release(&self) {
+        self.0.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// Ajoute à `nodes` le contenu de `current` (en pré-ordre, à la profondeur `depth`), voir
+/// build_full_tree. Avec --lazy, les sous-répertoires sont ajoutés sans leur contenu.
+#[allow(clippy::too_many_arguments)]
+fn build_dir(
+    current: &Path,
+    current_rel: &str,
+    depth: usize,
+    opts: &ScanOptions,
+    workers: &Workers,
+    ancestors: &mut Vec<PathBuf>, // chemins canoniques des répertoires parcourus
+    nodes: &mut Vec<Node>,
+    log: &mut Vec<String>,
+) -> Result<()> {
+    if opts.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+        return Err(ScanCancelled.into());
+    }
+
+    let read = match fs::read_dir(current) {
+        Ok(read) => read,
+        // Sous-répertoire illisible : on le signale et on continue le parcours.
+        // Son node est le dernier ajouté, ses enfants n'ayant pas encore été lus.
+        Err(err) if depth > 1 => {
+            log.push(format!("Skipped unreadable directory {}: {}", current.display(), err));
+            if let Some(node) = nodes.last_mut() {
+                node.unreadable = true;
+            }
+            return Ok(());
+        }
+        Err(err) => return Err(err).context(format!("Reading directory {:?}", current)),
+    };
+
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+
+    for ent in read {
+        let e = match ent {
+            Ok(e) => e,
+            Err(err) => {
+                log.push(format!("Skipped an entry of {}: {}", current.display(), err));
+                continue;
+            }
+        };
+        let p = e.path();
+        let name = p
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "".into());
+        if opts.hides(&name) {
+            continue;
+        }
+        // Le verrou de la session en cours n'a rien à faire dans l'arbre
+        if current_rel.is_empty() && name == LOCK_FILE {
+            continue;
+        }
+        if p.is_dir() {
+            dirs.push((p, name));
+        } else {
+            files.push((p, name));
+        }
+    }
+
+    dirs.sort_by_key(|(_, n)| n.clone());
+    files.sort_by_key(|(_, n)| n.clone());
+
+    std::thread::scope(|scope| {
+        let mut parts: Vec<Part> = Vec::new();
+
+        for (p, n) in dirs.into_iter().chain(files) {
+            let is_dir = p.is_dir();
+            let rel = if current_rel.is_empty() {
+                n.clone()
+            } else {
+                format!("{}/{}", current_rel, n)
+            };
+            let metadata = fs::metadata(&p).ok();
+            let mtime = if is_dir {
+                None
+            } else {
+                metadata.as_ref().and_then(|m| m.modified().ok())
+            };
+            let permissions = metadata.as_ref().and_then(unix_mode);
+            let size = if is_dir { 0 } else { metadata.as_ref().map_or(0, |m| m.len()) };
+            let mut node = Node {
+                path: p.clone(),
+                rel: rel.clone(),
+                name: n,
+                is_dir,
+                depth,
+                expanded: false,
+                mode: Mode::N,
+                mark: false,
+                cpt_exception: 0,
+                cpt_mixed_marks: 0,
+                generic_mark: false, // NEW
+                override_line: None,
+                mtime,
+                permissions,
+                size,
+                target: None,
+                pinned: false,
+                also: None,
+                global: false,
+                unreadable: false,
+                loaded: !(is_dir && opts.lazy),
+            };
+            if !is_dir {
+                sink(&mut parts, nodes, log).0.push(node);
+                continue;
+            }
+
+            // Un lien symbolique vers un répertoire parent bouclerait à l'infini
+            let canonical = fs::canonicalize(&p).unwrap_or_else(|_| p.clone());
+            if ancestors.contains(&canonical) {
+                let (nodes, log) = sink(&mut parts, nodes, log);
+                // Rien à charger plus tard non plus
+                node.loaded = true;
+                nodes.push(node);
+                log.push(format!(
+                    "Symlink cycle avoided: {} -> {}",
+                    p.display(),
+                    canonical.display()
+                ));
+                continue;
+            }
+            if opts.lazy {
+                sink(&mut parts, nodes, log).0.push(node);
+                continue;
+            }
+
+            if workers.take() {
+                let mut ancestors = ancestors.clone();
+                ancestors.push(canonical);
+                let running = scope.spawn(move || {
+                    let mut nodes = vec![node];
+                    let mut log = Vec::new();
+                    let result = build_dir(&p, &rel, depth + 1, opts, workers, &mut ancestors, &mut nodes, &mut log);
+                    workers.release();
+                    result.map(|()| (nodes, log))
+                });
+                parts.push(Part { running, nodes: Vec::new(), log: Vec::new() });
+            } else {
+                let (nodes, log) = sink(&mut parts, nodes, log);
+                nodes.push(node);
+                ancestors.push(canonical);
+                let result = build_dir(&p, &rel, depth + 1, opts, workers, ancestors, nodes, log);
+                ancestors.pop();
+                result?;
+            }
+        }
+
+        // Recollage dans l'ordre des entrées
+        for part in parts {
+            let (subtree, subtree_log) = part.running.join().unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+            nodes.extend(subtree);
+            log.extend(subtree_log);
+            nodes.extend(part.nodes);
+            log.extend(part.log);
+        }
+        Ok(())
+    })
+}
+
+/// Construit l'arbre COMPLET de tous les fichiers/répertoires (en pré-ordre).
+/// On ajoute un noeud racine "/" qui contient tout le répertoire `root`.
+/// Tous les nodes démarrent avec mode = N, mark = false
+/// Les incidents non bloquants (entrée illisible, cycle de liens symboliques évité)
+/// sont ajoutés à `log`.
+///
+/// Les sous-répertoires sont parcourus en parallèle (voir ScanOptions::threads) : chaque
+/// thread renvoie le bloc pré-ordre de son sous-arbre, recollé à sa place dans l'ordre
+/// des entrées. Le résultat, journal compris, est identique à un parcours séquentiel.
+pub fn build_full_tree(root: &Path, opts: &ScanOptions, log: &mut Vec<String>) -> Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+
+    // --- NOEUD RACINE VIRTUEL CLIQUABLE ---
+    nodes.push(Node {
+        path: root.to_path_buf(),
+        rel: String::new(),
+        name: "/".to_string(),
+        is_dir: true,
+        depth: 0,
+        expanded: true, // on commence ouvert
+        mode: Mode::N,
+        mark: false,
+        cpt_exception: 0,
+        cpt_mixed_marks: 0,
+        generic_mark: false, // NEW
+        override_line: None,
+        mtime: None,
+        permissions: fs::metadata(root).ok().as_ref().and_then(unix_mode),
+        size: 0,
+        target: None,
+        pinned: false,
+        also: None,
+        global: false,
+        unreadable: false,
+        loaded: true,
+    });
+
+    // Les enfants du root sont en profondeur 1
+    let mut ancestors = vec![fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())];
+    let workers = Workers::new(opts.threads);
+    build_dir(root, "", 1, opts, &workers, &mut ancestors, &mut nodes, log)?;
+    Ok(nodes)
+}
+
+/// Parcourt le répertoire `dir` (un node de l'arbre de `root`) laissé de côté par --lazy.
+/// Renvoie son node (marqué unreadable s'il n'a pas pu être lu) suivi de son contenu en
+/// pré-ordre, prêt à remplacer le node dans l'arbre : seulement ses entrées avec --lazy,
+/// tout le sous-arbre sinon.
+pub fn load_children(root: &Path, dir: &Node, opts: &ScanOptions, log: &mut Vec<String>) -> Result<Vec<Node>> {
+    let mut nodes = vec![Node { loaded: true, ..dir.clone() }];
+    // Répertoires déjà traversés depuis la racine, pour la détection des cycles
+    let mut ancestors: Vec<PathBuf> = dir
+        .path
+        .ancestors()
+        .take_while(|p| p.starts_with(root))
+        .map(|p| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf()))
+        .collect();
+    let workers = Workers::new(opts.threads);
+    build_dir(&dir.path, &dir.rel, dir.depth + 1, opts, &workers, &mut ancestors, &mut nodes, log)?;
+    Ok(nodes)
+}
+
+/// Une règle venue d'un .gitignore imbriqué y sera réécrite à la sauvegarde.
+/// Les autres ne touchent pas à la cible choisie avec T.
+fn set_target(target: &mut Option<PathBuf>, rule: &Rule) {
+    if rule.source.is_some() {
+        *target = rule.source.clone();
+    }
+}
+
+/// Applique les règles, dans l'ordre (la dernière qui porte sur un node l'emporte) :
+/// état C / E exact, marks propagés aux descendants, puis compteurs recalculés.
+pub fn apply_rules_to_nodes(nodes: &mut [Node], rules: &[Rule], ignore_case: bool) {
+    // Sans casse : on compare tout en minuscules
+    let lowered: Vec<Rule>;
+    let rules = if ignore_case {
+        lowered = rules
+            .iter()
+            .map(|r| Rule { pattern: r.pattern.to_lowercase(), ..r.clone() })
+            .collect();
+        &lowered[..]
+    } else {
+        rules
+    };
+
+    for node in nodes.iter_mut() {
+        // reset de base
+        node.mode = Mode::N;
+        node.mark = false;
+        node.generic_mark = false;
+
+        let rel: Cow<str> = if ignore_case {
+            Cow::Owned(node.rel.to_lowercase())
+        } else {
+            Cow::Borrowed(&node.rel)
+        };
+
+        for rule in rules {
+            // La règle "*" porte sur la racine virtuelle et englobe toute l'arborescence.
+            // "build/" ne porte pas sur un fichier nommé build (ni, donc, sur un descendant).
+            let m = match_rule(&rel, &rule.pattern);
+            let is_exact = m == RuleMatch::Exact && (node.is_dir || !rule.dir_only);
+            let is_descendant = m == RuleMatch::Descendant;
+
+            match rule.mode {
+                Mode::C => {
+                    if is_exact {
+                        node.mode = Mode::C;
+                        node.mark = true;
+                        set_target(&mut node.target, rule);
+                    } else if is_descendant {
+                        node.mark = true;
+                        if node.mode == Mode::E {
+                            node.mode = Mode::N;
+                        }
+                    }
+                }
+                Mode::E => {
+                    if is_exact {
+                        node.mode = Mode::E;
+                        node.mark = false;
+                        set_target(&mut node.target, rule);
+                    } else if is_descendant {
+                        node.mark = false;
+                        if node.mode == Mode::C {
+                            node.mode = Mode::N;
+                        }
+                    }
+                }
+                Mode::N => {}
+            }
+        }
+    }
+
+    // cpt_exception pour tout l'arbre
+    recompute_cpt_exception(nodes);
+    // cpt_mixed_marks pour tout l'arbre
+    recompute_cpt_mixed_marks(nodes);
+}
+
+/// Index du parent de chaque node (None pour les nodes de tête de la tranche).
+/// Repose sur le pré-ordre : le parent d'un node est le dernier node
+/// qui le précède avec une depth plus petite.
+pub fn parent_indices(nodes: &[Node]) -> Vec<Option<usize>> {
+    let mut parents = Vec::with_capacity(nodes.len());
+    let mut stack: Vec<usize> = Vec::new();
+
+    for (j, n) in nodes.iter().enumerate() {
+        while stack.last().is_some_and(|&p| nodes[p].depth >= n.depth) {
+            stack.pop();
+        }
+        parents.push(stack.last().copied());
+        stack.push(j);
+    }
+    parents
+}
+
+/// Calcule cpt_exception pour chaque node de la tranche :
+/// - fichier : 1 si mode = E, sinon 0
+/// - répertoire : (1 si mode = E) + nombre d'exceptions parmi tous ses descendants
+pub fn compute_cpt_exception(nodes: &[Node]) -> Vec<usize> {
+    let parents = parent_indices(nodes);
+    let mut cpt: Vec<usize> = nodes.iter().map(|n| usize::from(n.mode == Mode::E)).collect();
+
+    // En ordre inverse, chaque node a reçu tout son sous-arbre avant de le remonter au parent
+    for j in (0..nodes.len()).rev() {
+        if let Some(p) = parents[j] {
+            cpt[p] += cpt[j];
+        }
+    }
+    cpt
+}
+
+/// Calcule cpt_mixed_marks pour chaque node de la tranche :
+/// pour un répertoire, nombre de descendants (récursif) dont la marque diffère de la sienne ;
+/// 0 pour un fichier.
+pub fn compute_cpt_mixed_marks(nodes: &[Node]) -> Vec<usize> {
+    let parents = parent_indices(nodes);
+    // Nombre de descendants, et de descendants marqués, de chaque node
+    let mut size = vec![0usize; nodes.len()];
+    let mut marked = vec![0usize; nodes.len()];
+
+    for j in (0..nodes.len()).rev() {
+        if let Some(p) = parents[j] {
+            size[p] += size[j] + 1;
+            marked[p] += marked[j] + usize::from(nodes[j].mark);
+        }
+    }
+
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| match (n.is_dir, n.mark) {
+            (false, _) => 0,
+            (true, true) => size[i] - marked[i],
+            (true, false) => marked[i],
+        })
+        .collect()
+}
+
+/// Recalcule cpt_exception pour tous les nodes (voir compute_cpt_exception).
+pub fn recompute_cpt_exception(nodes: &mut [Node]) {
+    let cpt = compute_cpt_exception(nodes);
+    for (n, c) in nodes.iter_mut().zip(cpt) {
+        n.cpt_exception = c;
+    }
+}
+
+/// Recalcule cpt_mixed_marks pour tous les nodes (voir compute_cpt_mixed_marks).
+pub fn recompute_cpt_mixed_marks(nodes: &mut [Node]) {
+    let cpt = compute_cpt_mixed_marks(nodes);
+    for (n, c) in nodes.iter_mut().zip(cpt) {
+        n.cpt_mixed_marks = c;
+    }
+}
+
+/// Vérifie si un fichier devrait être ignoré selon les règles du .gitignore,
+/// sans tenir compte de la casse si `ignore_case` (--ignore-case, touche I)
+pub fn should_be_ignored(file_path: &str, rules: &[Rule], ignore_case: bool) -> bool {
+    let mut normalized = file_path.replace("\\", "/");
+    if ignore_case {
+        normalized = normalized.to_lowercase();
+    }
+    let mut should_ignore = false;
+
+    for rule in rules {
+        let pattern: Cow<str> = if ignore_case {
+            Cow::Owned(rule.pattern.to_lowercase())
+        } else {
+            Cow::Borrowed(&rule.pattern)
+        };
+        // Même correspondance que apply_rules_to_nodes ("*" = tout) ; le chemin étant
+        // un fichier, "build/" ne le vise que s'il est dessous
+        match match_rule(&normalized, &pattern) {
+            RuleMatch::None => continue,
+            RuleMatch::Exact if rule.dir_only => continue,
+            _ => {}
+        }
+
+        match rule.mode {
+            Mode::C => {
+                should_ignore = true;
+            }
+            Mode::E => {
+                should_ignore = false;
+            }
+            Mode::N => {}
+        }
+    }
+
+    should_ignore
+}
+
+/// Verrou consultatif posé à la racine pendant la session, pour que deux instances
+/// ouvertes sur le même projet n'écrasent pas mutuellement leur .gitignore.
+pub const LOCK_FILE: &str = ".gitignore-tui.lock";
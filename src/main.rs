@@ -1,377 +1,737 @@
 use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
-use std::io::{stdout, Write};
+use std::io::{self, stdout, IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-
-use crossterm::event::{read, Event, KeyCode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossterm::event::{
+    self, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton,
+    MouseEvent, MouseEventKind,
+};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode, ClearType};
 use crossterm::{cursor, execute, queue, style, terminal};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use gitignore_tui::{
+    apply_rules_to_nodes, build_full_tree, gitignore_line, load_children, parent_indices,
+    parse_gitignore_content, read_nested_gitignores, recompute_cpt_exception, recompute_cpt_mixed_marks,
+    should_be_ignored, Mode, NestedGitignore, Node, Rule, ScanCancelled, ScanOptions, LOCK_FILE, ROOT_PATTERN,
+};
 use ignore::gitignore::GitignoreBuilder; // NEW
+use log::debug;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 const HEADER_ROWS: u16 = 2;
+/// Ligne d'état (entrée courante, compteurs) puis pied de page
+const FOOTER_ROWS: u16 = 2;
+/// Durée d'affichage d'un message transitoire dans le pied de page
+const FLASH_DURATION: Duration = Duration::from_millis(1500);
+/// Intervalle de poll des événements (sert à l'expiration des messages)
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Durée de la confirmation "Saved" avant de quitter (une touche l'écourte)
+const SAVED_DELAY: Duration = Duration::from_millis(300);
+/// Largeur d'une tabulation dans les popups
+const TAB_WIDTH: usize = 4;
+/// Pas du défilement horizontal des popups
+const HSCROLL_STEP: usize = 8;
+/// Positions gardées dans l'historique de navigation (Ctrl+O / Ctrl+I)
+const JUMP_HISTORY_LEN: usize = 50;
+/// Modifications de la sélection que u peut annuler
+const UNDO_HISTORY_LEN: usize = 100;
+
+/// Contenu du popup d'aide (touche ?)
+const HELP_LINES: &[&str] = &[
+    "↑/↓        Move the cursor",
+    "PgUp/PgDn  Move the cursor by a page",
+    "Ctrl+U/D   Move the cursor by half a page",
+    "←/→        Collapse/expand a directory, or go to the parent",
+    "gg/G       Jump to the first/last row",
+    "]/[        Jump to the next/previous directory",
+    "/          Search entry names as you type (Enter keeps the match, Esc goes back)",
+    "n/N        Jump to the next/previous match of the last search",
+    "Ctrl+O     Go back to where the cursor was before a jump (parent, f, search, gg/G)",
+    "Ctrl+I/Tab Go forward again in the jump history",
+    "Enter      Toggle ignore for the current entry (expand/collapse with enter_expands)",
+    "Space      Toggle ignore for the current entry",
+    "u/Ctrl+R   Undo/redo the last change to the selection",
+    "Mouse      Click a row to move there, its [ ] to toggle it; the wheel moves the cursor",
+    "t          Switch intent: ignore (Space toggles) / unignore (Space only un-ignores)",
+    "r          Reload the tree from disk",
+    "R          Pull in .gitignore changes made elsewhere, keeping your unsaved marks",
+    "a          Toggle every file next to the current entry (same directory)",
+    "M          Mark every shown file matching a pattern",
+    "Alt+M      Add a pattern as a single wildcard rule",
+    "A          Mark every file not modified for N days",
+    "B          Mark every directory larger than a size (e.g. 100M), after a preview",
+    "e          Edit the raw .gitignore line of the current entry",
+    "T          Choose which .gitignore receives the rule of the current entry",
+    "I          Toggle case-insensitive matching of the rules",
+    "+          Add a custom .gitignore line (e.g. *.log), or remove it",
+    "P          List the .gitignore rules the tool doesn't manage (kept verbatim)",
+    "p          Preview the tracked files -j/--git would untrack after a save",
+    "W          Show (◆) the entries matched by a rule the tool doesn't manage; Esc clears",
+    "V          Preview the effect of the selection on git status",
+    "D          Diff against the committed .gitignore",
+    "F          Switch between the tree and a flat list of files",
+    "y          Save the rules inside the current directory as a named template",
+    "Y          Apply a saved template to the current directory",
+    "x          Show/hide the permissions column (rwxr-xr-x)",
+    "z          Show/hide the size column (directories: total of their content)",
+    "h          Hide/show the entries that are already ignored",
+    "c          Show single-child directory chains on one row (com/example/app)",
+    "Z          Focus: collapse every directory outside the current path",
+    "E/C        Expand/collapse the current directory and everything below it",
+    "*          Expand the whole tree, or collapse it back to the root entries",
+    "f          Jump to a directory by fuzzy search (↑/↓ choose, Enter go)",
+    "!          Pin/unpin the current entry: never ignored, even when its directory is",
+    "i / Alt+i  Invert the selection in the directory (Alt: every file below it)",
+    "O          Sort marked entries first in each directory, or restore the order",
+    "L          Show/hide the message log",
+    "?          Show this help",
+    "s          Save .gitignore and exit",
+    "q          Quit without saving (asks first if there are unsaved changes)",
+];
+
+/// Variable d'environnement qui passe l'arbre en ASCII pur (consoles Windows, polices limitées)
+const ASCII_ENV: &str = "GITIGNORE_TUI_ASCII";
+
+/// Caractères de l'arbre et des cases à cocher
+#[derive(Debug, Clone, Copy)]
+struct Theme {
+    /// Un niveau d'indentation
+    connector: &'static str,
+    expanded: &'static str,
+    collapsed: &'static str,
+    marked: &'static str,
+    unmarked: &'static str,
+    /// Fichier ignoré par un pattern générique (*.png...)
+    generic: &'static str,
+    pinned: &'static str,
+    /// Ignoré par un fichier --also
+    also: &'static str,
+    /// Ignoré par les exclusions globales de git
+    global: &'static str,
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Mode {
-    C, // Règle classique dans .gitignore
-    E, // Exception (!...)
-    N, // Normal (aucune règle)
+impl Theme {
+    const UNICODE: Theme = Theme {
+        connector: "│ ",
+        expanded: "▾",
+        collapsed: "▸",
+        marked: "[x]",
+        unmarked: "[ ]",
+        generic: "[o]",
+        pinned: "[!]",
+        also: "[a]",
+        global: "[g]",
+    };
+
+    const ASCII: Theme = Theme { connector: "| ", expanded: "v", collapsed: ">", ..Theme::UNICODE };
+
+    /// ASCII si GITIGNORE_TUI_ASCII est posée (à autre chose que "" ou "0")
+    fn from_env() -> Theme {
+        match env::var(ASCII_ENV) {
+            Ok(v) if !v.is_empty() && v != "0" => Theme::ASCII,
+            _ => Theme::UNICODE,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-struct Node {
-    path: PathBuf,
-    name: String,
-    is_dir: bool,
-    depth: usize,
-    expanded: bool,
-    mode: Mode,
-    mark: bool,
-    cpt_exception: usize,
-    cpt_mixed_marks: usize,
-    generic_mark: bool, // NEW : fichier marqué par une règle générique (*.png, etc.)
+/// Échappe les espaces de fin d'une ligne générée, que git ignorerait sinon
+fn escape_trailing_spaces(line: &str) -> String {
+    let kept = line.trim_end_matches(' ');
+    format!("{}{}", kept, "\\ ".repeat(line.len() - kept.len()))
 }
 
-#[derive(Debug, Clone)]
-struct Rule {
-    pattern: String, // chemin relatif normalisé "target/flycheck0"
-    mode: Mode,      // C ou E
+/// Ctrl+C pendant le parcours initial : au lieu de tuer le processus (et de laisser
+/// le verrou derrière lui), le signal lève un drapeau que le parcours consulte.
+/// Une fois le parcours fini, Ctrl+C retrouve son effet par défaut.
+struct ScanCancel {
+    requested: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
 }
 
-/// Parsing du .gitignore :
-/// - on garde uniquement les règles SANS wildcard compliqué (* ? [)
-///   sauf "*" ou "/*" que l'on accepte comme "tout le repo"
-/// - on reconnaît "dir/*" comme "dir"
-/// - on accepte les règles avec ou sans "/" en tête, mais on normalise sans "/"
-/// - on distingue C (ligne normale) et E (ligne commençant par !)
-/// - on retourne une liste ordonnée de règles
-fn parse_gitignore(root: &Path) -> Result<Vec<Rule>> {
-    let gitignore_path = root.join(".gitignore");
-    if !gitignore_path.exists() {
-        return Ok(Vec::new());
+impl ScanCancel {
+    fn install() -> Result<ScanCancel> {
+        use signal_hook::consts::SIGINT;
+        let cancel = ScanCancel {
+            requested: Arc::new(AtomicBool::new(false)),
+            finished: Arc::new(AtomicBool::new(false)),
+        };
+        signal_hook::flag::register_conditional_default(SIGINT, cancel.finished.clone())
+            .context("Installing the Ctrl+C handler")?;
+        signal_hook::flag::register(SIGINT, cancel.requested.clone())
+            .context("Installing the Ctrl+C handler")?;
+        Ok(cancel)
+    }
+
+    fn flag(&self) -> Arc<AtomicBool> {
+        self.requested.clone()
     }
 
-    let content = fs::read_to_string(&gitignore_path)
-        .context("Reading existing .gitignore")?;
+    fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+}
 
-    let mut rules = Vec::new();
+/// Permissions au format de `ls -l` ("rwxr-xr-x"), tirets si inconnues
+fn format_permissions(mode: Option<u32>) -> String {
+    let Some(mode) = mode else {
+        return "-".repeat(9);
+    };
+    let mut out = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    out
+}
 
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
+/// Applique mark + reset des modes/cpt_exception récursivement sur un répertoire.
+/// - mark du répertoire : `mark` ; marqué sans parent ignoré, il porte sa propre règle (C)
+/// - mark : valeur à mettre sur tous les enfants (-R)
+/// - mode des enfants : N, y compris les fichiers génériques (seul leur mark est gardé)
+/// - cpt_exception des enfants : 0
+/// - cpt_exception du répertoire : 0 (sera recalculé globalement ensuite)
+///
+/// Sans enfant épinglé, le répertoire n'a donc plus aucune exception en dessous et
+/// s'enregistre en une seule ligne "/dir" : une exception restée dans le sous-arbre
+/// le ferait passer à "/dir/*" (voir node_rule_lines).
+fn apply_recursive_mark_on_dir(nodes: &mut [Node], idx: usize, mark: bool) {
+    let depth = nodes[idx].depth;
+    let parent_marked = (0..idx).rev().find(|&j| nodes[j].depth < depth).is_some_and(|p| nodes[p].mark);
+    nodes[idx].mark = mark;
+    if mark && !parent_marked {
+        nodes[idx].mode = Mode::C;
+    }
+    nodes[idx].cpt_exception = 0;
+
+    let mut i = idx + 1;
+    while i < nodes.len() && nodes[i].depth > depth {
+        if nodes[i].pinned && mark {
+            // Épinglé : reste inclus par une exception, avec tout son sous-arbre
+            nodes[i].mark = false;
+            nodes[i].mode = Mode::E;
+            nodes[i].cpt_exception = 0;
+            i += 1 + subtree_size(nodes, i);
             continue;
         }
+        // Les fichiers génériques gardent leur mark (le pattern les ignore toujours),
+        // mais plus leur mode : une exception restée là couperait la règle du répertoire
+        if !nodes[i].generic_mark {
+            nodes[i].mark = mark;
+        }
+        nodes[i].mode = Mode::N;
+        nodes[i].cpt_exception = 0;
+        i += 1;
+    }
+}
 
-        let mut pattern = trimmed;
-        let mut mode = Mode::C;
+/// Nombre de descendants du node (son bloc en pré-ordre, lui exclu)
+fn subtree_size(nodes: &[Node], idx: usize) -> usize {
+    let depth = nodes[idx].depth;
+    nodes[idx + 1..].iter().take_while(|n| n.depth > depth).count()
+}
 
-        // Exception ?
-        if pattern.starts_with('!') {
-            mode = Mode::E;
-            pattern = &pattern[1..];
-        }
+/// Déplie (ou replie) le répertoire `idx` et tous les répertoires en dessous
+fn set_subtree_expanded(nodes: &mut [Node], idx: usize, expanded: bool) {
+    let end = idx + 1 + subtree_size(nodes, idx);
+    for n in nodes[idx..end].iter_mut().filter(|n| n.is_dir) {
+        n.expanded = expanded;
+    }
+}
 
-        // On enlève un éventuel "/" au début (on normalise les chemins sans "/")
-        if pattern.starts_with('/') {
-            pattern = &pattern[1..];
-        }
+/// Transition de Enter sur un node : (mode, mark) avant -> après.
+///
+/// | avant       | après       | pourquoi                                              |
+/// |-------------|-------------|-------------------------------------------------------|
+/// | N, ignoré   | E, inclus   | ignoré par un parent : il faut une exception          |
+/// | C, ignoré   | N, inclus   | sa propre règle disparaît                             |
+/// | E, ignoré   | E, inclus   | (incohérent) l'exception suffit déjà à l'inclure      |
+/// | N, inclus   | C, ignoré   | aucune règle : on en crée une                         |
+/// | E, inclus   | N, ignoré   | l'exception disparaît, le parent reprend la main      |
+/// | C, inclus   | C, ignoré   | (incohérent) la règle existe déjà                     |
+fn toggle_node(mode: Mode, mark: bool) -> (Mode, bool) {
+    let mode = match (mode, mark) {
+        (Mode::N, true) => Mode::E,
+        (Mode::C, true) => Mode::N,
+        (Mode::E, true) => Mode::E,
+        (Mode::N, false) => Mode::C,
+        (Mode::E, false) => Mode::N,
+        (Mode::C, false) => Mode::C,
+    };
+    (mode, !mark)
+}
 
-        // Cas spécial : "*" ou "" (si la ligne originale était "/" ou "/*")
-        let mut is_root_wildcard = false;
-        if pattern == "*" {
-            is_root_wildcard = true;
-        } else if pattern == "" {
-            // Cas bizarre mais au cas où quelqu'un mettrait juste "/"
-            is_root_wildcard = true;
-        }
+/// Enter sur un node, sans les vérifications de l'interface (épinglé, générique, --also) :
+/// transition de toggle_node, propagée à tout le contenu d'un répertoire.
+fn toggle_entry(nodes: &mut [Node], idx: usize) {
+    let was_marked = nodes[idx].mark;
+    let n = &mut nodes[idx];
+    (n.mode, n.mark) = toggle_node(n.mode, n.mark);
 
-        if is_root_wildcard {
-            rules.push(Rule {
-                pattern: "*".to_string(), // on encode le "tout" avec "*"
-                mode,
-            });
-            continue;
-        }
+    if n.is_dir {
+        apply_recursive_mark_on_dir(nodes, idx, !was_marked);
+    }
+
+    // Recalcul global des compteurs
+    recompute_cpt_exception(nodes);
+    recompute_cpt_mixed_marks(nodes);
+}
 
-        // On traite "xxx/*" comme "xxx" (répertoire)
-        if let Some(stripped) = pattern.strip_suffix("/*") {
-            pattern = stripped;
+/// Inverse la sélection dans le répertoire `dir` (touche i) : chaque enfant direct,
+/// ou avec `recursive` chaque fichier du sous-arbre, reçoit la transition de Enter
+/// (voir toggle_entry). Comme dans apply_recursive_mark_on_dir, les fichiers
+/// génériques ne bougent pas ; les nodes épinglés et ceux d'un fichier --also non plus.
+/// Renvoie le nombre d'entrées inversées.
+fn invert_selection(nodes: &mut [Node], dir: usize, recursive: bool) -> usize {
+    let depth = nodes[dir].depth;
+    let end = dir + 1 + subtree_size(nodes, dir);
+    let targets: Vec<usize> = (dir + 1..end)
+        .filter(|&i| if recursive { !nodes[i].is_dir } else { nodes[i].depth == depth + 1 })
+        .filter(|&i| {
+            let n = &nodes[i];
+            let generic = n.generic_mark && !n.is_dir;
+            let also_only = n.also.is_some() && !n.mark;
+            !(n.pinned || generic || also_only)
+        })
+        .collect();
+
+    // Les enfants directs ont des sous-arbres disjoints : les basculer un par un revient au même
+    for &i in &targets {
+        let was_marked = nodes[i].mark;
+        let n = &mut nodes[i];
+        (n.mode, n.mark) = toggle_node(n.mode, n.mark);
+        if n.is_dir {
+            apply_recursive_mark_on_dir(nodes, i, !was_marked);
         }
+    }
+    recompute_cpt_exception(nodes);
+    recompute_cpt_mixed_marks(nodes);
+    targets.len()
+}
 
-        // On enlève un éventuel "/" final
-        let pattern = pattern.trim_end_matches('/');
+/// Marque un fichier comme le ferait Enter sur un fichier non marqué
+/// (voir toggle_node). Un node épinglé n'est jamais marqué.
+fn mark_file(n: &mut Node) {
+    if n.pinned {
+        return;
+    }
+    (n.mode, n.mark) = toggle_node(n.mode, false);
+}
 
-        if pattern.is_empty() {
-            continue;
+/// Taille cumulée de chaque node : la sienne pour un fichier, celle de tout
+/// son contenu pour un répertoire.
+fn compute_sizes(nodes: &[Node]) -> Vec<u64> {
+    let parents = parent_indices(nodes);
+    let mut sizes: Vec<u64> = nodes.iter().map(|n| n.size).collect();
+    for j in (0..nodes.len()).rev() {
+        if let Some(p) = parents[j] {
+            sizes[p] += sizes[j];
         }
+    }
+    sizes
+}
 
-        // On ignore les règles trop génériques avec wildcard,
-        // sauf celles déjà gérées ci-dessus.
-        if pattern.contains('*') || pattern.contains('?') || pattern.contains('[') {
-            continue;
+/// Répertoires (racine exclue) de plus de `threshold` octets, sans ceux qui sont déjà
+/// ignorés ni ceux qui se trouvent dans un répertoire retenu : une règle sur le plus
+/// haut suffit. Renvoie (index, taille) dans l'ordre de l'arbre.
+fn dirs_larger_than(nodes: &[Node], threshold: u64) -> Vec<(usize, u64)> {
+    let sizes = compute_sizes(nodes);
+    let mut found: Vec<(usize, u64)> = Vec::new();
+    let mut i = 1;
+    while i < nodes.len() {
+        if nodes[i].is_dir && !nodes[i].mark && !nodes[i].pinned && sizes[i] > threshold {
+            found.push((i, sizes[i]));
+            i += 1 + subtree_size(nodes, i);
+        } else {
+            i += 1;
         }
+    }
+    found
+}
+
+/// Seuil de taille saisi par l'utilisateur : un nombre suivi d'une unité facultative
+/// (K, M, G, en multiples de 1024 ; "100M", "1.5 GB", "4096").
+fn parse_size(input: &str) -> Option<u64> {
+    let input = input.trim().to_ascii_uppercase();
+    let input = input.strip_suffix('B').unwrap_or(&input).trim_end();
+    let (number, unit) = match input.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((i, _)) => (input[..i].trim(), &input[i..]),
+        None => (input, ""),
+    };
+    let factor: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return None,
+    };
+    let value: f64 = number.parse().ok()?;
+    (value >= 0.0).then_some((value * factor as f64) as u64)
+}
 
-        let normalized = pattern.replace("\\", "/");
+/// Taille lisible, en multiples de 1024 ("1.2 GB", "340 KB", "12 B")
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
 
-        rules.push(Rule {
-            pattern: normalized,
-            mode,
-        });
+/// Répertoire parent de `idx` dont tout le contenu est désormais marqué, sans
+/// exception, alors que lui-même ne l'est pas : une règle sur le répertoire suffirait.
+/// La racine n'est jamais proposée.
+fn fully_marked_parent(nodes: &[Node], idx: usize) -> Option<usize> {
+    let depth = nodes[idx].depth;
+    let parent = (0..idx).rev().find(|&j| nodes[j].depth < depth)?;
+    if parent == 0 || nodes[parent].mark || nodes[parent].pinned {
+        return None;
     }
 
-    Ok(rules)
+    let parent_depth = nodes[parent].depth;
+    let all_marked = nodes[parent + 1..]
+        .iter()
+        .take_while(|n| n.depth > parent_depth)
+        .all(|n| n.mark && n.mode != Mode::E);
+    all_marked.then_some(parent)
 }
 
-/// Construit l'arbre COMPLET de tous les fichiers/répertoires (en pré-ordre).
-/// On ajoute un noeud racine "/" qui contient tout le répertoire `root`.
-/// Tous les nodes démarrent avec mode = N, mark = false
-fn build_full_tree(root: &Path) -> Result<Vec<Node>> {
-    fn build_dir(
-        current: &Path,
-        root: &Path,
-        depth: usize,
-        nodes: &mut Vec<Node>,
-    ) -> Result<()> {
-        let read = fs::read_dir(current)
-            .context(format!("Reading directory {:?}", current))?;
-
-        let mut dirs = Vec::new();
-        let mut files = Vec::new();
-
-        for ent in read {
-            if let Ok(e) = ent {
-                let p = e.path();
-                let name = p
-                    .file_name()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .unwrap_or_else(|| "".into());
-                if p.is_dir() {
-                    dirs.push((p, name));
-                } else {
-                    files.push((p, name));
-                }
+/// Pattern saisi, interprété comme une ligne de .gitignore (ex : "*.tmp")
+fn glob_matcher(root: &Path, pattern: &str) -> Result<ignore::gitignore::Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    builder
+        .add_line(None, pattern)
+        .map_err(anyhow::Error::from)
+        .and_then(|b| b.build().map_err(anyhow::Error::from))
+        .with_context(|| format!("invalid pattern '{}'", pattern))
+}
+
+/// Marque (en règle classique C) les entrées non marquées parmi `candidates` qui
+/// correspondent au pattern (voir glob_matcher), elles ou l'un de leurs répertoires
+/// parents, comme pour git. Avec `include_dirs`, un répertoire qui correspond est
+/// marqué comme par Enter, avec tout son contenu. Renvoie le nombre d'entrées marquées.
+fn mark_matching(
+    nodes: &mut [Node],
+    root: &Path,
+    pattern: &str,
+    include_dirs: bool,
+    candidates: &[usize],
+) -> Result<usize> {
+    let matcher = glob_matcher(root, pattern)?;
+
+    let mut count = 0;
+    for &i in candidates {
+        let n = &nodes[i];
+        if (n.is_dir && (!include_dirs || i == 0)) || n.generic_mark || n.mark || n.pinned {
+            continue;
+        }
+        if matcher.matched_path_or_any_parents(&n.rel, n.is_dir).is_ignore() {
+            mark_file(&mut nodes[i]);
+            if nodes[i].is_dir {
+                apply_recursive_mark_on_dir(nodes, i, true);
             }
+            count += 1;
         }
+    }
 
-        dirs.sort_by_key(|(_, n)| n.clone());
-        files.sort_by_key(|(_, n)| n.clone());
+    recompute_cpt_exception(nodes);
+    recompute_cpt_mixed_marks(nodes);
+    Ok(count)
+}
 
-        for (p, n) in dirs.into_iter().chain(files.into_iter()) {
-            let is_dir = p.is_dir();
-            let node = Node {
-                path: p.clone(),
-                name: n,
-                is_dir,
-                depth,
-                expanded: false,
-                mode: Mode::N,
-                mark: false,
-                cpt_exception: 0,
-                cpt_mixed_marks: 0,
-                generic_mark: false, // NEW
-            };
-            nodes.push(node);
-            if is_dir {
-                build_dir(&p, root, depth + 1, nodes)?;
-            }
+/// Déplie tous les répertoires de profondeur < `depth` (le noeud racine est en profondeur 0).
+fn expand_to_depth(nodes: &mut [Node], depth: usize) {
+    for n in nodes.iter_mut() {
+        if n.is_dir && n.depth < depth {
+            n.expanded = true;
         }
-        Ok(())
     }
+}
 
-    let mut nodes = Vec::new();
-
-    // --- NOEUD RACINE VIRTUEL CLIQUABLE ---
-    nodes.push(Node {
-        path: root.to_path_buf(),
-        name: "/".to_string(),
-        is_dir: true,
-        depth: 0,
-        expanded: true, // on commence ouvert
-        mode: Mode::N,
-        mark: false,
-        cpt_exception: 0,
-        cpt_mixed_marks: 0,
-        generic_mark: false, // NEW
-    });
+/// Démarque un fichier comme le ferait Enter sur un fichier marqué :
+/// une règle classique disparaît (C -> N), sinon une exception est créée (N -> E).
+fn unmark_file(n: &mut Node) {
+    n.mark = false;
+    n.mode = match n.mode {
+        Mode::C => Mode::N,
+        Mode::N | Mode::E => Mode::E,
+    };
+}
 
-    // Les enfants du root sont en profondeur 1
-    build_dir(root, root, 1, &mut nodes)?;
-    Ok(nodes)
+/// Indices des nodes de même parent que `idx` (lui compris), dans l'ordre.
+/// On s'écarte du node dans les deux sens en sautant les descendants (depth plus grand)
+/// jusqu'à sortir du bloc du parent (depth plus petit).
+fn sibling_indices(nodes: &[Node], idx: usize) -> Vec<usize> {
+    let depth = nodes[idx].depth;
+    let mut before = Vec::new();
+    let mut j = idx;
+    while j > 0 && nodes[j - 1].depth >= depth {
+        j -= 1;
+        if nodes[j].depth == depth {
+            before.push(j);
+        }
+    }
+    before.reverse();
+
+    let mut siblings = before;
+    siblings.push(idx);
+    let mut j = idx + 1;
+    while j < nodes.len() && nodes[j].depth >= depth {
+        if nodes[j].depth == depth {
+            siblings.push(j);
+        }
+        j += 1;
+    }
+    siblings
 }
 
-fn apply_rules_to_nodes(nodes: &mut Vec<Node>, root: &Path, rules: &[Rule]) {
-    let len = nodes.len();
+/// Mode "liste à plat" : uniquement les fichiers, triés par chemin relatif,
+/// quel que soit l'état replié des répertoires.
+fn build_flat_indices(nodes: &[Node], filter: &ViewFilter) -> Vec<usize> {
+    let mut files: Vec<usize> = (0..nodes.len())
+        .filter(|&i| !nodes[i].is_dir && filter.shows(&nodes[i]))
+        .collect();
+    files.sort_by(|&a, &b| nodes[a].rel.cmp(&nodes[b].rel));
+    files
+}
 
-    for i in 0..len {
-        let rel = if nodes[i].path == root {
-            // noeud racine virtuel -> chemin relatif vide
-            Path::new("")
-        } else {
-            nodes[i].path.strip_prefix(root).unwrap_or(&nodes[i].path)
-        };
-        let rel_str = rel.to_string_lossy().replace("\\", "/");
+/// Unique enfant de `idx` s'il s'agit d'un répertoire (et rien d'autre à côté)
+fn single_dir_child(nodes: &[Node], idx: usize) -> Option<usize> {
+    let depth = nodes[idx].depth;
+    let child = idx + 1;
+    if !nodes[idx].is_dir || !nodes.get(child).is_some_and(|c| c.is_dir && c.depth == depth + 1) {
+        return None;
+    }
+    let after = child + 1 + subtree_size(nodes, child);
+    if nodes.get(after).is_some_and(|n| n.depth > depth) {
+        return None;
+    }
+    Some(child)
+}
 
-        // reset de base
-        nodes[i].mode = Mode::N;
-        nodes[i].mark = false;
-
-        for rule in rules {
-            let pat = &rule.pattern;
-
-            // Cas spécial : "*" = toute l'arborescence
-            if pat == "*" {
-                match rule.mode {
-                    Mode::C => {
-                        nodes[i].mark = true;
-                        if nodes[i].mode == Mode::E {
-                            nodes[i].mode = Mode::N;
-                        }
-                    }
-                    Mode::E => {
-                        nodes[i].mark = false;
-                        if nodes[i].mode == Mode::C {
-                            nodes[i].mode = Mode::N;
-                        }
-                    }
-                    Mode::N => {}
-                }
-                continue;
-            }
+/// Répertoires affichés sur la même ligne que `idx` en mode compact (`com/example/app`),
+/// `idx` compris. La racine n'est jamais fusionnée avec son contenu.
+fn compact_chain(nodes: &[Node], idx: usize) -> Vec<usize> {
+    let mut chain = vec![idx];
+    if idx == 0 {
+        return chain;
+    }
+    let mut cur = idx;
+    while let Some(child) = single_dir_child(nodes, cur) {
+        chain.push(child);
+        cur = child;
+    }
+    chain
+}
 
-            let is_exact = rel_str == *pat;
-            let is_descendant = rel_str.starts_with(pat)
-                && rel_str.len() > pat.len()
-                && rel_str.as_bytes()[pat.len()] == b'/';
-
-            match rule.mode {
-                Mode::C => {
-                    if is_exact {
-                        nodes[i].mode = Mode::C;
-                        nodes[i].mark = true;
-                    } else if is_descendant {
-                        nodes[i].mark = true;
-                        if nodes[i].mode == Mode::E {
-                            nodes[i].mode = Mode::N;
-                        }
-                    }
-                }
-                Mode::E => {
-                    if is_exact {
-                        nodes[i].mode = Mode::E;
-                        nodes[i].mark = false;
-                    } else if is_descendant {
-                        nodes[i].mark = false;
-                        if nodes[i].mode == Mode::C {
-                            nodes[i].mode = Mode::N;
-                        }
-                    }
-                }
-                Mode::N => {}
-            }
-        }
+/// Partie de la chaîne de `idx` affichée sur sa ligne : elle s'arrête au premier
+/// répertoire replié, dont le contenu n'est pas affiché (dépliages divergents après
+/// --expand-depth, Z...).
+fn shown_chain(nodes: &[Node], idx: usize) -> Vec<usize> {
+    let mut chain = compact_chain(nodes, idx);
+    if let Some(k) = chain.iter().position(|&c| !nodes[c].expanded) {
+        chain.truncate(k + 1);
     }
+    chain
+}
 
-    // cpt_exception pour tout l'arbre
-    recompute_cpt_exception(nodes);
-    // cpt_mixed_marks pour tout l'arbre
-    recompute_cpt_mixed_marks(nodes);
+/// Vrai si le node est fusionné dans la ligne de son parent en mode compact
+fn is_compacted(nodes: &[Node], idx: usize) -> bool {
+    match parent_of(nodes, idx) {
+        Some(p) if p != 0 => nodes[p].expanded && single_dir_child(nodes, p) == Some(idx),
+        _ => false,
+    }
 }
 
-/// Recalcule cpt_exception pour tous les nodes.
-/// - fichier : 1 si mode = E, sinon 0
-/// - répertoire : (1 si mode = E) + somme récursive de tous les descendants
-fn recompute_cpt_exception(nodes: &mut Vec<Node>) {
-    for n in nodes.iter_mut() {
-        n.cpt_exception = if n.mode == Mode::E { 1 } else { 0 };
+/// Indices des `choices` qui correspondent à `query` en recherche floue, du meilleur
+/// score au moins bon ; tous, dans leur ordre, si `query` est vide.
+fn fuzzy_filter(choices: &[String], query: &str) -> Vec<usize> {
+    let matcher = SkimMatcherV2::default();
+    let mut scored: Vec<(i64, usize)> = choices
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| matcher.fuzzy_match(c, query).map(|score| (score, i)))
+        .collect();
+    // Tri stable : à score égal, l'ordre d'origine est gardé
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Parent du node (None pour la racine)
+fn parent_of(nodes: &[Node], idx: usize) -> Option<usize> {
+    let depth = nodes[idx].depth;
+    (0..idx).rev().find(|&p| nodes[p].depth < depth)
+}
+
+/// Profondeur d'affichage de chaque node en mode compact : chaque répertoire fusionné
+/// dans la ligne de son parent (voir is_compacted) fait remonter d'un cran tout ce
+/// qu'il contient. Un seul passage, refait par rebuild_visible.
+fn compact_depths(nodes: &[Node]) -> Vec<usize> {
+    let parents = parent_indices(nodes);
+    let mut children = vec![0usize; nodes.len()];
+    for &p in parents.iter().flatten() {
+        children[p] += 1;
     }
+    let mut depths = vec![0; nodes.len()];
+    for i in 1..nodes.len() {
+        let p = parents[i].unwrap_or(0);
+        let merged = p != 0 && nodes[p].expanded && nodes[i].is_dir && children[p] == 1;
+        depths[i] = depths[p] + usize::from(!merged);
+    }
+    depths
+}
 
-    let len = nodes.len();
-    if len == 0 {
-        return;
+/// Réordonne les lignes affichées pour mettre en tête, dans chaque répertoire, les
+/// entrées marquées ou contenant un mark (l'ordre d'origine est gardé à égalité).
+/// Seul `visible` est réordonné : `nodes` reste en pré-ordre, les sous-arbres y restent
+/// contigus et les compteurs ne sont pas touchés.
+fn marked_first(nodes: &[Node], visible: &[usize], flat: bool) -> Vec<usize> {
+    if flat {
+        let mut rows = visible.to_vec();
+        rows.sort_by_key(|&i| !nodes[i].mark);
+        return rows;
     }
 
-    // Comme nodes est en pré-ordre, les descendants d'un répertoire
-    // sont dans un bloc contigu après lui, avec depth plus grand.
-    for i in (0..len).rev() {
-        if nodes[i].is_dir {
-            let depth = nodes[i].depth;
-            let mut j = i + 1;
-            let mut sum = nodes[i].cpt_exception;
-            while j < len && nodes[j].depth > depth {
-                sum += nodes[j].cpt_exception;
-                j += 1;
+    // has_mark[i] : le node ou un de ses descendants est marqué
+    let parents = parent_indices(nodes);
+    let mut has_mark: Vec<bool> = nodes.iter().map(|n| n.mark).collect();
+    for i in (1..nodes.len()).rev() {
+        if has_mark[i] {
+            if let Some(p) = parents[i] {
+                has_mark[p] = true;
             }
-            nodes[i].cpt_exception = sum;
         }
     }
+
+    let mut out = Vec::with_capacity(visible.len());
+    sort_sibling_blocks(nodes, visible, &has_mark, &mut out);
+    out
 }
 
-/// Recalcule cpt_mixed_marks pour tous les nodes.
-/// Pour un répertoire : compte le nombre total de descendants (récursif) avec une marque différente
-fn recompute_cpt_mixed_marks(nodes: &mut Vec<Node>) {
-    let len = nodes.len();
-    if len == 0 {
+/// `rows` est une suite de sous-arbres frères (en pré-ordre) : on les trie
+/// entre eux, puis on descend dans chacun.
+fn sort_sibling_blocks(nodes: &[Node], rows: &[usize], has_mark: &[bool], out: &mut Vec<usize>) {
+    if rows.is_empty() {
         return;
     }
+    let depth = nodes[rows[0]].depth;
+    let mut blocks: Vec<&[usize]> = Vec::new();
+    let mut start = 0;
+    for k in 1..=rows.len() {
+        if k == rows.len() || nodes[rows[k]].depth <= depth {
+            blocks.push(&rows[start..k]);
+            start = k;
+        }
+    }
 
-    // Reset tous les compteurs
-    for n in nodes.iter_mut() {
-        n.cpt_mixed_marks = 0;
+    blocks.sort_by_key(|b| !has_mark[b[0]]);
+    for block in blocks {
+        out.push(block[0]);
+        sort_sibling_blocks(nodes, &block[1..], has_mark, out);
     }
+}
 
-    // Parcours en ordre inverse (post-ordre) pour remonter les compteurs
-    for i in (0..len).rev() {
-        if nodes[i].is_dir {
-            let parent_mark = nodes[i].mark;
-            let depth = nodes[i].depth;
-            let mut j = i + 1;
-            let mut count = 0;
-            
-            while j < len && nodes[j].depth > depth {
-                // Compte si l'enfant a une marque différente
-                if nodes[j].mark != parent_mark {
-                    count += 1;
-                }
-                // Ajoute le compteur de l'enfant s'il est un répertoire
-                if nodes[j].is_dir {
-                    count += nodes[j].cpt_mixed_marks;
-                }
-                j += 1;
+/// Filtre d'affichage de l'arbre, sans effet sur la sauvegarde
+#[derive(Debug, Clone, Default)]
+struct ViewFilter {
+    /// --ext : extensions (sans le point, en minuscules) des fichiers affichés
+    extensions: Option<Vec<String>>,
+    /// --since : seuls les fichiers apparus depuis cette révision sont affichés
+    since: Option<HashSet<String>>,
+    /// Touche h : masque les entrées déjà ignorées (affichage seulement)
+    hide_ignored: bool,
+}
+
+impl ViewFilter {
+    /// Vrai si le node passe le filtre. Les répertoires sont toujours gardés pour la structure.
+    fn shows(&self, n: &Node) -> bool {
+        if n.is_dir {
+            return true;
+        }
+        if self.hide_ignored && n.mark {
+            return false;
+        }
+        if let Some(exts) = &self.extensions {
+            let ext = n
+                .path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !exts.contains(&ext) {
+                return false;
+            }
+        }
+        if let Some(added) = &self.since {
+            if !added.contains(&n.rel) {
+                return false;
             }
-            
-            nodes[i].cpt_mixed_marks = count;
         }
+        true
     }
-}
-
-/// Applique mark + reset des modes/cpt_exception récursivement sur un répertoire.
-/// - mark : valeur à mettre sur tous les enfants (-R)
-/// - mode des enfants : N
-/// - cpt_exception des enfants : 0
-/// - cpt_exception du répertoire : 0 (sera recalculé globalement ensuite)
-fn apply_recursive_mark_on_dir(nodes: &mut Vec<Node>, idx: usize, mark: bool) {
-    let depth = nodes[idx].depth;
-    nodes[idx].cpt_exception = 0;
 
-    let mut i = idx + 1;
-    while i < nodes.len() && nodes[i].depth > depth {
-        if !nodes[i].generic_mark {
-            // NEW : on ne touche pas aux fichiers génériques
-            nodes[i].mark = mark;
-            nodes[i].mode = Mode::N;
+    /// Pour chaque node, vrai si c'est un répertoire masqué avec tout son contenu :
+    /// touche h, et lui comme chacun de ses descendants sont ignorés. Un seul passage,
+    /// à l'envers du pré-ordre, pour tout l'arbre.
+    fn hidden_dirs(&self, nodes: &[Node]) -> Vec<bool> {
+        if !self.hide_ignored {
+            return vec![false; nodes.len()];
         }
-        nodes[i].cpt_exception = 0;
-        i += 1;
+        let parents = parent_indices(nodes);
+        let mut fully_marked: Vec<bool> = nodes.iter().map(|n| n.mark).collect();
+        for i in (1..nodes.len()).rev() {
+            if let (false, Some(p)) = (fully_marked[i], parents[i]) {
+                fully_marked[p] = false;
+            }
+        }
+        fully_marked.iter().enumerate().map(|(i, &all)| all && i != 0 && nodes[i].is_dir).collect()
     }
 }
 
-/// Construit la liste des indices visibles en fonction de expanded / depth.
-fn build_visible_indices(nodes: &Vec<Node>) -> Vec<usize> {
+/// Liste d'extensions de --ext ("log,.tmp") normalisée
+fn parse_extensions(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+/// Construit la liste des indices visibles en fonction de expanded / depth
+/// et du filtre d'affichage.
+/// En mode `compact`, une chaîne de répertoires à enfant unique n'occupe qu'une ligne,
+/// celle du premier répertoire de la chaîne, dont le dépliage vaut pour toute la chaîne.
+fn build_visible_indices(nodes: &[Node], filter: &ViewFilter, compact: bool) -> Vec<usize> {
+    let hidden = filter.hidden_dirs(nodes);
     let mut visible = Vec::new();
     let mut i = 0;
     while i < nodes.len() {
-        visible.push(i);
+        if hidden[i] {
+            i += 1 + subtree_size(nodes, i);
+            continue;
+        }
+        if filter.shows(&nodes[i]) {
+            visible.push(i);
+        }
+        if compact && nodes[i].expanded {
+            // On saute les répertoires fusionnés dans la ligne de `i` ; le dernier décide
+            // ensuite, comme un répertoire ordinaire, si son contenu est affiché
+            i = shown_chain(nodes, i).last().copied().unwrap_or(i);
+        }
         if nodes[i].is_dir && !nodes[i].expanded {
             let depth = nodes[i].depth;
             i += 1;
@@ -385,11 +745,9 @@ fn build_visible_indices(nodes: &Vec<Node>) -> Vec<usize> {
     visible
 }
 
-fn render_header(out: &mut impl Write) -> Result<()> {
+fn render_header(out: &mut impl Write, app: &App) -> Result<()> {
     queue!(
         out,
-        cursor::MoveTo(0, 0),
-        terminal::Clear(ClearType::CurrentLine),
         style::SetAttribute(style::Attribute::Bold),
         style::SetBackgroundColor(style::Color::DarkGrey),
         style::SetForegroundColor(style::Color::White),
@@ -403,67 +761,520 @@ fn render_header(out: &mut impl Write) -> Result<()> {
         style::Print(" [Q]uit "),
         style::ResetColor,
         style::SetAttribute(style::Attribute::Reset),
-        cursor::MoveTo(0, 1),
-        terminal::Clear(ClearType::CurrentLine)
+        style::Print("  "),
+        style::SetForegroundColor(style::Color::DarkGrey),
+        style::Print(if app.scan_opts.ignore_case { "case: ignored" } else { "case: sensitive" }),
+        style::ResetColor
     )?;
+    if app.scan_opts.strict {
+        queue!(
+            out,
+            style::Print("  "),
+            style::SetForegroundColor(style::Color::Yellow),
+            style::Print("strict (read-only)"),
+            style::ResetColor
+        )?;
+    }
+    if !app.scan_opts.also.is_empty() {
+        queue!(
+            out,
+            style::Print("  "),
+            style::SetForegroundColor(style::Color::Cyan),
+            style::Print(format!("[a] also: {}", app.scan_opts.also.len())),
+            style::ResetColor
+        )?;
+    }
+    // Mode "unignore" (touche t) : mis en évidence, Enter ne fait plus que des exceptions
+    if app.unignore {
+        queue!(
+            out,
+            style::Print("  "),
+            style::SetForegroundColor(style::Color::Green),
+            style::SetAttribute(style::Attribute::Bold),
+            style::Print("intent: unignore"),
+            style::SetAttribute(style::Attribute::Reset),
+            style::ResetColor
+        )?;
+    } else {
+        queue!(
+            out,
+            style::Print("  "),
+            style::SetForegroundColor(style::Color::DarkGrey),
+            style::Print("intent: ignore"),
+            style::ResetColor
+        )?;
+    }
     Ok(())
 }
 
-fn render(nodes: &Vec<Node>, visible: &Vec<usize>, cursor_pos: usize, scroll_offset: usize) -> Result<()> {
-    let mut out = stdout();
+/// Curseur et scroll corrigés pour `len` lignes affichées dans `height` lignes d'écran :
+/// le curseur reste dans la liste et sa ligne est à l'écran, et le scroll ne dépasse
+/// jamais `len - height` (0 si tout tient), donc la dernière ligne reste atteignable.
+fn clamp_cursor_and_scroll(cursor: usize, scroll: usize, len: usize, height: usize) -> (usize, usize) {
+    if len == 0 {
+        return (0, 0);
+    }
+    // Un terminal minuscule affiche quand même la ligne du curseur
+    let height = height.max(1);
+    let cursor = cursor.min(len - 1);
+    let max_scroll = len.saturating_sub(height);
+
+    let mut scroll = scroll;
+    if cursor < scroll {
+        scroll = cursor;
+    } else if cursor >= scroll + height {
+        scroll = cursor + 1 - height;
+    }
+    (cursor, scroll.min(max_scroll))
+}
 
-    let (_, term_height) = terminal::size()?;
-    let viewport_rows = term_height.saturating_sub(HEADER_ROWS) as usize;
+/// Ligne de `visible` affichée à la ligne d'écran `y` (clic de souris), ou None
+/// si `y` tombe dans l'en-tête, le pied de page ou après la fin de la liste.
+fn row_at(y: u16, scroll: usize, len: usize, height: usize) -> Option<usize> {
+    let line = y.checked_sub(HEADER_ROWS)? as usize;
+    if line >= height {
+        return None;
+    }
+    Some(scroll + line).filter(|&row| row < len)
+}
+
+/// Colonne où `render` dessine la case "[ ]" du node `idx` : après les permissions
+/// (touche x), les tailles (touche z) et l'indentation de l'arbre. À garder en accord
+/// avec `render`.
+fn mark_column(app: &App, idx: usize) -> usize {
+    let mut col = if app.show_permissions { 10 } else { 0 };
+    if app.show_sizes {
+        col += SIZE_COLUMN_WIDTH + 1;
+    }
+    if !app.flat {
+        let depth = if app.compact { app.compact_depths[idx] } else { app.nodes[idx].depth };
+        col += app.theme.connector.width() * depth;
+    }
+    col
+}
+
+/// Largeur de la colonne des tailles : "1023.9 MB"
+const SIZE_COLUMN_WIDTH: usize = 9;
 
+/// Ligne de saisie, dessinée sur la 2e ligne de l'en-tête
+fn render_prompt(out: &mut impl Write, prompt: &Prompt) -> Result<()> {
     queue!(
         out,
-        cursor::Hide,
-        terminal::Clear(ClearType::All),
-        style::ResetColor,
+        style::SetAttribute(style::Attribute::Bold),
+        style::Print(format!("{} ", prompt.label)),
+        style::SetAttribute(style::Attribute::Reset),
+        style::Print(&prompt.input),
+        style::SetAttribute(style::Attribute::Reverse),
+        style::Print(" "),
         style::SetAttribute(style::Attribute::Reset)
     )?;
+    Ok(())
+}
 
-    render_header(&mut out)?;
+/// Nombre de lignes du panneau de messages (titre compris) quand il est affiché
+const LOG_ROWS: u16 = 6;
 
-    let visible_start = scroll_offset.min(visible.len());
-    let visible_end = (visible_start + viewport_rows).min(visible.len());
+/// Nombre de lignes disponibles pour l'arbre (entre l'en-tête et le pied de page,
+/// moins le panneau de messages s'il est affiché).
+fn viewport_rows(app: &App) -> Result<usize> {
+    let (_, term_height) = terminal::size()?;
+    let log_rows = if app.show_log { LOG_ROWS } else { 0 };
+    let extra_rows = u16::from(!app.extra_rules.is_empty());
+    Ok((term_height.saturating_sub(HEADER_ROWS + FOOTER_ROWS + log_rows + extra_rows) as usize).max(1))
+}
 
-    for (line_idx, vis_idx) in (visible_start..visible_end).enumerate() {
-        let i = visible[vis_idx];
-        let n = &nodes[i];
-        let y = HEADER_ROWS + line_idx as u16;
+/// Découpe `text` en colonnes d'affichage : saute les `skip` premières et en garde au plus `width`.
+/// Les tabulations et caractères de contrôle sont remplacés pour ne pas décaler l'écran ;
+/// un "…" signale le texte qui dépasse à gauche ou à droite. Renvoie le texte et sa largeur.
+fn clip_columns(text: &str, skip: usize, width: usize) -> (String, usize) {
+    // Cellules (texte, largeur) après le décalage horizontal
+    let mut cells: Vec<(String, usize)> = Vec::new();
+    let mut col = 0;
+    for c in text.chars() {
+        let (piece, w) = match c {
+            '\t' => {
+                let w = TAB_WIDTH - col % TAB_WIDTH;
+                (" ".repeat(w), w)
+            }
+            c if c.is_control() => ("?".to_string(), 1),
+            c => (c.to_string(), UnicodeWidthChar::width(c).unwrap_or(0)),
+        };
+        if col >= skip {
+            cells.push((piece, w));
+        } else if col + w > skip {
+            // Caractère large coupé par le bord gauche
+            let rest = col + w - skip;
+            cells.push((" ".repeat(rest), rest));
+        }
+        col += w;
+    }
 
-        queue!(out, cursor::MoveTo(0, y), terminal::Clear(ClearType::CurrentLine))?;
+    let mut out = String::new();
+    let mut used = 0;
+    let mut width = width;
+    if skip > 0 && width > 0 && !text.is_empty() {
+        out.push('…');
+        used += 1;
+        width -= 1;
+    }
 
-        if vis_idx == cursor_pos {
-            queue!(out, style::SetAttribute(style::Attribute::Reverse))?;
+    let total: usize = cells.iter().map(|(_, w)| w).sum();
+    // On réserve une colonne pour le "…" de droite si tout ne tient pas
+    let room = if total > width { width.saturating_sub(1) } else { width };
+    let mut taken = 0;
+    for (piece, w) in &cells {
+        if taken + w > room {
+            break;
         }
+        out.push_str(piece);
+        taken += w;
+    }
+    used += taken;
+    if total > width && width > 0 {
+        out.push('…');
+        used += 1;
+    }
+    (out, used)
+}
 
-        for _ in 0..n.depth {
-            queue!(out, style::Print("│ "))?;
-        }
+/// Popup encadré et défilant qui remplace les lignes de l'arbre qu'il recouvre.
+/// Sert à tous les popups (diff, aide...) : les lignes trop longues sont coupées
+/// et se lisent avec le défilement horizontal.
+fn render_scrollable_popup(rows: &mut [Vec<u8>], popup: &Popup, term_width: usize) -> Result<()> {
+    if rows.len() < 3 {
+        return Ok(());
+    }
+    let (top, rest) = rows.split_first_mut().unwrap();
+    let (bottom, body) = rest.split_last_mut().unwrap();
+
+    // Une colonne de marge de chaque côté, plus les bordures
+    let inner = term_width.saturating_sub(4).max(1);
+    let frame = |text: &str, left: &str, right: &str| {
+        let (label, w) = clip_columns(text, 0, inner);
+        format!(" {}{}{}{} ", left, label, "─".repeat(inner - w), right)
+    };
 
-        // NEW : affichage du symbole de mark
-        let mark_symbol = if n.generic_mark {
-            "[o]" // NEW : fichier marqué par règle générique
-        } else if n.mark {
-            "[x]"
-        } else {
-            "[ ]"
-        };
+    top.clear();
+    queue!(top, style::Print(frame(&format!(" {} ", popup.title), "┌", "┐")))?;
+
+    let end = (popup.scroll + body.len()).min(popup.lines.len());
+    let shown = &popup.lines[popup.scroll.min(end)..end];
+    for (i, row) in body.iter_mut().enumerate() {
+        row.clear();
+        let (text, color) = shown
+            .get(i)
+            .map(|(t, c)| (t.as_str(), *c))
+            .unwrap_or(("", None));
+        let (text, w) = clip_columns(text, popup.hscroll, inner);
+
+        queue!(row, style::Print(" │"))?;
+        match color {
+            Some(c) => queue!(
+                row,
+                style::SetForegroundColor(c),
+                style::Print(&text),
+                style::ResetColor
+            )?,
+            None => queue!(row, style::Print(&text))?,
+        }
+        queue!(row, style::Print(format!("{}│ ", " ".repeat(inner - w))))?;
+    }
 
-        queue!(out, style::Print(format!("{} ", mark_symbol)))?;
+    bottom.clear();
+    let hint = format!(
+        " {}-{}/{} · {} ",
+        (popup.scroll + 1).min(popup.lines.len()),
+        end,
+        popup.lines.len(),
+        popup.keys_hint
+    );
+    queue!(bottom, style::Print(frame(&hint, "└", "┘")))?;
+    Ok(())
+}
+
+/// Ligne récapitulant les règles libres ajoutées avec "+"
+fn render_extra_rules(out: &mut Vec<u8>, rules: &[String], term_width: usize) -> Result<()> {
+    let (text, _) = clip_columns(&format!(" Custom rules: {}", rules.join("  ")), 0, term_width);
+    queue!(
+        out,
+        style::SetForegroundColor(style::Color::Magenta),
+        style::Print(text),
+        style::ResetColor
+    )?;
+    Ok(())
+}
+
+/// Panneau de messages ancré en bas : titre puis les derniers messages
+fn render_log(rows: &mut [Vec<u8>], log: &[String]) -> Result<()> {
+    let Some((title, entries)) = rows.split_first_mut() else {
+        return Ok(());
+    };
+
+    queue!(
+        title,
+        style::SetAttribute(style::Attribute::Bold),
+        style::Print(format!("── Messages ({}) ── [L] to hide ", log.len())),
+        style::SetAttribute(style::Attribute::Reset)
+    )?;
+
+    let start = log.len().saturating_sub(entries.len());
+    for (row, msg) in entries.iter_mut().zip(&log[start..]) {
+        queue!(
+            row,
+            style::SetForegroundColor(style::Color::DarkYellow),
+            style::Print(format!(" {}", msg)),
+            style::ResetColor
+        )?;
+    }
+    Ok(())
+}
+
+/// Ligne d'état, au-dessus du pied de page : mode et chemin complet de l'entrée sous
+/// le curseur, puis les compteurs globaux. Un chemin trop long est coupé à gauche.
+fn render_status(out: &mut impl Write, app: &App, term_width: usize) -> Result<()> {
+    // Part des fichiers de l'arbre qui seraient ignorés
+    let percent = (app.marked_files * 100).checked_div(app.total_files).unwrap_or(0);
+    let exceptions = app.nodes.iter().filter(|n| n.mode == Mode::E).count();
+    let marked_size: u64 = app.nodes.iter().filter(|n| n.mark && !n.is_dir).map(|n| n.size).sum();
+    let counts = format!(
+        "  marked {} / {} files ({}), {}%  exceptions: {}",
+        app.marked_files,
+        app.total_files,
+        format_size(marked_size),
+        percent,
+        exceptions
+    );
+
+    if let Some(n) = app.visible.get(app.cursor_pos).map(|&i| &app.nodes[i]) {
+        let mode = match n.mode {
+            Mode::C => "C rule",
+            Mode::E => "E exception",
+            Mode::N => "N",
+        };
+        let path = if n.rel.is_empty() { "/" } else { n.rel.as_str() };
+        let room = term_width.saturating_sub(mode.len() + 4 + counts.len());
+        let skip = path.width().saturating_sub(room);
+        let (path, _) = clip_columns(path, skip, room);
+        queue!(
+            out,
+            style::Print(format!(" {} ", path)),
+            style::SetForegroundColor(style::Color::DarkGrey),
+            style::Print(format!("[{}]", mode)),
+            style::ResetColor
+        )?;
+    }
+
+    queue!(
+        out,
+        style::SetForegroundColor(style::Color::DarkGrey),
+        style::Print(counts),
+        style::ResetColor
+    )?;
+    Ok(())
+}
+
+fn render_footer(out: &mut impl Write, app: &App) -> Result<()> {
+    if let Some(flash) = &app.flash {
+        queue!(
+            out,
+            style::SetForegroundColor(flash.color),
+            style::Print(format!(" {}", flash.text)),
+            style::ResetColor
+        )?;
+    }
+
+    let conflicts = app.nodes.iter().filter(|n| app.is_conflict(n)).count();
+    if conflicts > 0 {
+        queue!(
+            out,
+            style::SetForegroundColor(style::Color::Red),
+            style::Print(format!("  {} ignored but tracked", conflicts)),
+            style::ResetColor
+        )?;
+    }
+
+    if let Some((rule, matches)) = &app.rule_highlight {
+        queue!(
+            out,
+            style::SetForegroundColor(style::Color::Magenta),
+            style::Print(format!("  ◆ {}: {}", rule, matches.len())),
+            style::ResetColor
+        )?;
+    }
+
+    // Taille du .gitignore : règles comprises par l'outil / recopiées telles quelles
+    let counts = app.rule_counts;
+    queue!(
+        out,
+        style::SetForegroundColor(style::Color::DarkGrey),
+        style::Print(format!(
+            "  {}: {} lines, {} parsed, {} kept as-is",
+            primary_label(app.scan_opts.exclude_file.as_deref()),
+            counts.lines,
+            counts.rules,
+            counts.passthrough
+        )),
+        style::ResetColor
+    )?;
+    Ok(())
+}
+
+/// Renderer différentiel : garde les lignes dessinées à la frame précédente
+/// et ne réécrit que celles dont le contenu (surlignage du curseur compris) a changé.
+struct Screen {
+    prev: Vec<Vec<u8>>,
+    size: (u16, u16),
+}
+
+impl Screen {
+    fn new() -> Self {
+        Screen {
+            prev: Vec::new(),
+            size: (0, 0),
+        }
+    }
+
+    /// Force un redessin complet à la prochaine frame.
+    fn invalidate(&mut self) {
+        self.prev.clear();
+    }
+
+    /// Dessine une frame (une entrée par ligne de terminal, sans positionnement)
+    /// en ne réécrivant que les lignes modifiées.
+    fn draw(&mut self, rows: Vec<Vec<u8>>) -> Result<()> {
+        let mut out = stdout();
+        let size = terminal::size()?;
+
+        if size != self.size || self.prev.is_empty() {
+            self.size = size;
+            self.prev.clear();
+            queue!(out, terminal::Clear(ClearType::All))?;
+        }
+
+        queue!(out, cursor::Hide)?;
+        for (y, row) in rows.iter().enumerate() {
+            if self.prev.get(y) == Some(row) {
+                continue;
+            }
+            queue!(
+                out,
+                cursor::MoveTo(0, y as u16),
+                style::ResetColor,
+                style::SetAttribute(style::Attribute::Reset),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+            out.write_all(row)?;
+            queue!(out, style::ResetColor, style::SetAttribute(style::Attribute::Reset))?;
+        }
+
+        out.flush()?;
+        self.prev = rows;
+        Ok(())
+    }
+}
+
+fn render(app: &App, screen: &mut Screen) -> Result<()> {
+    let (term_width, term_height) = terminal::size()?;
+    let nodes = &app.nodes;
+    let visible = &app.visible;
+    let cursor_pos = app.cursor_pos;
+    let scroll_offset = app.scroll_offset;
+    let theme = &app.theme;
+
+    let viewport_rows = viewport_rows(app)?;
+
+    // Une entrée par ligne de terminal : en-tête, arbre, lignes vides, pied de page
+    let mut rows: Vec<Vec<u8>> = vec![Vec::new(); term_height as usize];
+
+    if let Some(row) = rows.get_mut(0) {
+        render_header(row, app)?;
+    }
+    if let (Some(prompt), Some(row)) = (&app.prompt, rows.get_mut(1)) {
+        render_prompt(row, prompt)?;
+    }
+
+    let visible_start = scroll_offset.min(visible.len());
+    let visible_end = (visible_start + viewport_rows).min(visible.len());
+
+    for (line_idx, vis_idx) in (visible_start..visible_end).enumerate() {
+        let i = visible[vis_idx];
+        let n = &nodes[i];
+        let y = HEADER_ROWS as usize + line_idx;
+        let Some(out) = rows.get_mut(y) else {
+            break;
+        };
+
+        if vis_idx == cursor_pos {
+            queue!(out, style::SetAttribute(style::Attribute::Reverse))?;
+        }
+
+        // Colonne des permissions (touche x), en rouge si tout le monde peut écrire
+        if app.show_permissions {
+            let world_writable = n.permissions.is_some_and(|m| m & 0o002 != 0);
+            let color = if world_writable { style::Color::Red } else { style::Color::DarkGrey };
+            queue!(
+                out,
+                style::SetForegroundColor(color),
+                style::Print(format!("{} ", format_permissions(n.permissions))),
+                style::ResetColor
+            )?;
+        }
+
+        // Colonne des tailles (touche z) : cumulée pour un répertoire
+        if app.show_sizes {
+            queue!(
+                out,
+                style::SetForegroundColor(style::Color::DarkGrey),
+                style::Print(format!("{:>w$} ", format_size(app.sizes[i]), w = SIZE_COLUMN_WIDTH)),
+                style::ResetColor
+            )?;
+        }
+
+        let compact = app.compact && !app.flat;
+        if !app.flat {
+            let depth = if compact { app.compact_depths[i] } else { n.depth };
+            queue!(out, style::Print(theme.connector.repeat(depth)))?;
+        }
+
+        // NEW : affichage du symbole de mark
+        let (mark_symbol, symbol_color) = if n.pinned {
+            (theme.pinned, Some(style::Color::Green)) // épinglé : jamais ignoré
+        } else if n.generic_mark {
+            (theme.generic, None) // NEW : fichier marqué par règle générique
+        } else if n.mark {
+            (theme.marked, None)
+        } else if n.also.is_some() {
+            (theme.also, Some(style::Color::Cyan)) // ignoré par un fichier --also, en lecture seule
+        } else if n.global {
+            (theme.global, Some(style::Color::Magenta)) // exclusions globales de git, jamais enregistrées
+        } else {
+            (theme.unmarked, None)
+        };
+        if let Some(color) = symbol_color {
+            queue!(
+                out,
+                style::SetForegroundColor(color),
+                style::Print(format!("{} ", mark_symbol)),
+                style::ResetColor
+            )?;
+        } else {
+            queue!(out, style::Print(format!("{} ", mark_symbol)))?;
+        }
 
         if n.is_dir {
-            let marker = if n.expanded { "▾" } else { "▸" };
-            let has_mixed = n.cpt_mixed_marks > 0;
+            let chain = if compact { shown_chain(nodes, i) } else { vec![i] };
+            // Ligne compacte : repliée dès qu'un répertoire de la chaîne l'est
+            let expanded = chain.last().is_some_and(|&c| nodes[c].expanded);
+            let marker = if expanded { theme.expanded } else { theme.collapsed };
+            let name = chain.iter().map(|&c| nodes[c].name.as_str()).collect::<Vec<_>>().join("/");
+            let has_mixed = app.config.shows_mixed(n);
             
             if has_mixed {
                 queue!(
                     out,
                     style::SetForegroundColor(style::Color::Yellow),
                     style::SetAttribute(style::Attribute::Bold),
-                    style::Print(format!("{} {}", marker, n.name)),
+                    style::Print(format!("{} {}", marker, name)),
                     style::ResetColor,
                     style::SetAttribute(style::Attribute::Reset)
                 )?;
@@ -479,14 +1290,19 @@ fn render(nodes: &Vec<Node>, visible: &Vec<usize>, cursor_pos: usize, scroll_off
                     out,
                     style::SetForegroundColor(dir_color),
                     style::SetAttribute(style::Attribute::Bold),
-                    style::Print(format!("{} {}", marker, n.name)),
+                    style::Print(format!("{} {}", marker, name)),
                     style::ResetColor,
                     style::SetAttribute(style::Attribute::Reset)
                 )?;
             }
         } else {
-            // NEW : fichier marqué -> gris
-            let file_color = if n.mark {
+            // En liste à plat, le chemin complet remplace l'arborescence
+            let label = if app.flat { &n.rel } else { &n.name };
+
+            // NEW : fichier marqué -> gris ; ignoré mais encore suivi -> rouge
+            let file_color = if app.is_conflict(n) {
+                style::Color::Red
+            } else if n.mark {
                 style::Color::DarkGrey
             } else {
                 style::Color::White
@@ -495,7 +1311,47 @@ fn render(nodes: &Vec<Node>, visible: &Vec<usize>, cursor_pos: usize, scroll_off
             queue!(
                 out,
                 style::SetForegroundColor(file_color),
-                style::Print(format!("  {}", n.name)),
+                style::Print(format!("  {}", label)),
+                style::ResetColor
+            )?;
+        }
+
+        // .gitignore de sous-répertoire qui recevra la règle
+        if let Some(dir) = &n.target {
+            let rel = dir.strip_prefix(&app.root).unwrap_or(dir);
+            queue!(
+                out,
+                style::SetForegroundColor(style::Color::Cyan),
+                style::Print(format!("  → {}/.gitignore", rel.to_string_lossy())),
+                style::ResetColor
+            )?;
+        }
+
+        // Entrée touchée par la règle examinée avec W (lecture seule)
+        if app.rule_highlight.as_ref().is_some_and(|(_, m)| m.contains(&i)) {
+            queue!(
+                out,
+                style::SetForegroundColor(style::Color::Magenta),
+                style::Print("  ◆"),
+                style::ResetColor
+            )?;
+        }
+
+        // Ligne de règle saisie à la main
+        if n.unreadable {
+            queue!(
+                out,
+                style::SetForegroundColor(style::Color::Red),
+                style::Print("  (unreadable)"),
+                style::ResetColor
+            )?;
+        }
+
+        if let Some(line) = &n.override_line {
+            queue!(
+                out,
+                style::SetForegroundColor(style::Color::Magenta),
+                style::Print(format!("  ✎ {}", line)),
                 style::ResetColor
             )?;
         }
@@ -505,149 +1361,443 @@ fn render(nodes: &Vec<Node>, visible: &Vec<usize>, cursor_pos: usize, scroll_off
         }
     }
 
-    out.flush()?;
-    Ok(())
+    // Sous l'arbre : lignes libres, puis journal, puis pied de page
+    let mut bottom = rows.len().saturating_sub(FOOTER_ROWS as usize);
+    if app.show_log {
+        let start = bottom.saturating_sub(LOG_ROWS as usize);
+        render_log(&mut rows[start..bottom], &app.log)?;
+        bottom = start;
+    }
+    if !app.extra_rules.is_empty() && bottom > HEADER_ROWS as usize {
+        render_extra_rules(&mut rows[bottom - 1], &app.extra_rules, term_width as usize)?;
+    }
+
+    if let Some(popup) = &app.popup {
+        let start = (HEADER_ROWS as usize).min(rows.len());
+        let end = (start + viewport_rows).min(rows.len());
+        render_scrollable_popup(&mut rows[start..end], popup, term_width as usize)?;
+    }
+
+    // Propositions de la saisie en cours (touches f, W), par-dessus l'arbre
+    if let Some(prompt) = app.prompt.as_ref().filter(|p| !p.choices.is_empty()) {
+        let start = (HEADER_ROWS as usize).min(rows.len());
+        let end = (start + viewport_rows).min(rows.len());
+        let popup = choices_popup(prompt, viewport_rows.saturating_sub(2));
+        render_scrollable_popup(&mut rows[start..end], &popup, term_width as usize)?;
+    }
+
+    if let [.., status, footer] = rows.as_mut_slice() {
+        render_status(status, app, term_width as usize)?;
+        render_footer(footer, app)?;
+    }
+
+    screen.draw(rows)
 }
 
-/// Vérifie si un fichier devrait être ignoré selon les règles du .gitignore
-fn should_be_ignored(file_path: &str, rules: &[Rule]) -> bool {
-    let normalized = file_path.replace("\\", "/");
-    let mut should_ignore = false;
+/// Propositions de la saisie filtrées par le texte tapé, la ligne choisie
+/// en surbrillance et toujours dans les `body_rows` lignes affichées.
+fn choices_popup(prompt: &Prompt, body_rows: usize) -> Popup {
+    let lines: Vec<(String, Option<style::Color>)> = fuzzy_filter(&prompt.choices, &prompt.input)
+        .into_iter()
+        .enumerate()
+        .map(|(row, i)| {
+            if row == prompt.selected {
+                (format!("> {}", prompt.choices[i]), Some(style::Color::Yellow))
+            } else {
+                (format!("  {}", prompt.choices[i]), None)
+            }
+        })
+        .collect();
+    let (title, keys_hint) = match prompt.kind {
+        PromptKind::JumpToDir => ("Directories", "↑/↓ choose · Enter jump · Esc cancel"),
+        PromptKind::ConfirmSizeMark(_) => ("Directories to mark", "y mark them · any other key cancels"),
+        _ => ("Rules kept verbatim", "↑/↓ choose · Enter show matches · Esc cancel"),
+    };
+    let scroll = (prompt.selected + 1).saturating_sub(body_rows.max(1));
+    let mut popup = Popup::new(&format!("{} ({})", title, lines.len()), lines);
+    popup.scroll = scroll;
+    popup.keys_hint = keys_hint;
+    popup
+}
 
-    for rule in rules {
-        let pat = &rule.pattern;
+/// Système de gestion de versions du projet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Vcs {
+    Git,
+    Jj,
+}
 
-        // "*" = tout
-        if pat == "*" {
-            match rule.mode {
-                Mode::C => {
-                    should_ignore = true;
-                }
-                Mode::E => {
-                    should_ignore = false;
-                }
-                Mode::N => {}
+impl Vcs {
+    /// Détecte le VCS en remontant depuis root. .jj est prioritaire :
+    /// un dépôt jj colocalisé contient aussi un .git.
+    fn detect(root: &Path) -> Option<Vcs> {
+        let start = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        for dir in start.ancestors() {
+            if dir.join(".jj").is_dir() {
+                return Some(Vcs::Jj);
             }
-            continue;
+            if dir.join(".git").exists() {
+                return Some(Vcs::Git);
+            }
+        }
+        None
+    }
+
+    /// Nom de la dernière révision committée, pour l'affichage
+    fn committed_label(self) -> &'static str {
+        match self {
+            Vcs::Git => "HEAD:.gitignore",
+            Vcs::Jj => "@-:.gitignore",
         }
+    }
 
-        let is_exact = normalized == *pat;
-        let is_descendant = normalized.starts_with(pat)
-            && normalized.len() > pat.len()
-            && normalized.as_bytes()[pat.len()] == b'/';
+    /// Fichiers suivis par le VCS, en chemins relatifs à root
+    fn tracked_files(self, root: &Path) -> Result<Vec<String>> {
+        // -z : chemins bruts séparés par NUL, sans guillemets pour les caractères spéciaux.
+        // jj n'a pas d'équivalent stable : une ligne par chemin (un saut de ligne
+        // dans un nom reste donc impossible à distinguer).
+        let (program, args, sep): (&str, &[&str], char) = match self {
+            Vcs::Git => ("git", &["ls-files", "-z"], '\0'),
+            Vcs::Jj => ("jj", &["file", "list"], '\n'),
+        };
+        Ok(split_paths(&run_vcs(root, program, args)?, sep))
+    }
 
-        match rule.mode {
-            Mode::C => {
-                if is_exact || is_descendant {
-                    should_ignore = true;
-                }
+    /// Contenu du .gitignore de root dans la dernière révision committée
+    fn committed_gitignore(self, root: &Path) -> Result<String> {
+        let (program, args): (&str, &[&str]) = match self {
+            Vcs::Git => ("git", &["show", "HEAD:./.gitignore"]),
+            Vcs::Jj => ("jj", &["file", "show", "-r", "@-", ".gitignore"]),
+        };
+        run_vcs(root, program, args)
+    }
+
+    /// Fichiers apparus depuis la révision `rev`, en chemins relatifs à root :
+    /// ajoutés depuis `rev`, plus (avec git) les fichiers non suivis.
+    fn added_since(self, root: &Path, rev: &str) -> Result<HashSet<String>> {
+        let mut files = HashSet::new();
+        match self {
+            Vcs::Git => {
+                let added = run_vcs(root, "git", &["diff", "--name-only", "--relative", "--diff-filter=A", "-z", rev])?;
+                let untracked = run_vcs(root, "git", &["ls-files", "--others", "-z"])?;
+                files.extend(added.split('\0').chain(untracked.split('\0')).map(str::to_string));
             }
-            Mode::E => {
-                if is_exact || is_descendant {
-                    should_ignore = false;
-                }
+            Vcs::Jj => {
+                // jj suit tout seul les nouveaux fichiers : lignes "A chemin" du résumé
+                let summary = run_vcs(root, "jj", &["diff", "--summary", "--from", rev])?;
+                files.extend(summary.lines().filter_map(|l| l.strip_prefix("A ")).map(str::to_string));
             }
-            Mode::N => {}
+        }
+        files.remove("");
+        Ok(files.into_iter().map(|f| if cfg!(windows) { f.replace('\\', "/") } else { f }).collect())
+    }
+
+    /// Nom de la commande, pour les messages
+    fn program(self) -> &'static str {
+        match self {
+            Vcs::Git => "git",
+            Vcs::Jj => "jj",
+        }
+    }
+
+    /// Commande qui arrête de suivre un fichier sans le supprimer du disque
+    fn untrack_args(self) -> &'static [&'static str] {
+        match self {
+            Vcs::Git => &["rm", "--cached", "--quiet", "--"],
+            Vcs::Jj => &["file", "untrack"],
         }
     }
 
-    should_ignore
+    /// Arrête de suivre `file` (chemin relatif à root)
+    fn untrack(self, root: &Path, file: &str) -> Result<()> {
+        let mut args = self.untrack_args().to_vec();
+        args.push(file);
+        run_vcs(root, self.program(), &args).map(|_| ())
+    }
+}
+
+/// Découpe la sortie d'une commande du VCS en chemins, sur `sep` uniquement :
+/// rien n'est retiré autour des noms, les espaces en tête ou en fin en font partie
+fn split_paths(output: &str, sep: char) -> Vec<String> {
+    output
+        .split(sep)
+        .filter(|f| !f.is_empty())
+        .map(|f| if cfg!(windows) { f.replace('\\', "/") } else { f.to_string() })
+        .collect()
+}
+
+/// --exclude : chemin du .git/info/exclude du dépôt git dont `root` est la racine
+/// (le fichier et le répertoire info/ sont créés à la sauvegarde s'il le faut)
+fn git_exclude_file(root: &Path) -> Result<PathBuf> {
+    let prefix = run_vcs(root, "git", &["rev-parse", "--show-prefix"]).context("--exclude needs a git repository")?;
+    if !prefix.trim_end().is_empty() {
+        bail!(
+            "--exclude must be run from the repository root ('{}' is inside it)",
+            prefix.trim_end().trim_end_matches('/')
+        );
+    }
+    // --git-path : aussi juste pour un worktree, où .git est un fichier
+    let path = run_vcs(root, "git", &["rev-parse", "--git-path", "info/exclude"])?;
+    Ok(root.join(path.trim_end_matches(['\n', '\r'])))
 }
 
-/// Exécute `jj file list` et désindexe les fichiers qui devraient être ignorés
-fn untrack_ignored_files(root: &Path) -> Result<()> {
-    // Exécute `jj file list`
-    let output = Command::new("jj")
-        .arg("file")
-        .arg("list")
+/// Lance une commande du VCS dans root et renvoie sa sortie standard
+fn run_vcs(root: &Path, program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
         .current_dir(root)
         .output()
-        .context("Failed to execute 'jj file list'")?;
+        .context(format!("Failed to execute '{} {}'", program, args.join(" ")))?;
 
     if !output.status.success() {
-        bail!("'jj file list' failed: {}", String::from_utf8_lossy(&output.stderr));
+        bail!(
+            "'{} {}' failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
     }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
 
-    let tracked_files = String::from_utf8_lossy(&output.stdout);
-    
-    // Parse les règles du .gitignore actuel (règles simples)
-    let rules = parse_gitignore(root)?;
+/// Règle du .gitignore qui fait ignorer un fichier suivi
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UntrackMatch {
+    /// Règle simple, gérée dans l'arbre
+    Rule,
+    /// Pattern générique (*.png, etc.)
+    Generic,
+}
 
-    // NEW : matcher pour les règles génériques (*.png, etc.)
-    let generic_gitignore = build_generic_gitignore(root)?;
-    
-    let mut untracked_count = 0;
-    
-    for file in tracked_files.lines() {
-        let file = file.trim();
-        if file.is_empty() {
+/// Fichiers suivis par `vcs` que le fichier d'ignore édité (voir read_primary) ignore,
+/// par règles simples et génériques. Analyse en lecture seule, partagée par l'untrack
+/// et --list-untrack.
+fn tracked_files_to_untrack(root: &Path, vcs: Vcs, opts: &ScanOptions) -> Result<Vec<(String, UntrackMatch)>> {
+    // Exécute `jj file list` / `git ls-files`
+    let tracked_files = vcs.tracked_files(root)?;
+
+    let mut warnings = Vec::new();
+    let content = read_primary(root, opts)?;
+    let files = untrack_matches(root, &content, &tracked_files, opts.ignore_case, &mut warnings)?;
+    for w in &warnings {
+        eprintln!("Warning: {}", w);
+    }
+    Ok(files)
+}
+
+/// Parmi les fichiers suivis `tracked_files`, ceux qu'ignore le .gitignore de contenu
+/// `content` : règles simples, puis patterns génériques (*.png, etc.), avec la même
+/// casse que l'arbre (`ignore_case`, voir ScanOptions)
+fn untrack_matches(
+    root: &Path,
+    content: &str,
+    tracked_files: &[String],
+    ignore_case: bool,
+    log: &mut Vec<String>,
+) -> Result<Vec<(String, UntrackMatch)>> {
+    let rules = parse_gitignore_content(content);
+    let generic_gitignore = generic_gitignore_from_content(root, content, ignore_case, log)?;
+
+    Ok(tracked_files
+        .iter()
+        .filter(|file| !file.is_empty())
+        .filter_map(|file| {
+            // Règles simples, puis patterns génériques
+            if should_be_ignored(file, &rules, ignore_case) {
+                Some((file.to_string(), UntrackMatch::Rule))
+            } else if generic_gitignore
+                .as_ref()
+                .is_some_and(|gi| gi.matched(Path::new(file), false).is_ignore())
+            {
+                Some((file.to_string(), UntrackMatch::Generic))
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+/// --list-untrack : affiche les fichiers suivis que le .gitignore ignore,
+/// groupés par type de règle, sans rien modifier dans le dépôt.
+fn print_untrack_list(root: &Path, opts: &ScanOptions) -> Result<()> {
+    let Some(vcs) = Vcs::detect(root) else {
+        bail!("--list-untrack needs a git or jj repository");
+    };
+    let files = tracked_files_to_untrack(root, vcs, opts)?;
+    if files.is_empty() {
+        println!("No tracked file is ignored by the current {}.", primary_label(opts.exclude_file.as_deref()));
+        return Ok(());
+    }
+
+    for (kind, title) in [
+        (UntrackMatch::Rule, "Matched by a rule"),
+        (UntrackMatch::Generic, "Matched by a generic pattern"),
+    ] {
+        let group: Vec<&str> = files
+            .iter()
+            .filter(|(_, k)| *k == kind)
+            .map(|(f, _)| f.as_str())
+            .collect();
+        if group.is_empty() {
             continue;
         }
-        
-        // Vérifie si le fichier devrait être ignoré par les règles simples
-        let mut ignored = should_be_ignored(file, &rules);
+        println!("{} ({}):", title, group.len());
+        for file in group {
+            println!("  {}", file);
+        }
+    }
+    println!("\n{} tracked file(s) would be untracked.", files.len());
+    Ok(())
+}
+
+/// Réponse à la question posée pour chaque fichier avec --interactive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UntrackAnswer {
+    Yes,
+    No,
+    /// Oui pour ce fichier et tous les suivants
+    All,
+    /// On s'arrête là
+    Quit,
+}
+
+fn ask_untrack(file: &str) -> Result<UntrackAnswer> {
+    loop {
+        let answer = match inquire::Text::new(&format!("untrack '{}'? [y/n/a/q]", file)).prompt() {
+            Ok(answer) => answer,
+            // Échap ou Ctrl+C : on arrête sans rien désindexer de plus
+            Err(inquire::InquireError::OperationCanceled)
+            | Err(inquire::InquireError::OperationInterrupted) => return Ok(UntrackAnswer::Quit),
+            Err(e) => return Err(e).context("Reading answer"),
+        };
+        match answer.trim() {
+            "y" | "Y" => return Ok(UntrackAnswer::Yes),
+            "n" | "N" | "" => return Ok(UntrackAnswer::No),
+            "a" | "A" => return Ok(UntrackAnswer::All),
+            "q" | "Q" => return Ok(UntrackAnswer::Quit),
+            _ => println!("Please answer y (yes), n (no), a (all remaining) or q (quit)."),
+        }
+    }
+}
+
+/// Récapitulatif affiché avant l'untrack avec --confirm : les commandes exactes qui vont
+/// être lancées, puis une question y/N. Renvoie false si l'utilisateur renonce.
+fn confirm_untrack(root: &Path, vcs: Vcs, files: &[(String, UntrackMatch)]) -> Result<bool> {
+    println!("The following command(s) will run in '{}':", root.display());
+    let command = format!("{} {}", vcs.program(), vcs.untrack_args().join(" "));
+    for (file, _) in files {
+        println!("  {} {}", command, file);
+    }
+    let by_rule = files.iter().filter(|(_, k)| *k == UntrackMatch::Rule).count();
+    println!(
+        "\n{} tracked file(s) will be untracked ({} by a rule, {} by a generic pattern).",
+        files.len(),
+        by_rule,
+        files.len() - by_rule
+    );
+
+    match inquire::Confirm::new("Proceed?").with_default(false).prompt() {
+        Ok(answer) => Ok(answer),
+        // Échap ou Ctrl+C valent non
+        Err(inquire::InquireError::OperationCanceled)
+        | Err(inquire::InquireError::OperationInterrupted) => Ok(false),
+        Err(e) => Err(e).context("Reading answer"),
+    }
+}
 
-        // NEW : vérifie aussi contre les patterns génériques
-        if !ignored {
-            if let Some(ref gi) = generic_gitignore {
-                let path = Path::new(file);
-                if gi.matched(path, false).is_ignore() {
-                    ignored = true;
+/// Liste les fichiers suivis par `vcs` (`jj file list` / `git ls-files`) et désindexe
+/// ceux qui devraient être ignorés (`jj file untrack` / `git rm --cached`).
+/// En `dry_run`, on affiche seulement les fichiers qui seraient désindexés.
+/// En `interactive`, chaque fichier est soumis à confirmation (y/n/a/q).
+/// Avec `confirm`, le récapitulatif de confirm_untrack doit d'abord être accepté.
+fn untrack_ignored_files(
+    root: &Path,
+    vcs: Vcs,
+    opts: &ScanOptions,
+    dry_run: bool,
+    interactive: bool,
+    confirm: bool,
+) -> Result<()> {
+    let files = tracked_files_to_untrack(root, vcs, opts)?;
+
+    if confirm && !dry_run && !files.is_empty() && !confirm_untrack(root, vcs, &files)? {
+        println!("\nUntrack cancelled: no file was untracked.");
+        return Ok(());
+    }
+
+    // Rien n'est modifié en dry-run : inutile de demander
+    let mut ask = interactive && !dry_run;
+    let mut untracked_count = 0;
+    let mut skipped_count = 0;
+
+    for (i, (file, _)) in files.iter().enumerate() {
+        if ask {
+            match ask_untrack(file)? {
+                UntrackAnswer::Yes => {}
+                UntrackAnswer::No => {
+                    skipped_count += 1;
+                    continue;
+                }
+                UntrackAnswer::All => ask = false,
+                UntrackAnswer::Quit => {
+                    skipped_count += files.len() - i;
+                    break;
                 }
             }
         }
-        
-        if ignored {
-            println!("Untracking: {}", file);
-            
-            let untrack_output = Command::new("jj")
-                .arg("file")
-                .arg("untrack")
-                .arg(file)
-                .current_dir(root)
-                .output()
-                .context(format!("Failed to untrack '{}'", file))?;
-            
-            if !untrack_output.status.success() {
-                eprintln!("Warning: Failed to untrack '{}': {}", 
-                    file, 
-                    String::from_utf8_lossy(&untrack_output.stderr));
-            } else {
-                untracked_count += 1;
-            }
+
+        if dry_run {
+            println!("Would untrack: {}", file);
+            untracked_count += 1;
+            continue;
+        }
+        println!("Untracking: {}", file);
+
+        // Un échec sur un fichier n'arrête pas les suivants
+        match vcs.untrack(root, file) {
+            Ok(()) => untracked_count += 1,
+            Err(e) => eprintln!("Warning: Failed to untrack '{}': {:#}", file, e),
         }
     }
-    
-    if untracked_count > 0 {
+
+    if untracked_count > 0 && dry_run {
+        println!("\nWould untrack {} file(s) that should be ignored (dry run).", untracked_count);
+    } else if untracked_count > 0 {
         println!("\nUntracked {} file(s) that should be ignored.", untracked_count);
-    } else {
+    } else if skipped_count == 0 {
         println!("\nNo files to untrack.");
     }
-    
-    Ok(())
-}
-
-/// NEW : Construit un matcher pour les règles génériques (*.png, etc.)
-fn build_generic_gitignore(root: &Path) -> Result<Option<ignore::gitignore::Gitignore>> {
-    let gitignore_path = root.join(".gitignore");
-    if !gitignore_path.exists() {
-        return Ok(None);
+    if skipped_count > 0 {
+        println!("Left {} ignored file(s) tracked.", skipped_count);
     }
 
-    let content = fs::read_to_string(&gitignore_path)
-        .context("Reading .gitignore for generic patterns")?;
+    Ok(())
+}
 
+/// NEW : Construit un matcher pour les règles génériques (*.png, etc.) d'un contenu de .gitignore.
+/// Les lignes que le matcher refuse sont ignorées et signalées dans `log`.
+fn generic_gitignore_from_content(
+    root: &Path,
+    content: &str,
+    ignore_case: bool,
+    log: &mut Vec<String>,
+) -> Result<Option<ignore::gitignore::Gitignore>> {
     let mut builder = GitignoreBuilder::new(root);
+    builder.case_insensitive(ignore_case)?;
     let mut has_patterns = false;
 
-    for line in content.lines() {
-        let trimmed = line.trim();
+    for (line_no, line) in content.lines().enumerate() {
+        let trimmed = gitignore_line(line);
         if trimmed.is_empty() || trimmed.starts_with('#') {
             continue;
         }
 
-        // On ignore les exceptions génériques pour l'instant
+        // Toutes les exceptions entrent dans le matcher, à leur place dans le fichier :
+        // "*.log" puis "!keep.log" ré-inclut keep.log, l'ordre inverse non (comme git)
         if trimmed.starts_with('!') {
+            if let Err(e) = builder.add_line(None, trimmed) {
+                log.push(format!(".gitignore line {}: could not parse '{}': {}", line_no + 1, trimmed, e));
+            }
             continue;
         }
 
@@ -663,10 +1813,15 @@ fn build_generic_gitignore(root: &Path) -> Result<Option<ignore::gitignore::Giti
 
         // On ne garde que les patterns avec wildcard
         if trimmed.contains('*') || trimmed.contains('?') || trimmed.contains('[') {
-            builder
-                .add_line(None, trimmed)
-                .context("Adding generic pattern to GitignoreBuilder")?;
-            has_patterns = true;
+            match builder.add_line(None, trimmed) {
+                Ok(_) => has_patterns = true,
+                Err(e) => log.push(format!(
+                    ".gitignore line {}: could not parse '{}': {}",
+                    line_no + 1,
+                    trimmed,
+                    e
+                )),
+            }
         }
     }
 
@@ -681,11 +1836,12 @@ fn build_generic_gitignore(root: &Path) -> Result<Option<ignore::gitignore::Giti
     Ok(Some(gitignore))
 }
 
-/// NEW : Marque les fichiers qui correspondent aux patterns génériques
-fn mark_generic_matches(nodes: &mut Vec<Node>, root: &Path) -> Result<()> {
-    let gitignore_opt = build_generic_gitignore(root)?;
-    let Some(gitignore) = gitignore_opt else {
-        return Ok(());
+/// NEW : Marque les fichiers qui correspondent aux patterns génériques.
+/// Un pattern qui vise un répertoire ("**/node_modules", "build/") marque tout
+/// ce qu'il contient, comme git.
+fn mark_generic_matches(nodes: &mut [Node], root: &Path, gitignore: Option<&ignore::gitignore::Gitignore>) {
+    let Some(gitignore) = gitignore else {
+        return;
     };
 
     for n in nodes.iter_mut() {
@@ -696,365 +1852,5786 @@ fn mark_generic_matches(nodes: &mut Vec<Node>, root: &Path) -> Result<()> {
             continue;
         }
 
-        let rel = n.path.strip_prefix(root).unwrap_or(&n.path);
-        let matched = gitignore.matched(rel, false);
+        let matched = gitignore.matched_path_or_any_parents(&n.rel, false);
 
         if matched.is_ignore() {
             n.mark = true;
             n.generic_mark = true;
+        } else if matched.is_whitelist() && n.generic_mark {
+            // Ré-inclus par une exception ("!keep.log") placée après le pattern
+            n.mark = false;
+            n.generic_mark = false;
         }
     }
 
     // Les marks ayant changé, on recalcule les mixed-marks
     recompute_cpt_mixed_marks(nodes);
-    Ok(())
 }
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    
-    let mut root_path = ".";
-    let mut use_jj = false;
-    
-    // Parse des arguments
-    let mut i = 1;
-    while i < args.len() {
-        match args[i].as_str() {
-            "-j" | "--jj" => {
-                use_jj = true;
-            }
-            arg if !arg.starts_with('-') => {
-                root_path = arg;
-            }
-            _ => {
-                bail!("Unknown argument: {}", args[i]);
+/// Préférences utilisateur, lues dans le fichier "config" de config_dir() (ou --config).
+/// Format : une ligne "clé = valeur" par option, "#" pour les commentaires.
+#[derive(Debug, Clone)]
+struct Config {
+    /// Commande lancée après l'écriture du .gitignore, qui reçoit son chemin en argument
+    on_save: Option<String>,
+    /// Voir GenerateOptions::collapse_extensions
+    collapse_extensions: bool,
+    /// Couleur jaune des répertoires au contenu mixte
+    mixed_color: bool,
+    /// Le jaune n'apparaît qu'au-delà de ce nombre de descendants mixtes
+    mixed_threshold: usize,
+    /// Répertoires dépliés au démarrage jusqu'à cette profondeur (voir --expand-depth)
+    expand_depth: Option<usize>,
+    /// Modèles de règles par nom (touches y / Y), lignes "template.<nom> = ..."
+    templates: BTreeMap<String, Template>,
+    /// Au-delà de ce nombre de descendants, basculer un répertoire demande confirmation (0 : jamais)
+    confirm_threshold: usize,
+    /// Récapitulatif à accepter avant l'untrack de -j (voir --confirm)
+    confirm_untrack: bool,
+    /// Enter déplie/replie les répertoires au lieu de les basculer (Espace bascule toujours)
+    enter_expands: bool,
+    /// Fichier d'où vient la configuration, et où les modèles sont enregistrés
+    path: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            on_save: None,
+            collapse_extensions: false,
+            mixed_color: true,
+            mixed_threshold: 0,
+            expand_depth: None,
+            templates: BTreeMap::new(),
+            confirm_threshold: 1000,
+            confirm_untrack: false,
+            enter_expands: false,
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Vrai si le répertoire doit être affiché avec la couleur "mixte"
+    fn shows_mixed(&self, n: &Node) -> bool {
+        self.mixed_color && n.cpt_mixed_marks > self.mixed_threshold
+    }
+
+    /// Emplacement par défaut du fichier de configuration
+    fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("gitignore-tui").join("config"))
+    }
+
+    /// Charge la configuration depuis `path` (--config), ou depuis l'emplacement par défaut.
+    /// Valeurs par défaut si le fichier n'existe pas.
+    fn load(path: Option<PathBuf>) -> Result<Config> {
+        let Some(path) = path.or_else(Config::default_path) else {
+            return Ok(Config::default());
+        };
+        if !path.exists() {
+            return Ok(Config {
+                path: Some(path),
+                ..Config::default()
+            });
+        }
+        let content = fs::read_to_string(&path)
+            .context(format!("Reading config {:?}", path))?;
+        let config = Config::parse(&content).context(format!("Parsing config {:?}", path))?;
+        Ok(Config {
+            path: Some(path),
+            ..config
+        })
+    }
+
+    fn parse(content: &str) -> Result<Config> {
+        let mut config = Config::default();
+
+        for (i, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let Some((key, value)) = trimmed.split_once('=') else {
+                bail!("line {}: expected 'key = value'", i + 1);
+            };
+            let value = value.trim();
+
+            match key.trim() {
+                "on_save" => {
+                    config.on_save = (!value.is_empty()).then(|| value.to_string());
+                }
+                "collapse_extensions" => {
+                    config.collapse_extensions = parse_bool(value)
+                        .with_context(|| format!("line {}", i + 1))?;
+                }
+                "mixed_color" => {
+                    config.mixed_color = parse_bool(value)
+                        .with_context(|| format!("line {}", i + 1))?;
+                }
+                "mixed_threshold" => {
+                    config.mixed_threshold = value
+                        .parse()
+                        .with_context(|| format!("line {}: expected a number, got '{}'", i + 1, value))?;
+                }
+                "expand_depth" => {
+                    config.expand_depth = Some(
+                        value
+                            .parse()
+                            .with_context(|| format!("line {}: expected a number, got '{}'", i + 1, value))?,
+                    );
+                }
+                "confirm_threshold" => {
+                    config.confirm_threshold = value
+                        .parse()
+                        .with_context(|| format!("line {}: expected a number, got '{}'", i + 1, value))?;
+                }
+                "confirm_untrack" => {
+                    config.confirm_untrack = parse_bool(value)
+                        .with_context(|| format!("line {}", i + 1))?;
+                }
+                "enter_expands" => {
+                    config.enter_expands = parse_bool(value)
+                        .with_context(|| format!("line {}", i + 1))?;
+                }
+                key if key.starts_with("template.") => {
+                    let name = &key["template.".len()..];
+                    let template = Template::parse(value)
+                        .with_context(|| format!("line {}: template '{}'", i + 1, name))?;
+                    config.templates.insert(name.to_string(), template);
+                }
+                other => bail!("line {}: unknown key '{}'", i + 1, other),
             }
         }
-        i += 1;
+
+        Ok(config)
     }
-    
-    let root = Path::new(root_path);
 
-    if !root.exists() || !root.is_dir() {
-        bail!("Path '{}' does not exist or is not a directory", root_path);
+    /// Enregistre (ou remplace) un modèle dans le fichier de configuration
+    fn save_template(&self, name: &str, template: &Template) -> Result<PathBuf> {
+        let Some(path) = self.path.clone() else {
+            bail!("can't locate the configuration directory, use --config <path>");
+        };
+        let existing = if path.exists() {
+            fs::read_to_string(&path).context(format!("Reading config {:?}", path))?
+        } else {
+            String::new()
+        };
+
+        let key = format!("template.{}", name);
+        let mut content: String = existing
+            .lines()
+            .filter(|l| l.split_once('=').map(|(k, _)| k.trim()) != Some(key.as_str()))
+            .map(|l| format!("{}\n", l))
+            .collect();
+        content.push_str(&format!("{} = {}\n", key, template.format()));
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!("Creating {:?}", dir))?;
+        }
+        fs::write(&path, content).context(format!("Writing config {:?}", path))?;
+        Ok(path)
     }
+}
 
-    let gitignore_path = root.join(".gitignore");
+/// Forme des règles d'un répertoire, réutilisable sur un autre de même structure :
+/// chemins relatifs au répertoire, avec leur mode (C ou E), en pré-ordre.
+/// Format dans la configuration : "C dist; E dist/keep.txt".
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Template {
+    entries: Vec<(String, Mode)>,
+}
 
-    // 1) On parse le .gitignore comme liste ordonnée de règles
-    let rules = parse_gitignore(root)?;
+impl Template {
+    /// Entrées "C <path>" / "E <path>" séparées par ";". Dans un chemin, "\" échappe
+    /// le caractère suivant : ";", "\\" lui-même, ou un espace de début ou de fin.
+    fn parse(value: &str) -> Result<Template> {
+        let mut entries = Vec::new();
+        for item in split_unescaped(value, ';') {
+            let item = item.trim_start();
+            if item.trim_end().is_empty() {
+                continue;
+            }
+            let mode = match item.split_once(' ') {
+                Some(("C", _)) => Mode::C,
+                Some(("E", _)) => Mode::E,
+                _ => bail!("expected 'C <path>' or 'E <path>', got '{}'", item.trim_end()),
+            };
+            let rel = unescape_path(&item[2..]);
+            if rel.is_empty() {
+                bail!("missing path in '{}'", item.trim_end());
+            }
+            entries.push((rel, mode));
+        }
+        Ok(Template { entries })
+    }
 
-    // 2) On construit l'arbre COMPLET (tous les fichiers, même dans les dossiers "repliés")
-    let mut nodes: Vec<Node> = build_full_tree(root)?;
+    /// Inverse de parse. Un dernier chemin qui finit par un espace est suivi d'un ";",
+    /// sans quoi la lecture de la configuration (qui enlève les espaces de fin de
+    /// ligne) le perdrait.
+    fn format(&self) -> String {
+        let mut value = self
+            .entries
+            .iter()
+            .map(|(rel, mode)| format!("{} {}", if *mode == Mode::E { "E" } else { "C" }, escape_path(rel)))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if self.entries.last().is_some_and(|(rel, _)| rel.ends_with(char::is_whitespace)) {
+            value.push(';');
+        }
+        value
+    }
 
-    // 3) On applique les règles : propagation des marks + exceptions
-    apply_rules_to_nodes(&mut nodes, root, &rules);
+    /// Relève les règles (C/E) à l'intérieur du répertoire `dir`
+    fn capture(nodes: &[Node], dir: usize) -> Template {
+        let depth = nodes[dir].depth;
+        let prefix = dir_prefix(&nodes[dir].rel);
+        let entries = nodes[dir + 1..]
+            .iter()
+            .take_while(|n| n.depth > depth)
+            .filter(|n| n.mode != Mode::N)
+            .map(|n| (n.rel[prefix.len()..].to_string(), n.mode))
+            .collect();
+        Template { entries }
+    }
 
-    // NEW : on applique les patterns génériques (*.png, etc.)
-    mark_generic_matches(&mut nodes, root)?;
+    /// Indice, sous `dir`, du node de chaque entrée ; Err avec les chemins absents
+    fn resolve(&self, nodes: &[Node], dir: usize) -> std::result::Result<Vec<(usize, Mode)>, Vec<String>> {
+        let depth = nodes[dir].depth;
+        let prefix = dir_prefix(&nodes[dir].rel);
+        let children: HashMap<&str, usize> = nodes[dir + 1..]
+            .iter()
+            .take_while(|n| n.depth > depth)
+            .enumerate()
+            .map(|(k, n)| (&n.rel[prefix.len()..], dir + 1 + k))
+            .collect();
+
+        let mut resolved = Vec::new();
+        let mut missing = Vec::new();
+        for (rel, mode) in &self.entries {
+            match children.get(rel.as_str()) {
+                Some(&i) => resolved.push((i, *mode)),
+                None => missing.push(rel.clone()),
+            }
+        }
+        if missing.is_empty() {
+            Ok(resolved)
+        } else {
+            Err(missing)
+        }
+    }
+}
 
-    // 4) On recalcule les cpt_exception et cpt_mixed_marks
-    recompute_cpt_exception(&mut nodes);
-    recompute_cpt_mixed_marks(&mut nodes);
+/// Découpe `value` sur les `sep` qui ne sont pas échappés par "\" (les échappements
+/// restent dans les morceaux, voir unescape_path)
+fn split_unescaped(value: &str, sep: char) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == sep {
+            items.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    items.push(&value[start..]);
+    items
+}
 
-    enable_raw_mode()?;
-    execute!(stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+/// Chemin d'une entrée de modèle : sans les espaces non échappés du début et de la
+/// fin, "\x" devenant "x"
+fn unescape_path(raw: &str) -> String {
+    let mut path = String::new();
+    // Longueur jusqu'au dernier caractère à garder (ni espace, ou bien échappé)
+    let mut keep = 0;
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        let escaped = c == '\\';
+        let c = if escaped { chars.next().unwrap_or('\\') } else { c };
+        path.push(c);
+        if escaped || !c.is_whitespace() {
+            keep = path.len();
+        }
+    }
+    path.truncate(keep);
+    path
+}
 
-    let mut cursor_pos: usize = 0;      // index dans les visibles
-    let mut scroll_offset: usize = 0;
+/// Inverse de unescape_path : échappe "\", ";" et les espaces du début et de la fin
+fn escape_path(path: &str) -> String {
+    let inner = path.trim_matches(char::is_whitespace);
+    let start = path.len() - path.trim_start_matches(char::is_whitespace).len();
+    let mut escaped = String::new();
+    for (i, c) in path.char_indices() {
+        let edge = i < start || i >= start + inner.len();
+        if matches!(c, '\\' | ';') || (edge && c.is_whitespace()) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
 
-    let mut visible = build_visible_indices(&nodes);
-    render(&nodes, &visible, cursor_pos, scroll_offset)?;
+/// Préfixe des chemins relatifs des descendants d'un répertoire ("" pour la racine)
+fn dir_prefix(rel: &str) -> String {
+    if rel.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", rel)
+    }
+}
 
-    loop {
-        match read()? {
-            Event::Key(k) => {
-                let (_, term_height) = terminal::size()?;
-                let available_height = (term_height as usize).saturating_sub(HEADER_ROWS as usize).max(1);
-
-                // Liste des visibles AVANT de traiter la touche
-                visible = build_visible_indices(&nodes);
-                if visible.is_empty() {
-                    cursor_pos = 0;
-                    scroll_offset = 0;
-                    continue;
-                }
-                if cursor_pos >= visible.len() {
-                    cursor_pos = visible.len().saturating_sub(1);
-                }
+/// Répertoire de configuration de la plateforme :
+/// $XDG_CONFIG_HOME (ou ~/.config) sous Linux et les autres Unix,
+/// ~/Library/Application Support sous macOS, %APPDATA% sous Windows.
+#[cfg(target_os = "macos")]
+fn config_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join("Library").join("Application Support"))
+}
 
-                let mut jump_to_idx: Option<usize> = None;
+#[cfg(windows)]
+fn config_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(PathBuf::from)
+}
 
-                match k.code {
-                    KeyCode::Char('q') => break,
-                    KeyCode::Up => {
-                        if cursor_pos > 0 {
-                            cursor_pos -= 1;
-                            if cursor_pos < scroll_offset {
-                                scroll_offset = cursor_pos;
-                            }
-                        }
-                    }
-                    KeyCode::Down => {
-                        if cursor_pos + 1 < visible.len() {
-                            cursor_pos += 1;
-                            if cursor_pos >= scroll_offset + available_height {
-                                scroll_offset = cursor_pos + 1 - available_height;
-                            }
-                        }
-                    }
-                    KeyCode::Right => {
-                        let idx = visible[cursor_pos];
-                        if nodes[idx].is_dir && !nodes[idx].expanded {
-                            nodes[idx].expanded = true;
-                        }
-                    }
-                    KeyCode::Left => {
-                        let idx = visible[cursor_pos];
-                        if nodes[idx].is_dir && nodes[idx].expanded {
-                            nodes[idx].expanded = false;
-                        } else {
-                            // Aller au parent si possible
-                            let depth = nodes[idx].depth;
-                            if depth > 0 {
-                                let mut p = idx;
-                                while p > 0 {
-                                    p -= 1;
-                                    if nodes[p].depth < depth {
-                                        jump_to_idx = Some(p);
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    KeyCode::Enter => {
-                        if visible.is_empty() {
-                            continue;
-                        }
+#[cfg(not(any(target_os = "macos", windows)))]
+fn config_dir() -> Option<PathBuf> {
+    // Un chemin relatif dans XDG_CONFIG_HOME est invalide d'après la spécification
+    if let Some(xdg) = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        if xdg.is_absolute() {
+            return Some(xdg);
+        }
+    }
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config"))
+}
 
-                        let idx = visible[cursor_pos];
-                        
-                        // NEW : les fichiers marqués par une règle générique (*.png, etc.) ne sont pas cliquables
-                        if nodes[idx].generic_mark && !nodes[idx].is_dir {
-                            continue;
-                        }
-                        
-                        let was_marked = nodes[idx].mark;
-                        let is_dir = nodes[idx].is_dir;
-
-                        if !was_marked {
-                            // mark : false -> true
-                            nodes[idx].mark = true;
-
-                            match nodes[idx].mode {
-                                Mode::E => {
-                                    nodes[idx].mode = Mode::N;
-                                }
-                                Mode::N => {
-                                    nodes[idx].mode = Mode::C;
-                                }
-                                Mode::C => {}
-                            }
-
-                            if is_dir {
-                                apply_recursive_mark_on_dir(&mut nodes, idx, true);
-                            }
-                        } else {
-                            // mark : true -> false
-                            nodes[idx].mark = false;
-
-                            match nodes[idx].mode {
-                                Mode::N => {
-                                    nodes[idx].mode = Mode::E;
-                                }
-                                Mode::C => {
-                                    nodes[idx].mode = Mode::N;
-                                }
-                                Mode::E => {}
-                            }
-
-                            if is_dir {
-                                apply_recursive_mark_on_dir(&mut nodes, idx, false);
-                            }
-                        }
+/// Valeur booléenne d'une option de configuration
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => bail!("expected a boolean, got '{}'", value),
+    }
+}
 
-                        // Recalcul global des compteurs
-                        recompute_cpt_exception(&mut nodes);
-                        recompute_cpt_mixed_marks(&mut nodes);
-                    }
-                    KeyCode::Char('s') => {
-                        let existing_content = if gitignore_path.exists() {
-                            fs::read_to_string(&gitignore_path)
-                                .context("Reading existing .gitignore")?
-                        } else {
-                            String::new()
-                        };
-
-                        let mut lines: Vec<String> =
-                            existing_content.lines().map(|s| s.to_string()).collect();
-
-                        use std::collections::HashSet;
-                        let mut to_remove: HashSet<String> = HashSet::new();
-
-                        // On prépare les variantes à supprimer (avec et sans "/")
-                        for n in &nodes {
-                            let rel = n.path.strip_prefix(root).unwrap_or(&n.path);
-                            let mut entry = rel.to_string_lossy().to_string();
-                            entry = entry.replace("\\", "/");
-
-                            if entry.is_empty() {
-                                continue; // le noeud racine "/" est géré à part
-                            }
-
-                            let base = entry.clone();
-
-                            // Anciennes formes sans "/" devant
-                            to_remove.insert(base.clone());
-                            to_remove.insert(format!("{base}/*"));
-                            to_remove.insert(format!("!{base}"));
-                            to_remove.insert(format!("!{base}/*"));
-
-                            // Nouvelles formes avec "/" devant
-                            to_remove.insert(format!("/{base}"));
-                            to_remove.insert(format!("/{base}/*"));
-                            to_remove.insert(format!("!/{base}"));
-                            to_remove.insert(format!("!/{base}/*"));
-                        }
+/// Variable d'environnement posée pendant l'exécution du hook on_save,
+/// pour qu'une instance lancée par le hook ne relance pas le hook elle-même.
+const HOOK_ENV: &str = "GITIGNORE_TUI_IN_HOOK";
 
-                        // On gère aussi les patterns globaux "*", "/*", "!*", "/*!*"
-                        to_remove.insert("*".to_string());
-                        to_remove.insert("/*".to_string());
-                        to_remove.insert("!*".to_string());
-                        to_remove.insert("!/*".to_string());
-
-                        // On garde les lignes qui ne nous concernent pas
-                        lines.retain(|line| {
-                            let trimmed = line.trim();
-                            if trimmed.is_empty() || trimmed.starts_with('#') {
-                                return true;
-                            }
-                            !to_remove.contains(trimmed)
-                        });
-
-                        // --- CAS PARTICULIER : NOEUD RACINE "/" ---
-                        // On commence par gérer le noeud racine s'il est marqué
-                        if !nodes.is_empty() {
-                            let root_node = &nodes[0];
-                            if root_node.mark {
-                                // Le noeud racine est marqué -> on veut "/*" en premier
-                                lines.insert(0, "/*".to_string());
-                            }
-                        }
+/// Lance la commande on_save avec le chemin du .gitignore en dernier argument,
+/// puis affiche sa sortie et son code de retour.
+/// La commande est découpée sur les espaces (pas d'interprétation shell).
+fn run_on_save_hook(command: &str, gitignore_path: &Path) -> Result<()> {
+    if env::var_os(HOOK_ENV).is_some() {
+        println!("Skipping on_save hook: already running from an on_save hook.");
+        return Ok(());
+    }
 
-                        // On ajoute les nouvelles règles selon mode / cpt_exception
-                        for n in &nodes {
-                            let rel = n.path.strip_prefix(root).unwrap_or(&n.path);
-                            let mut entry = rel.to_string_lossy().to_string();
-                            entry = entry.replace("\\", "/");
-
-                            // Sauter le noeud racine, déjà traité ci-dessus
-                            if entry.is_empty() {
-                                continue;
-                            }
-
-                            // Pour les autres entrées : on écrit toujours un "/" devant
-                            match n.mode {
-                                Mode::N => {
-                                    // Répertoire "normal" mais qui contient au moins une exception
-                                    // -> on veut :
-                                    // !/entry
-                                    // /entry/*
-                                    if n.is_dir && n.cpt_exception > 0 {
-                                        lines.push(format!("!/{entry}"));
-                                        lines.push(format!("/{entry}/*"));
-                                    }
-                                }
-                                Mode::C => {
-                                    // Règle d'ignore classique
-                                    // - si c'est un dossier avec des exceptions -> /entry/*
-                                    // - sinon -> /entry
-                                    if n.is_dir && n.cpt_exception > 0 {
-                                        lines.push(format!("/{entry}/*"));
-                                    } else {
-                                        lines.push(format!("/{entry}"));
-                                    }
-                                }
-                                Mode::E => {
-                                    // Exception explicite
-                                    lines.push(format!("!/{entry}"));
-                                }
-                            }
-                        }
+    let mut parts = command.split_whitespace();
+    let Some(program) = parts.next() else {
+        return Ok(());
+    };
 
-                        let mut new_content = String::new();
-                        for (i, line) in lines.iter().enumerate() {
-                            if i > 0 {
-                                new_content.push('\n');
-                            }
-                            new_content.push_str(line);
-                        }
-                        if !new_content.is_empty() && !new_content.ends_with('\n') {
-                            new_content.push('\n');
-                        }
+    println!("\nRunning on_save hook: {} {}", command, gitignore_path.display());
 
-                        fs::write(&gitignore_path, new_content)
-                            .context("Writing .gitignore")?;
-                        break;
-                    }
-                    _ => {}
-                }
+    let output = match Command::new(program)
+        .args(parts)
+        .arg(gitignore_path)
+        .env(HOOK_ENV, "1")
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("Warning: on_save command '{}' not found.", program);
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(e).context(format!("Failed to execute on_save command '{}'", program));
+        }
+    };
 
-                // Après modification, on recalcule les visibles et on corrige le curseur / scroll
-                visible = build_visible_indices(&nodes);
-                if visible.is_empty() {
-                    cursor_pos = 0;
-                    scroll_offset = 0;
-                } else {
-                    if let Some(target_idx) = jump_to_idx {
-                        if let Some(new_row) = visible.iter().position(|&i| i == target_idx) {
-                            cursor_pos = new_row;
-                        }
-                    }
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
 
-                    if cursor_pos >= visible.len() {
-                        cursor_pos = visible.len().saturating_sub(1);
-                    }
+    if output.status.success() {
+        println!("on_save hook finished successfully.");
+    } else {
+        eprintln!("Warning: on_save hook failed ({}).", output.status);
+    }
+    Ok(())
+}
 
-                    let max_scroll = visible.len().saturating_sub(available_height);
-                    if cursor_pos < scroll_offset {
-                        scroll_offset = cursor_pos;
-                    } else if cursor_pos >= scroll_offset + available_height {
-                        scroll_offset = cursor_pos + 1 - available_height;
-                    }
-                    scroll_offset = scroll_offset.min(max_scroll);
+/// Normalise un chemin donné par l'utilisateur en chemin relatif "a/b"
+/// (sans "./" ni "/" au début, sans "/" final, séparateurs "/").
+/// "/" ou "." désignent la racine et donnent une chaîne vide.
+fn normalize_entry(path: &str) -> String {
+    let mut entry = path.replace('\\', "/");
+    while let Some(rest) = entry.strip_prefix("./") {
+        entry = rest.to_string();
+    }
+    if entry == "." {
+        entry.clear();
+    }
+    entry.trim_matches('/').to_string()
+}
+
+/// Toutes les formes sous lesquelles une entrée peut apparaître dans le .gitignore :
+/// avec ou sans "/" devant, en règle classique ou en exception, avec ou sans "/*".
+/// Une entrée vide (le noeud racine) correspond aux patterns globaux "*", "/*", "!*", "!/*".
+fn rule_variants(entry: &str) -> Vec<String> {
+    if entry.is_empty() {
+        return vec![
+            "*".to_string(),
+            "/*".to_string(),
+            "!*".to_string(),
+            "!/*".to_string(),
+        ];
+    }
+
+    [
+        // Anciennes formes sans "/" devant
+        entry.to_string(),
+        format!("{entry}/*"),
+        format!("!{entry}"),
+        format!("!{entry}/*"),
+        // Nouvelles formes avec "/" devant
+        format!("/{entry}"),
+        format!("/{entry}/*"),
+        format!("!/{entry}"),
+        format!("!/{entry}/*"),
+    ]
+    .iter()
+    .map(|line| escape_trailing_spaces(line))
+    .collect()
+}
+
+/// Construit l'ensemble des lignes à retirer du .gitignore pour une liste d'entrées
+/// (chemins relatifs normalisés).
+fn build_to_remove<I, S>(entries: I) -> HashSet<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut to_remove = HashSet::new();
+    for entry in entries {
+        to_remove.extend(rule_variants(entry.as_ref()));
+    }
+    to_remove
+}
+
+/// Garde les lignes du .gitignore qui ne sont pas dans `to_remove`
+/// (les commentaires et lignes vides sont toujours conservés).
+fn retain_unmanaged_lines(content: &str, to_remove: &HashSet<String>) -> Vec<String> {
+    content
+        .lines()
+        .filter(|line| {
+            let trimmed = gitignore_line(line);
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                return true;
+            }
+            !to_remove.contains(trimmed)
+        })
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Recolle les lignes avec un "\n" final (rien si la liste est vide).
+fn join_lines(lines: &[String]) -> String {
+    let mut content = lines.join("\n");
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content
+}
+
+/// Sous-commande `rm` : retire du .gitignore (ou, avec --exclude, de .git/info/exclude)
+/// les règles correspondant aux chemins donnés, sans passer par l'interface. Comme une
+/// sauvegarde, elle prend le verrou de session et garde une copie .bak (sauf --no-backup).
+fn remove_paths(root: &Path, args: &[String]) -> Result<()> {
+    let (flags, paths): (Vec<&String>, Vec<&String>) = args.iter().partition(|a| a.starts_with("--"));
+    let (mut exclude, mut backup) = (false, true);
+    for flag in flags {
+        match flag.as_str() {
+            "--exclude" => exclude = true,
+            "--no-backup" => backup = false,
+            other => bail!("Unknown option for rm: {}", other),
+        }
+    }
+    if paths.is_empty() {
+        bail!("Usage: git-ignore rm [--exclude] [--no-backup] <path>...");
+    }
+
+    let exclude_file = if exclude { Some(git_exclude_file(root)?) } else { None };
+    let label = primary_label(exclude_file.as_deref());
+    let path = primary_file(root, exclude_file.as_deref());
+    if !path.exists() {
+        println!("No {} in '{}', nothing to remove.", label, root.display());
+        return Ok(());
+    }
+
+    let _lock = LockGuard::acquire(root)?;
+    let content = fs::read_to_string(&path).context(format!("Reading existing {}", label))?;
+
+    let to_remove = build_to_remove(paths.iter().map(|p| normalize_entry(p)));
+    let lines = retain_unmanaged_lines(&content, &to_remove);
+    let removed = content.lines().count() - lines.len();
+
+    if removed == 0 {
+        println!("No matching rule found in {}.", label);
+        return Ok(());
+    }
+
+    let new_content = join_lines(&lines);
+    if backup {
+        if let Err(e) = backup_gitignore(&path, &new_content) {
+            eprintln!("Warning: could not back up {}: {:#}", path.display(), e);
+        }
+    }
+    fs::write(&path, new_content).context(format!("Writing {}", label))?;
+    println!("Removed {} rule(s) from {}.", removed, label);
+    Ok(())
+}
+
+/// Options de génération du .gitignore
+#[derive(Debug, Clone, Default)]
+struct GenerateOptions {
+    /// Regroupe les fichiers d'un répertoire partageant une extension en "/dir/*.ext"
+    collapse_extensions: bool,
+    /// Lignes libres ajoutées avec "+" ou Alt+M, écrites après les règles des nodes
+    extra_rules: Vec<String>,
+    /// Voir ScanOptions::exclude_file
+    exclude_file: Option<PathBuf>,
+    /// --template : lignes à ajouter au fichier existant (voir template_seed)
+    seed: String,
+}
+
+/// Regroupe les fichiers ignorés individuellement (mode C) d'un même répertoire qui
+/// partagent une extension en une seule règle "/dir/*.ext".
+/// On ne regroupe que si TOUS les fichiers de cette extension dans le répertoire
+/// (enfants directs) sont ignorés, et qu'au moins deux ont leur propre règle.
+/// Renvoie, pour chaque node concerné, la règle à émettre à sa place :
+/// Some(règle) pour le premier fichier du groupe, None pour les suivants.
+fn collapse_extension_rules(nodes: &[Node]) -> HashMap<usize, Option<String>> {
+    let mut collapsed = HashMap::new();
+
+    for (i, dir) in nodes.iter().enumerate() {
+        if !dir.is_dir {
+            continue;
+        }
+
+        // extension -> (indices des fichiers en mode C, tous ignorés ?)
+        let mut groups: Vec<(String, Vec<usize>, bool)> = Vec::new();
+        let mut j = i + 1;
+        while j < nodes.len() && nodes[j].depth > dir.depth {
+            let n = &nodes[j];
+            j += 1;
+            if n.depth != dir.depth + 1 || n.is_dir {
+                continue;
+            }
+            let Some(ext) = n.path.extension().map(|e| e.to_string_lossy().to_string()) else {
+                continue;
+            };
+
+            let pos = match groups.iter().position(|(e, _, _)| *e == ext) {
+                Some(pos) => pos,
+                None => {
+                    groups.push((ext, Vec::new(), true));
+                    groups.len() - 1
                 }
+            };
+            let group = &mut groups[pos];
+            if n.override_line.is_some() || n.target.is_some() {
+                // Sa ligne saisie à la main doit rester telle quelle,
+                // ou elle est écrite dans un autre .gitignore
+                group.2 = false;
+            } else if n.mode == Mode::C && n.mark && !n.generic_mark {
+                group.1.push(j - 1);
+            } else if !n.mark {
+                group.2 = false;
+            }
+        }
 
-                render(&nodes, &visible, cursor_pos, scroll_offset)?;
+        let dir_entry = &dir.rel;
+        for (ext, files, all_ignored) in groups {
+            if !all_ignored || files.len() < 2 {
+                continue;
             }
-            Event::Resize(_, _) => {
-                visible = build_visible_indices(&nodes);
-                render(&nodes, &visible, cursor_pos, scroll_offset)?;
+            let rule = if dir_entry.is_empty() {
+                format!("/*.{ext}")
+            } else {
+                format!("/{dir_entry}/*.{ext}")
+            };
+            collapsed.insert(files[0], Some(rule));
+            for &f in &files[1..] {
+                collapsed.insert(f, None);
             }
-            _ => {}
         }
     }
 
-    execute!(stdout(), cursor::Show, terminal::LeaveAlternateScreen)?;
-    disable_raw_mode()?;
+    collapsed
+}
 
-    println!(
-        "Selection completed. The `.gitignore` file has been updated in '{}'.",
-        root_path
-    );
-    
-    // Si l'option -j est activée, on désindexe les fichiers ignorés
-    if use_jj {
-        println!("\nChecking tracked files with jj...");
-        if let Err(e) = untrack_ignored_files(root) {
-            eprintln!("Error while untracking files: {}", e);
+/// Génère le contenu du .gitignore à partir de l'état des nodes : les règles déjà
+/// présentes restent à leur place, dans leur section, celles qui ont disparu sont
+/// retirées, et les nouvelles sont rangées par place_rules. Les commentaires et les
+/// lignes que l'outil ne gère pas sont conservés.
+fn generate_gitignore(nodes: &[Node], existing: &str, opts: &GenerateOptions) -> String {
+    // Les lignes d'un modèle sont traitées comme si elles étaient déjà dans le fichier
+    let existing = &merge_seed(existing, &opts.seed);
+    let originals = original_rule_lines(existing);
+    let mut rules = Vec::new();
+
+    // --- CAS PARTICULIER : NOEUD RACINE "/" ---
+    // Le noeud racine marqué donne "/*", toujours en tête
+    let root_marked = nodes.first().is_some_and(|n| n.mark);
+    if root_marked {
+        rules.extend(keep_original("/*".to_string(), true, &originals));
+    }
+
+    let collapsed = if opts.collapse_extensions {
+        collapse_extension_rules(nodes)
+    } else {
+        HashMap::new()
+    };
+
+    // On ajoute les nouvelles règles selon mode / cpt_exception
+    for (i, n) in nodes.iter().enumerate() {
+        let entry = &n.rel;
+
+        // Sauter le noeud racine, déjà traité ci-dessus,
+        // et les nodes dont la règle va dans le .gitignore d'un sous-répertoire
+        if entry.is_empty() || n.target.is_some() {
+            continue;
         }
+
+        // Ligne saisie à la main : écrite telle quelle
+        if let Some(line) = &n.override_line {
+            rules.push(line.clone());
+            continue;
+        }
+
+        // Fichier regroupé dans une règle "/dir/*.ext"
+        if let Some(rule) = collapsed.get(&i) {
+            if let Some(rule) = rule {
+                rules.push(rule.clone());
+            }
+            continue;
+        }
+
+        rules.extend(node_rule_lines(n, entry).into_iter().flat_map(|l| keep_original(l, n.is_dir, &originals)));
+    }
+    let rules = dedup_rules(&[], rules);
+
+    let managed = managed_lines(nodes);
+    let mut lines = place_rules(existing, &managed, &rules, root_marked).unwrap_or_else(|| {
+        // Ordre du fichier incompatible avec celui des règles : on réécrit comme avant,
+        // les règles à la suite des lignes conservées ("/*" en tête)
+        let mut lines = retain_unmanaged_lines(existing, &managed);
+        let (root, rest) = rules.split_at(usize::from(root_marked));
+        lines.splice(0..0, root.iter().cloned());
+        lines.extend_from_slice(rest);
+        lines
+    });
+
+    // Lignes libres, après les règles des nodes
+    let extra = dedup_rules(&lines, opts.extra_rules.clone());
+    lines.extend(extra);
+
+    join_lines(&lines)
+}
+
+/// Modèles de .gitignore fournis avec l'outil (github/gitignore), pour --template
+const BUNDLED_TEMPLATES: &[(&str, &str)] = &[
+    ("Go", include_str!("../templates/Go.gitignore")),
+    ("Java", include_str!("../templates/Java.gitignore")),
+    ("Node", include_str!("../templates/Node.gitignore")),
+    ("Python", include_str!("../templates/Python.gitignore")),
+    ("Rust", include_str!("../templates/Rust.gitignore")),
+];
+
+/// --template : les règles du modèle `name` (sans casse) que `existing` ne contient
+/// pas encore, sous une ligne de titre. "" si elles y sont toutes.
+fn template_seed(name: &str, existing: &str) -> Result<String> {
+    let Some((name, template)) = BUNDLED_TEMPLATES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) else {
+        let names: Vec<&str> = BUNDLED_TEMPLATES.iter().map(|(n, _)| *n).collect();
+        bail!("Unknown template '{}' (available: {})", name, names.join(", "));
+    };
+    // Déjà présente (ou déjà vue dans le modèle) : la même ligne, ou une règle qui ignore au moins la même chose pour
+    // git ("/target" couvre "/target/", mais pas "target/" qui vaut à toute profondeur)
+    let mut present: HashSet<&str> = existing.lines().map(gitignore_line).collect();
+    let rules = parse_gitignore_content(existing);
+    let same_rule = |line: &str| {
+        parse_gitignore_content(line).first().is_some_and(|r| {
+            rules.iter().any(|e| {
+                (e.mode, &e.pattern) == (r.mode, &r.pattern)
+                    && is_anchored(&e.line) == is_anchored(&r.line)
+                    && (r.dir_only || !e.dir_only)
+            })
+        })
+    };
+    let lines: Vec<&str> = template
+        .lines()
+        .map(gitignore_line)
+        .filter(|l| !l.is_empty() && !l.starts_with('#') && !same_rule(l) && present.insert(l))
+        .collect();
+    if lines.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(format!("# {} template\n{}\n", name, lines.join("\n")))
+}
+
+/// Vrai si git n'applique la ligne qu'à partir du répertoire de son .gitignore : elle
+/// contient un "/" ailleurs qu'à la fin ("/target", "docs/build"), sinon elle vaut à
+/// toute profondeur ("target/", "*.log")
+fn is_anchored(line: &str) -> bool {
+    let line = gitignore_line(line);
+    let line = line.strip_prefix('!').unwrap_or(line);
+    line.trim_end_matches('/').contains('/')
+}
+
+/// Contenu existant suivi des lignes de --template, séparées par une ligne vide
+fn merge_seed(existing: &str, seed: &str) -> String {
+    let kept = existing.trim_end_matches('\n');
+    if seed.is_empty() {
+        existing.to_string()
+    } else if kept.is_empty() {
+        seed.to_string()
+    } else {
+        format!("{}\n\n{}", kept, seed)
+    }
+}
+
+/// Titre de la section où vont les nouvelles règles sans voisine dans le fichier
+const ADDED_SECTION: &str = "# Added by gitignore-tui";
+
+/// Chemin visé par une ligne de règle : "!/logs/*" -> "logs"
+fn rule_path(line: &str) -> &str {
+    let line = line.strip_prefix('!').unwrap_or(line);
+    let line = line.strip_prefix('/').unwrap_or(line);
+    let line = line.strip_suffix("/*").unwrap_or(line);
+    line.strip_suffix('/').unwrap_or(line)
+}
+
+/// Deux règles dont l'ordre compte : elles portent sur le même chemin,
+/// ou l'une sur un parent de l'autre ("/*" sur tout).
+fn rules_overlap(a: &str, b: &str) -> bool {
+    let (a, b) = (rule_path(a), rule_path(b));
+    a.is_empty()
+        || b.is_empty()
+        || a == b
+        || b.strip_prefix(a).is_some_and(|rest| rest.starts_with('/'))
+        || a.strip_prefix(b).is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn is_rule_line(line: &str) -> bool {
+    let trimmed = gitignore_line(line);
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+/// Proximité entre une nouvelle règle et une ligne du fichier (0 : aucune) :
+/// chemins qui partagent leurs premiers répertoires, titre de section qui nomme l'un
+/// d'eux ("# Logs" pour /logs/old), ou même extension ("*.log" pour /debug.log).
+fn relatedness(rule: &str, line: &str) -> usize {
+    let trimmed = gitignore_line(line);
+    let path = rule_path(rule);
+    if let Some(title) = trimmed.strip_prefix('#') {
+        let words: Vec<String> = title
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let named = path.split('/').map(str::to_lowercase).any(|part| {
+            words.iter().any(|w| *w == part || *w == format!("{part}s") || part == format!("{w}s"))
+        });
+        return if named { 2 } else { 0 };
+    }
+    if trimmed.is_empty() {
+        return 0;
+    }
+
+    let other = rule_path(trimmed);
+    let common = path
+        .split('/')
+        .zip(other.split('/'))
+        .take_while(|(a, b)| a == b && !a.is_empty())
+        .count();
+    if common > 0 {
+        return 2 * common;
+    }
+    let extension = |p: &str| {
+        let name = p.rsplit('/').next().unwrap_or(p);
+        name.rsplit_once('.')
+            .map(|(_, ext)| ext.to_string())
+            .filter(|ext| !ext.is_empty() && !ext.contains(['*', '?', '[']))
+    };
+    match (extension(path), extension(other)) {
+        (Some(a), Some(b)) if a == b => 1,
+        _ => 0,
+    }
+}
+
+/// Fin de la section qui contient la ligne `k` : après ses règles, avant la ligne
+/// vide ou le commentaire suivant. Pour un titre, après les règles qui le suivent.
+fn section_end(lines: &[(String, Option<usize>)], k: usize) -> usize {
+    let mut j = k + 1;
+    if !is_rule_line(&lines[k].0) {
+        while j < lines.len() && gitignore_line(&lines[j].0).starts_with('#') {
+            j += 1;
+        }
+    }
+    while j < lines.len() && is_rule_line(&lines[j].0) {
+        j += 1;
+    }
+    j
+}
+
+/// Range les règles générées (`rules`, dans leur ordre) dans le fichier existant :
+/// - une règle déjà présente garde sa ligne et sa place ;
+/// - une ligne gérée par l'outil (`managed`) qui n'est plus générée est retirée ;
+/// - une nouvelle règle va à la fin de la section de la ligne la plus proche
+///   (voir relatedness), sinon dans la section ADDED_SECTION, créée au besoin si le
+///   fichier a des commentaires, sinon à la fin. Avec `root`, rules[0] ("/*") va en tête.
+///
+/// Le sens du fichier ne doit pas changer : une règle reste après celles qui la
+/// précèdent dans `rules` sur un même chemin, et après les lignes conservées de sens
+/// contraire (une négation "!*.log" après un nouveau "/debug.log" le ré-inclurait).
+/// Si c'est impossible (règles conservées dans un autre ordre), renvoie None.
+fn place_rules(existing: &str, managed: &HashSet<String>, rules: &[String], root: bool) -> Option<Vec<String>> {
+    let index: HashMap<&str, usize> = rules.iter().enumerate().map(|(i, r)| (r.as_str(), i)).collect();
+    let mut placed = vec![false; rules.len()];
+
+    // Lignes du fichier, avec l'indice de la règle générée qu'elles portent
+    let mut lines: Vec<(String, Option<usize>)> = Vec::new();
+    for line in existing.lines() {
+        let trimmed = gitignore_line(line);
+        match index.get(trimmed) {
+            Some(&g) if !placed[g] => {
+                placed[g] = true;
+                lines.push((line.to_string(), Some(g)));
+            }
+            // Règle retirée, ou doublon d'une règle déjà gardée
+            Some(_) => {}
+            None if is_rule_line(line) && managed.contains(trimmed) => {}
+            None => lines.push((line.to_string(), None)),
+        }
+    }
+
+    let kept: Vec<usize> = lines.iter().filter_map(|(_, g)| *g).collect();
+    for (i, &a) in kept.iter().enumerate() {
+        if kept[i + 1..].iter().any(|&b| b < a && rules_overlap(&rules[a], &rules[b])) {
+            return None;
+        }
+    }
+
+    let has_sections = lines.iter().any(|(l, _)| gitignore_line(l).starts_with('#'));
+    for g in (0..rules.len()).filter(|&g| !placed[g]) {
+        let rule = &rules[g];
+        if root && g == 0 {
+            lines.insert(0, (rule.clone(), Some(g)));
+            continue;
+        }
+
+        // Intervalle où la règle garde son sens : après `lower`, au plus tard en `upper`
+        let negated = rule.starts_with('!');
+        let mut lower = 0;
+        let mut upper = lines.len();
+        for (k, (line, other)) in lines.iter().enumerate() {
+            match other {
+                Some(h) if *h < g && rules_overlap(rule, &rules[*h]) => lower = k + 1,
+                Some(h) if *h > g && rules_overlap(rule, &rules[*h]) => upper = upper.min(k),
+                None if is_rule_line(line) && gitignore_line(line).starts_with('!') != negated => lower = k + 1,
+                _ => {}
+            }
+        }
+        if lower > upper {
+            return None;
+        }
+
+        let nearest = lines
+            .iter()
+            .enumerate()
+            .map(|(k, (line, _))| (relatedness(rule, line), k))
+            .filter(|&(score, _)| score > 0)
+            .max();
+        let section = match nearest {
+            Some((_, k)) => Some(k),
+            None => lines.iter().position(|(l, _)| gitignore_line(l) == ADDED_SECTION),
+        };
+        let pos = match section {
+            Some(k) => section_end(&lines, k),
+            None if has_sections && upper == lines.len() => {
+                if lines.last().is_some_and(|(l, _)| !l.trim().is_empty()) {
+                    lines.push((String::new(), None));
+                }
+                lines.push((ADDED_SECTION.to_string(), None));
+                upper = lines.len();
+                upper
+            }
+            None => lines.len(),
+        };
+        let pos = if (lower..=upper).contains(&pos) { pos } else { lower };
+        lines.insert(pos, (rule.clone(), Some(g)));
+    }
+
+    Some(lines.into_iter().map(|(line, _)| line).collect())
+}
+
+/// Forme qu'aurait la règle si l'outil l'écrivait (ancrée par "/", voir node_rule_lines),
+/// suivie d'un "/" pour une règle de répertoire (voir keep_original)
+fn canonical_rule_line(rule: &Rule) -> String {
+    let bang = if rule.mode == Mode::E { "!" } else { "" };
+    if rule.pattern == ROOT_PATTERN {
+        return format!("{bang}/*");
+    }
+    // "dir/*" reste "dir/*" : c'est la forme générée pour un répertoire avec exceptions
+    let suffix = if rule.line.ends_with("/*") {
+        "/*"
+    } else if rule.dir_only {
+        "/"
+    } else {
+        ""
+    };
+    escape_trailing_spaces(&format!("{bang}/{}{suffix}", rule.pattern))
+}
+
+/// Règles du .gitignore existant ("target", "/target", "build/" ...),
+/// regroupées par leur forme générée (voir canonical_rule_line), avec leur rang dans le fichier.
+fn original_rule_lines(existing: &str) -> HashMap<String, Vec<(usize, String)>> {
+    let mut originals: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+    for (pos, rule) in parse_gitignore_content(existing).into_iter().enumerate() {
+        originals.entry(canonical_rule_line(&rule)).or_default().push((pos, rule.line));
+    }
+    originals
+}
+
+/// Une règle déjà présente et inchangée est réécrite sous sa forme d'origine,
+/// pour ne changer ni son ancrage ni le diff ; les nouvelles prennent la forme de l'outil.
+/// Une règle de répertoire ("build/") n'est reprise que pour un répertoire. Toutes les
+/// formes présentes sont gardées, dans l'ordre du fichier : "/target" et "target/"
+/// n'ignorent pas la même chose.
+fn keep_original(line: String, is_dir: bool, originals: &HashMap<String, Vec<(usize, String)>>) -> Vec<String> {
+    let dir_forms = is_dir.then(|| originals.get(&format!("{line}/"))).flatten();
+    let mut kept: Vec<&(usize, String)> = dir_forms.into_iter().chain(originals.get(&line)).flatten().collect();
+    if kept.is_empty() {
+        return vec![line];
+    }
+    kept.sort();
+    kept.into_iter().map(|(_, l)| l.clone()).collect()
+}
+
+/// Lignes de .gitignore que la génération réécrit : les variantes (avec et sans "/")
+/// des chemins de l'arbre, le noeud racine donnant les patterns globaux "*", "/*", ...,
+/// plus les lignes saisies à la main, réécrites à leur place.
+fn managed_lines(nodes: &[Node]) -> HashSet<String> {
+    let mut to_remove = build_to_remove(nodes.iter().map(|n| n.rel.as_str()));
+    to_remove.extend(nodes.iter().filter_map(|n| n.override_line.clone()));
+    to_remove
+}
+
+/// Règles du .gitignore existant que l'outil ne gère pas et recopie telles quelles
+/// à la sauvegarde, avec la raison pour laquelle elles lui échappent.
+fn passthrough_rules(nodes: &[Node], existing: &str) -> Vec<(String, &'static str)> {
+    retain_unmanaged_lines(existing, &managed_lines(nodes))
+        .into_iter()
+        .filter(|line| {
+            let trimmed = gitignore_line(line);
+            !trimmed.is_empty() && !trimmed.starts_with('#')
+        })
+        .map(|line| {
+            let trimmed = gitignore_line(&line);
+            let is_wildcard = trimmed.contains('*') || trimmed.contains('?') || trimmed.contains('[');
+            // Même classement que parse_gitignore_content / build_generic_gitignore
+            let reason = if !parse_gitignore_content(trimmed).is_empty() {
+                "no such path in the tree"
+            } else if is_wildcard && trimmed.starts_with('!') {
+                "wildcard exception, not applied in the tree"
+            } else if is_wildcard && !trimmed.ends_with("/*") {
+                "wildcard pattern, matches shown as [o]"
+            } else if is_wildcard {
+                "wildcard under a directory, not applied in the tree"
+            } else {
+                "matches nothing"
+            };
+            (trimmed.to_string(), reason)
+        })
+        .collect()
+}
+
+/// (fichiers, fichiers marqués) de l'arbre, pour la couverture du pied de page
+fn count_files(nodes: &[Node]) -> (usize, usize) {
+    nodes
+        .iter()
+        .filter(|n| !n.is_dir)
+        .fold((0, 0), |(total, marked), n| (total + 1, marked + usize::from(n.mark)))
+}
+
+/// Taille du .gitignore sur disque, pour l'indicateur du pied de page
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct RuleCounts {
+    /// Lignes hors commentaires et lignes vides
+    lines: usize,
+    /// Règles reconnues par parse_gitignore_content
+    rules: usize,
+    /// Lignes recopiées telles quelles à la sauvegarde (voir passthrough_rules)
+    passthrough: usize,
+}
+
+fn count_rules(nodes: &[Node], content: &str) -> RuleCounts {
+    RuleCounts {
+        lines: content
+            .lines()
+            .map(gitignore_line)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .count(),
+        rules: parse_gitignore_content(content).len(),
+        passthrough: passthrough_rules(nodes, content).len(),
+    }
+}
+
+/// Lignes de `added` à écrire après `kept`, sans les règles en double : une copie
+/// n'est retirée que si aucune règle de sens contraire ne se trouve depuis la précédente
+/// ("*.log", "!keep.log", "*.log" ré-ignore keep.log, la 2e copie compte). Les lignes de
+/// `kept` (celles du fichier) ne sont jamais retirées ; les commentaires et lignes vides,
+/// qui peuvent légitimement se répéter, sont conservés.
+fn dedup_rules(kept: &[String], added: Vec<String>) -> Vec<String> {
+    // Nombre de (règles, exceptions) vues avant la dernière copie de chaque règle
+    let mut seen: HashMap<String, (usize, usize)> = HashMap::new();
+    let (mut rules, mut negations) = (0, 0);
+    let mut out = Vec::new();
+    let lines = kept.iter().map(|l| (l, false)).chain(added.iter().map(|l| (l, true)));
+    for (line, is_added) in lines {
+        let trimmed = gitignore_line(line);
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            let negation = trimmed.starts_with('!');
+            let repeated = seen
+                .get(trimmed)
+                .is_some_and(|&(r, n)| if negation { r == rules } else { n == negations });
+            if repeated && is_added {
+                continue;
+            }
+            seen.insert(trimmed.to_string(), (rules, negations));
+            if negation {
+                negations += 1;
+            } else {
+                rules += 1;
+            }
+        }
+        if is_added {
+            out.push(line.clone());
+        }
+    }
+    out
+}
+
+/// Lignes générées pour un node (hors racine) selon mode / cpt_exception.
+/// `entry` est son chemin relatif normalisé.
+fn node_rule_lines(n: &Node, entry: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    // On écrit toujours un "/" devant
+    match n.mode {
+        Mode::N => {
+            // Répertoire "normal" mais qui contient au moins une exception
+            // -> on veut :
+            // !/entry
+            // /entry/*
+            if n.is_dir && n.cpt_exception > 0 {
+                lines.push(format!("!/{entry}"));
+                lines.push(format!("/{entry}/*"));
+            }
+        }
+        Mode::C => {
+            // Règle d'ignore classique
+            // - si c'est un dossier avec des exceptions -> /entry/*
+            // - sinon -> /entry
+            if n.is_dir && n.cpt_exception > 0 {
+                lines.push(format!("/{entry}/*"));
+            } else {
+                lines.push(format!("/{entry}"));
+            }
+        }
+        Mode::E => {
+            // Exception explicite
+            lines.push(format!("!/{entry}"));
+        }
+    }
+
+    // Un nom qui finit par un espace doit l'échapper pour que git le garde
+    lines.iter().map(|line| escape_trailing_spaces(line)).collect()
+}
+
+/// Contenu actuel du .gitignore de root (vide s'il n'existe pas)
+fn read_gitignore(root: &Path) -> Result<String> {
+    read_ignore_file(&root.join(".gitignore"))
+}
+
+/// Contenu d'un fichier d'ignore ("" s'il n'existe pas)
+fn read_ignore_file(path: &Path) -> Result<String> {
+    if path.exists() {
+        fs::read_to_string(path).context(format!("Reading existing {:?}", path))
+    } else {
+        Ok(String::new())
+    }
+}
+
+/// Fichier d'ignore édité à la racine : le .gitignore, ou celui de --exclude
+fn primary_file(root: &Path, exclude_file: Option<&Path>) -> PathBuf {
+    exclude_file.map_or_else(|| root.join(".gitignore"), Path::to_path_buf)
+}
+
+/// Contenu du fichier d'ignore édité (voir primary_file)
+fn read_primary(root: &Path, opts: &ScanOptions) -> Result<String> {
+    read_ignore_file(&primary_file(root, opts.exclude_file.as_deref()))
+}
+
+/// Nom du fichier édité, pour les messages
+fn primary_label(exclude_file: Option<&Path>) -> &'static str {
+    if exclude_file.is_some() { ".git/info/exclude" } else { ".gitignore" }
+}
+
+/// Contenu du .gitignore racine (ou du fichier de --exclude) tel que la sauvegarde l'écrirait
+fn build_gitignore_content(nodes: &[Node], root: &Path, opts: &GenerateOptions) -> Result<String> {
+    let path = primary_file(root, opts.exclude_file.as_deref());
+    Ok(generate_gitignore(nodes, &read_ignore_file(&path)?, opts))
+}
+
+/// Fichiers que la sauvegarde écrit : le .gitignore racine, puis ceux des
+/// sous-répertoires choisis comme cible (touche T).
+fn gitignore_files(nodes: &[Node], root: &Path, opts: &GenerateOptions) -> Result<Vec<(PathBuf, String)>> {
+    let path = primary_file(root, opts.exclude_file.as_deref());
+    let mut files = vec![(path, build_gitignore_content(nodes, root, opts)?)];
+
+    let mut targets: Vec<&PathBuf> = nodes.iter().filter_map(|n| n.target.as_ref()).collect();
+    targets.sort();
+    targets.dedup();
+    for dir in targets {
+        let existing = read_gitignore(dir)?;
+        files.push((dir.join(".gitignore"), generate_nested_gitignore(nodes, dir, &existing)));
+    }
+    Ok(files)
+}
+
+/// Écrit le .gitignore à partir de l'état des nodes.
+/// Avec `backup`, l'ancienne version d'un fichier qui change est d'abord copiée en
+/// `.gitignore.bak` à côté de lui ; un échec de copie n'empêche pas la sauvegarde et
+/// revient dans les avertissements renvoyés.
+fn save_gitignore(nodes: &[Node], root: &Path, opts: &GenerateOptions, backup: bool) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    for (path, content) in gitignore_files(nodes, root, opts)? {
+        if backup {
+            if let Err(e) = backup_gitignore(&path, &content) {
+                warnings.push(format!("could not back up {}: {:#}", path.display(), e));
+            }
+        }
+        debug!("writing {:?}:\n{}", path, content);
+        // .git/info n'existe pas forcément
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!("Creating {:?}", dir))?;
+        }
+        fs::write(&path, content).context(format!("Writing {:?}", path))?;
+    }
+    Ok(warnings)
+}
+
+/// Copie `path` en `<nom>.bak` (`.gitignore.bak`) s'il existe et que `content` va le modifier
+fn backup_gitignore(path: &Path, content: &str) -> Result<()> {
+    match fs::read_to_string(path) {
+        Ok(current) if current != content => {
+            let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            let backup = path.with_file_name(format!("{}.bak", name));
+            fs::write(&backup, current).context(format!("Writing {:?}", backup))
+        }
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).context(format!("Reading {:?}", path)),
+    }
+}
+
+/// --dry-run : le contenu que `s` aurait écrit, sur stdout, entre deux bannières par fichier
+fn print_dry_run(root: &Path, files: &[(PathBuf, String)]) {
+    for (path, content) in files {
+        let name = path.strip_prefix(root).unwrap_or(path).display();
+        println!("--- begin {} ---", name);
+        print!("{}", content);
+        println!("--- end {} ---", name);
+    }
+}
+
+/// Contenu du .gitignore du sous-répertoire `dir` : les règles des nodes qui l'ont
+/// pour cible, avec des patterns relatifs à `dir`, ajoutées aux lignes existantes
+/// qui ne les concernent pas.
+fn generate_nested_gitignore(nodes: &[Node], dir: &Path, existing: &str) -> String {
+    let targeted: Vec<(&Node, String)> = nodes
+        .iter()
+        .filter(|n| n.target.as_deref() == Some(dir))
+        .map(|n| {
+            let rel = n.path.strip_prefix(dir).unwrap_or(&n.path);
+            (n, rel.to_string_lossy().replace("\\", "/"))
+        })
+        .collect();
+
+    let mut to_remove = build_to_remove(targeted.iter().map(|(_, entry)| entry.as_str()));
+    to_remove.extend(targeted.iter().filter_map(|(n, _)| n.override_line.clone()));
+
+    let originals = original_rule_lines(existing);
+    let mut rules = Vec::new();
+    for (n, entry) in &targeted {
+        match &n.override_line {
+            Some(line) => rules.push(line.clone()),
+            None => rules.extend(
+                node_rule_lines(n, entry).into_iter().flat_map(|l| keep_original(l, n.is_dir, &originals)),
+            ),
+        }
+    }
+    let rules = dedup_rules(&[], rules);
+
+    // Rangées comme dans le .gitignore racine (voir place_rules)
+    let lines = place_rules(existing, &to_remove, &rules, false).unwrap_or_else(|| {
+        let mut lines = retain_unmanaged_lines(existing, &to_remove);
+        lines.extend(rules);
+        lines
+    });
+    join_lines(&lines)
+}
+
+/// Répertoires ancêtres d'un node (racine exclue) qui ont leur propre .gitignore,
+/// du plus proche au plus lointain.
+fn nested_gitignore_dirs(nodes: &[Node], idx: usize) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let mut depth = nodes[idx].depth;
+    for p in (1..idx).rev() {
+        if nodes[p].depth < depth {
+            depth = nodes[p].depth;
+            if depth > 0 && nodes[p].path.join(".gitignore").is_file() {
+                dirs.push(nodes[p].path.clone());
+            }
+        }
+    }
+    dirs
+}
+
+/// Ligne d'un diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Diff ligne à ligne par plus longue sous-séquence commune
+/// (les .gitignore sont petits, le coût quadratique ne pose pas de problème).
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffLine<'a>> {
+    let (n, m) = (old.len(), new.len());
+
+    // lcs[i][j] = longueur de la plus longue sous-séquence commune de old[i..] et new[j..]
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            out.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            out.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    out.extend(old[i..].iter().map(|l| DiffLine::Removed(l)));
+    out.extend(new[j..].iter().map(|l| DiffLine::Added(l)));
+    out
+}
+
+/// Lignes de contexte autour de chaque modification dans un diff unifié
+const DIFF_CONTEXT: usize = 3;
+
+/// Diff unifié entre deux contenus, vide s'ils ont les mêmes lignes.
+fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> Vec<String> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let diff = diff_lines(&old_lines, &new_lines);
+
+    let changed: Vec<usize> = diff
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| !matches!(d, DiffLine::Same(_)))
+        .map(|(k, _)| k)
+        .collect();
+    if changed.is_empty() {
+        return Vec::new();
+    }
+
+    // Numéro (à partir de 0) de la ligne de chaque côté au début de chaque entrée du diff
+    let mut old_pos = Vec::with_capacity(diff.len());
+    let mut new_pos = Vec::with_capacity(diff.len());
+    let (mut o, mut n) = (0, 0);
+    for d in &diff {
+        old_pos.push(o);
+        new_pos.push(n);
+        match d {
+            DiffLine::Same(_) => {
+                o += 1;
+                n += 1;
+            }
+            DiffLine::Removed(_) => o += 1,
+            DiffLine::Added(_) => n += 1,
+        }
+    }
+
+    let mut out = vec![format!("--- {}", old_label), format!("+++ {}", new_label)];
+
+    let mut k = 0;
+    while k < changed.len() {
+        // Deux modifications séparées par au plus 2 * DIFF_CONTEXT lignes
+        // identiques tombent dans le même hunk
+        let start = changed[k].saturating_sub(DIFF_CONTEXT);
+        let mut last = changed[k];
+        while k + 1 < changed.len() && changed[k + 1] <= last + 2 * DIFF_CONTEXT + 1 {
+            k += 1;
+            last = changed[k];
+        }
+        let end = (last + DIFF_CONTEXT).min(diff.len() - 1);
+        k += 1;
+
+        let hunk = &diff[start..=end];
+        let old_count = hunk.iter().filter(|d| !matches!(d, DiffLine::Added(_))).count();
+        let new_count = hunk.iter().filter(|d| !matches!(d, DiffLine::Removed(_))).count();
+        // Convention du format : un côté vide donne le numéro de la ligne précédente
+        let old_start = old_pos[start] + usize::from(old_count > 0);
+        let new_start = new_pos[start] + usize::from(new_count > 0);
+
+        out.push(format!("@@ -{},{} +{},{} @@", old_start, old_count, new_start, new_count));
+        for d in hunk {
+            out.push(match d {
+                DiffLine::Same(l) => format!(" {}", l),
+                DiffLine::Removed(l) => format!("-{}", l),
+                DiffLine::Added(l) => format!("+{}", l),
+            });
+        }
+    }
+
+    out
+}
+
+/// Charge l'arbre depuis le disque et lui applique les règles du .gitignore.
+/// Les incidents non bloquants sont ajoutés à `log`.
+fn load_tree(root: &Path, opts: &ScanOptions, log: &mut Vec<String>) -> Result<Vec<Node>> {
+    load_tree_timed(root, opts, log).map(|(nodes, _)| nodes)
+}
+
+/// Durées des étapes du chargement de l'arbre (voir --stats)
+#[derive(Debug, Default)]
+struct LoadTimings {
+    parse: Duration,
+    build: Duration,
+    matching: Duration,
+    rules: usize,
+}
+
+/// load_tree, en mesurant chaque étape
+fn load_tree_timed(root: &Path, opts: &ScanOptions, log: &mut Vec<String>) -> Result<(Vec<Node>, LoadTimings)> {
+    let mut timings = LoadTimings::default();
+
+    // 1) On parse le .gitignore comme liste ordonnée de règles
+    let start = Instant::now();
+    let content = read_primary(root, opts)?;
+    let rules = parse_gitignore_content(&content);
+    timings.parse = start.elapsed();
+    timings.rules = rules.len();
+
+    // 2) On construit l'arbre COMPLET (tous les fichiers, même dans les dossiers "repliés")
+    let start = Instant::now();
+    let mut nodes: Vec<Node> = build_full_tree(root, opts, log)?;
+    timings.build = start.elapsed();
+
+    // 3) On applique les règles : propagation des marks + exceptions
+    let start = Instant::now();
+    match_tree(&mut nodes, root, &content, opts, log)?;
+
+    // 4) On recalcule les cpt_exception et cpt_mixed_marks
+    recompute_cpt_exception(&mut nodes);
+    recompute_cpt_mixed_marks(&mut nodes);
+    timings.matching = start.elapsed();
+
+    debug!("tree loaded from {:?}: {} nodes, {} rules", root, nodes.len(), rules.len());
+    Ok((nodes, timings))
+}
+
+/// État d'ignore de chaque node d'après le contenu du .gitignore `content`, selon
+/// les options du parcours (--strict, --ignore-case, --also, exclusions globales)
+fn match_tree(nodes: &mut [Node], root: &Path, content: &str, opts: &ScanOptions, log: &mut Vec<String>) -> Result<()> {
+    if opts.strict {
+        mark_strict(nodes, root, content, opts.ignore_case, log)?;
+    } else {
+        // Règles simples et patterns génériques (*.png, etc.), racine puis sous-répertoires
+        match_gitignore_content(nodes, root, content, opts.ignore_case, log)?;
+    }
+
+    // --also : règles des fichiers secondaires, signalées à part
+    mark_also_matches(nodes, root, &opts.also, opts.ignore_case, log)?;
+    mark_global_matches(nodes, root, opts.excludes_file.as_deref(), opts.ignore_case, log)
+}
+
+/// --lazy : lit le contenu des répertoires `dirs` (indices croissants) et l'insère juste
+/// après chacun, ce qui garde le pré-ordre. Les nouveaux nodes prennent l'état que leur
+/// donne `content`, sauf si leur répertoire a été modifié à la main depuis : ils suivent
+/// alors son mark, comme l'aurait fait Enter sur le répertoire déjà chargé.
+/// Avec `full`, tout le sous-arbre est lu. Renvoie, pour chaque répertoire dans l'ordre,
+/// le nombre de nodes insérés.
+fn load_dir_contents(
+    nodes: &mut Vec<Node>,
+    dirs: &[usize],
+    root: &Path,
+    content: &str,
+    opts: &ScanOptions,
+    full: bool,
+    log: &mut Vec<String>,
+) -> Result<Vec<usize>> {
+    let opts = ScanOptions { lazy: opts.lazy && !full, ..opts.clone() };
+    let mut counts = vec![0; dirs.len()];
+    // En partant de la fin, les indices restant à traiter ne bougent pas
+    for (k, &idx) in dirs.iter().enumerate().rev() {
+        let mut loaded = load_children(root, &nodes[idx], &opts, log)?;
+        let children: Vec<Node> = loaded.drain(1..).collect();
+        counts[k] = children.len();
+        nodes[idx].loaded = true;
+        nodes[idx].unreadable = loaded[0].unreadable;
+        nodes.splice(idx + 1..idx + 1, children);
+    }
+
+    let mut shift = 0;
+    let dirs: Vec<usize> = dirs
+        .iter()
+        .zip(&counts)
+        .map(|(&idx, &count)| {
+            shift += count;
+            idx + shift - count
+        })
+        .collect();
+
+    // Ce que donnent les règles (.gitignore imbriqués, génériques), sur les seuls
+    // nodes qui décident de l'état du nouveau contenu
+    let picked = match_context(nodes, &dirs, &counts);
+    let mut baseline: Vec<Node> = picked.iter().map(|&i| nodes[i].clone()).collect();
+    match_tree(&mut baseline, root, content, &opts, log)?;
+    let at = |i: usize| picked.binary_search(&i).expect("node in the match context");
+    for (&idx, &count) in dirs.iter().zip(&counts) {
+        let b = &baseline[at(idx)];
+        let edited = (nodes[idx].mode, nodes[idx].mark) != (b.mode, b.mark);
+        let mark = nodes[idx].mark;
+        for i in idx + 1..=idx + count {
+            let (n, b) = (&mut nodes[i], &baseline[at(i)]);
+            (n.mode, n.mark, n.generic_mark) = (b.mode, b.mark, b.generic_mark);
+            (n.target, n.also, n.global) = (b.target.clone(), b.also, b.global);
+            if edited {
+                n.mode = Mode::N;
+                n.mark = mark || n.generic_mark;
+            }
+        }
+    }
+    recompute_cpt_exception(nodes);
+    recompute_cpt_mixed_marks(nodes);
+    Ok(counts)
+}
+
+/// Indices croissants des nodes dont dépend l'état du contenu des répertoires `dirs`,
+/// qui viennent de recevoir `counts` enfants : leurs ancêtres et les .gitignore
+/// imbriqués de ceux-ci, puis les répertoires et leur contenu. Tout node y garde son
+/// parent, si bien que match_tree les traite comme l'arbre entier.
+fn match_context(nodes: &[Node], dirs: &[usize], counts: &[usize]) -> Vec<usize> {
+    let parents = parent_indices(nodes);
+    let mut picked = Vec::new();
+    let mut seen = HashSet::new();
+    for (&idx, &count) in dirs.iter().zip(counts) {
+        picked.extend(idx..=idx + count);
+        let mut cur = parents[idx];
+        while let Some(dir) = cur.filter(|&d| seen.insert(d)) {
+            picked.push(dir);
+            // Celui de la racine est déjà `content`
+            if dir != 0 {
+                let depth = nodes[dir].depth;
+                picked.extend(
+                    (dir + 1..nodes.len())
+                        .take_while(|&j| nodes[j].depth > depth)
+                        .find(|&j| nodes[j].depth == depth + 1 && !nodes[j].is_dir && nodes[j].name == ".gitignore"),
+                );
+            }
+            cur = parents[dir];
+        }
+    }
+    picked.sort_unstable();
+    picked.dedup();
+    picked
+}
+
+/// --lazy : lit le contenu de tous les répertoires non encore lus pour lesquels
+/// `wanted` est vrai, y compris ceux qui apparaissent en chemin (rien sans --lazy)
+fn load_dirs_where(
+    nodes: &mut Vec<Node>,
+    root: &Path,
+    opts: &ScanOptions,
+    log: &mut Vec<String>,
+    wanted: impl Fn(&Node) -> bool,
+) -> Result<()> {
+    if !opts.lazy {
+        return Ok(());
+    }
+    let content = read_primary(root, opts)?;
+    loop {
+        let dirs: Vec<usize> = (0..nodes.len())
+            .filter(|&i| nodes[i].is_dir && !nodes[i].loaded && wanted(&nodes[i]))
+            .collect();
+        if dirs.is_empty() {
+            return Ok(());
+        }
+        load_dir_contents(nodes, &dirs, root, &content, opts, false, log)?;
+    }
+}
+
+/// --strict : marque les nodes exactement comme git les ignore, d'après toutes les
+/// lignes du .gitignore (négations et patterns compris), un répertoire ignoré
+/// emportant tout son contenu. Aucune règle C/E n'est posée.
+fn mark_strict(nodes: &mut [Node], root: &Path, content: &str, ignore_case: bool, log: &mut Vec<String>) -> Result<()> {
+    let mut builder = GitignoreBuilder::new(root);
+    builder.case_insensitive(ignore_case)?;
+    for (line_no, line) in content.lines().enumerate() {
+        if let Err(e) = builder.add_line(None, line) {
+            log.push(format!(".gitignore line {}: could not parse '{}': {}", line_no + 1, line, e));
+        }
+    }
+    let gitignore = builder.build()?;
+
+    // Comme git : dans un répertoire ignoré, une négation ne ré-inclut rien
+    let parents = parent_indices(nodes);
+    for i in 1..nodes.len() {
+        let parent_ignored = parents[i].is_some_and(|p| nodes[p].mark);
+        let n = &mut nodes[i];
+        n.mode = Mode::N;
+        n.generic_mark = false;
+        n.mark = parent_ignored || gitignore.matched(&n.path, n.is_dir).is_ignore();
+    }
+    Ok(())
+}
+
+/// --also : évalue les fichiers secondaires comme un seul .gitignore à la racine (dans
+/// l'ordre de la ligne de commande, avec la sémantique complète de git) et note dans
+/// Node::also celui qui ignore chaque node. Les marks du .gitignore principal ne changent pas.
+fn mark_also_matches(
+    nodes: &mut [Node],
+    root: &Path,
+    files: &[PathBuf],
+    ignore_case: bool,
+    log: &mut Vec<String>,
+) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    builder.case_insensitive(ignore_case)?;
+    for file in files {
+        let content = fs::read_to_string(file).context(format!("Reading --also file {:?}", file))?;
+        for (line_no, line) in content.lines().enumerate() {
+            if let Err(e) = builder.add_line(Some(file.clone()), line) {
+                log.push(format!("{} line {}: could not parse '{}': {}", file.display(), line_no + 1, line, e));
+            }
+        }
+    }
+    let gitignore = builder.build()?;
+
+    // Le contenu d'un répertoire ignoré l'est aussi, par le même fichier
+    let parents = parent_indices(nodes);
+    for i in 1..nodes.len() {
+        let inherited = parents[i].and_then(|p| nodes[p].also);
+        let n = &mut nodes[i];
+        n.also = inherited.or_else(|| match gitignore.matched(&n.path, n.is_dir) {
+            ignore::Match::Ignore(glob) => glob
+                .from()
+                .and_then(|from| files.iter().position(|f| f == from)),
+            _ => None,
+        });
+    }
+    Ok(())
+}
+
+/// Exclusions globales de git : note dans Node::global les nodes qu'ignore `file`,
+/// évalué comme un .gitignore à la racine. Les marks du .gitignore du dépôt ne changent pas.
+fn mark_global_matches(
+    nodes: &mut [Node],
+    root: &Path,
+    file: Option<&Path>,
+    ignore_case: bool,
+    log: &mut Vec<String>,
+) -> Result<()> {
+    let Some(file) = file else {
+        return Ok(());
+    };
+    let mut builder = GitignoreBuilder::new(root);
+    builder.case_insensitive(ignore_case)?;
+    if let Some(e) = builder.add(file) {
+        log.push(format!("{}: {}", file.display(), e));
+    }
+    let gitignore = builder.build()?;
+
+    // Le contenu d'un répertoire ignoré l'est aussi
+    let parents = parent_indices(nodes);
+    for i in 1..nodes.len() {
+        let inherited = parents[i].is_some_and(|p| nodes[p].global);
+        let n = &mut nodes[i];
+        n.global = inherited || gitignore.matched(&n.path, n.is_dir).is_ignore();
+    }
+    Ok(())
+}
+
+/// Fichier d'exclusions global de git : core.excludesFile, sinon $XDG_CONFIG_HOME/git/ignore
+/// (ou ~/.config/git/ignore), comme git. None si ce fichier n'existe pas.
+fn global_excludes_file(root: &Path) -> Option<PathBuf> {
+    let configured = run_vcs(root, "git", &["config", "--path", "--get", "core.excludesFile"])
+        .ok()
+        .map(|out| out.trim_end_matches(['\n', '\r']).to_string())
+        .filter(|path| !path.is_empty())
+        .map(|path| root.join(path));
+    let file = configured.or_else(|| {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("git").join("ignore"))
+    })?;
+    file.is_file().then_some(file)
+}
+
+/// --stats : taille de l'arbre et durée de chaque étape du chargement, sur stdout
+fn print_stats(root: &Path, opts: &ScanOptions) -> Result<()> {
+    let mut log = Vec::new();
+    let start = Instant::now();
+    let (nodes, timings) = load_tree_timed(root, opts, &mut log)?;
+    let total = start.elapsed();
+
+    // Le noeud racine virtuel n'est pas compté
+    let dirs = nodes.iter().skip(1).filter(|n| n.is_dir).count();
+    let files = nodes.len().saturating_sub(1) - dirs;
+    let max_depth = nodes.iter().map(|n| n.depth).max().unwrap_or(0);
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+
+    println!("Nodes:          {} ({} directories, {} files)", nodes.len() - 1, dirs, files);
+    println!("Max depth:      {}", max_depth);
+    println!("Rules parsed:   {} in {:.2} ms", timings.rules, ms(timings.parse));
+    println!("Tree build:     {:.2} ms", ms(timings.build));
+    println!("Rule matching:  {:.2} ms", ms(timings.matching));
+    println!("Total:          {:.2} ms", ms(total));
+    if !log.is_empty() {
+        println!("Warnings:       {}", log.len());
+    }
+    Ok(())
+}
+
+/// Applique aux nodes les règles (simples et génériques) d'un contenu de .gitignore,
+/// puis celles des .gitignore des sous-répertoires, lus sur le disque.
+fn match_gitignore_content(
+    nodes: &mut [Node],
+    root: &Path,
+    content: &str,
+    ignore_case: bool,
+    log: &mut Vec<String>,
+) -> Result<()> {
+    let nested = read_nested_gitignores(nodes, log);
+    let mut rules = parse_gitignore_content(content);
+    rules.extend(nested.iter().flat_map(NestedGitignore::rules));
+    apply_rules_to_nodes(nodes, &rules, ignore_case);
+    let generic = generic_gitignore_from_content(root, content, ignore_case, log)?;
+    mark_generic_matches(nodes, root, generic.as_ref());
+    mark_nested_generic_matches(nodes, &nested, ignore_case, log)?;
+    Ok(())
+}
+
+/// Patterns génériques des .gitignore imbriqués : chacun ne porte que sur les
+/// fichiers de son répertoire, et le plus profond passe en dernier.
+fn mark_nested_generic_matches(
+    nodes: &mut [Node],
+    nested: &[NestedGitignore],
+    ignore_case: bool,
+    log: &mut Vec<String>,
+) -> Result<()> {
+    for file in nested {
+        let Some(gitignore) = generic_gitignore_from_content(&file.dir, &file.content, ignore_case, log)? else {
+            continue;
+        };
+        let prefix = format!("{}/", file.rel);
+        for n in nodes.iter_mut().filter(|n| !n.is_dir && n.rel.starts_with(&prefix)) {
+            let matched = gitignore.matched_path_or_any_parents(&n.path, false);
+            if matched.is_ignore() {
+                n.mark = true;
+                n.generic_mark = true;
+            } else if matched.is_whitelist() && n.generic_mark {
+                n.mark = false;
+                n.generic_mark = false;
+            }
+        }
+    }
+    recompute_cpt_mixed_marks(nodes);
+    Ok(())
+}
+
+/// Instantané de l'état d'un node, conservé à travers un rechargement
+#[derive(Debug, Clone)]
+struct NodeState {
+    mode: Mode,
+    mark: bool,
+    expanded: bool,
+    override_line: Option<String>,
+    target: Option<PathBuf>,
+    pinned: bool,
+}
+
+/// Prend un instantané (chemin relatif -> état) de tous les nodes.
+fn snapshot_marks(nodes: &[Node]) -> HashMap<String, NodeState> {
+    nodes
+        .iter()
+        .map(|n| {
+            let state = NodeState {
+                mode: n.mode,
+                mark: n.mark,
+                expanded: n.expanded,
+                override_line: n.override_line.clone(),
+                target: n.target.clone(),
+                pinned: n.pinned,
+            };
+            (n.rel.clone(), state)
+        })
+        .collect()
+}
+
+/// Réapplique un instantané sur un arbre reconstruit, pour les chemins qui existent encore.
+/// Renvoie (triés) les chemins marqués ou porteurs d'une règle qui ont disparu.
+fn restore_marks(nodes: &mut [Node], snapshot: &HashMap<String, NodeState>) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    for n in nodes.iter_mut() {
+        if let Some(state) = snapshot.get(&n.rel) {
+            n.mode = state.mode;
+            n.mark = state.mark;
+            n.expanded = state.expanded;
+            n.override_line = state.override_line.clone();
+            n.target = state.target.clone();
+            n.pinned = state.pinned;
+            seen.insert(n.rel.clone());
+        }
+    }
+
+    let mut lost: Vec<String> = snapshot
+        .iter()
+        .filter(|(rel, state)| {
+            !seen.contains(*rel)
+                && (state.mark || state.mode != Mode::N || state.override_line.is_some() || state.pinned)
+        })
+        .map(|(rel, _)| rel.clone())
+        .collect();
+    lost.sort();
+
+    recompute_cpt_exception(nodes);
+    recompute_cpt_mixed_marks(nodes);
+    lost
+}
+
+/// Message transitoire affiché dans le pied de page (ex : touche sans effet)
+struct Flash {
+    text: String,
+    shown_at: Instant,
+    color: style::Color,
+}
+
+/// Action déclenchée à la validation d'une saisie
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    MarkGlob,
+    /// Pattern écrit comme une seule règle (Alt+M)
+    GlobRule,
+    /// Ligne de .gitignore du node d'index donné
+    EditRule(usize),
+    MarkOlderThan,
+    AddRule,
+    /// Question y/N : remplacer les marks des enfants par une règle sur le répertoire
+    CollapseToDir(usize),
+    /// Question y/N avant de basculer un gros répertoire (voir Config::confirm_threshold)
+    ConfirmToggle(usize),
+    /// Nom sous lequel enregistrer le modèle du répertoire d'index donné
+    SaveTemplate(usize),
+    /// Nom du modèle à appliquer au répertoire d'index donné
+    ApplyTemplate(usize),
+    /// Recherche floue d'un répertoire où placer le curseur
+    JumpToDir,
+    /// Règle (non gérée, ou tapée) dont on veut voir les entrées qu'elle ignore
+    ShowRuleMatches,
+    /// Taille au-delà de laquelle marquer les répertoires
+    MarkLargerThan,
+    /// Question y/N : marquer les répertoires plus gros que ce seuil (en octets)
+    ConfirmSizeMark(u64),
+    /// Recherche par nom (touche /), depuis le node d'index donné où Esc ramène
+    Search(usize),
+    /// Question y/N : quitter en perdant les modifications non enregistrées
+    ConfirmQuit,
+    /// Le .gitignore a changé sur le disque depuis son chargement : r recharge, o écrase
+    ConfirmOverwrite,
+}
+
+/// Saisie en cours (une ligne de texte), validée par Enter, annulée par Esc
+struct Prompt {
+    kind: PromptKind,
+    label: String,
+    input: String,
+    /// Propositions filtrées par la saisie (vide pour une saisie libre)
+    choices: Vec<String>,
+    /// Ligne choisie parmi les propositions filtrées
+    selected: usize,
+}
+
+/// Texte défilant affiché par-dessus l'arbre, fermé par Esc
+struct Popup {
+    title: String,
+    /// Lignes avec leur couleur éventuelle
+    lines: Vec<(String, Option<style::Color>)>,
+    scroll: usize,
+    /// Décalage horizontal, en colonnes
+    hscroll: usize,
+    /// Touches rappelées en bas du cadre (par défaut celles du défilement)
+    keys_hint: &'static str,
+}
+
+impl Popup {
+    fn new(title: &str, lines: Vec<(String, Option<style::Color>)>) -> Self {
+        Popup {
+            title: title.to_string(),
+            lines,
+            scroll: 0,
+            hscroll: 0,
+            keys_hint: "↑/↓ PgUp/PgDn ←/→ scroll · Esc close",
+        }
+    }
+}
+
+/// Historique des sauts du curseur, façon jumplist de vim : indices de nodes,
+/// enregistrés aux sauts (parent, f) et non à chaque ligne.
+#[derive(Debug, Default)]
+struct JumpList {
+    back: Vec<usize>,
+    forward: Vec<usize>,
+}
+
+impl JumpList {
+    /// Retient `from` avant un saut ; un nouveau saut efface les positions « suivantes »
+    fn record(&mut self, from: usize) {
+        if self.back.last() != Some(&from) {
+            self.back.push(from);
+        }
+        if self.back.len() > JUMP_HISTORY_LEN {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+    }
+
+    /// Position précédente (Ctrl+O), `current` passe dans les positions suivantes
+    fn back(&mut self, current: usize) -> Option<usize> {
+        let idx = Self::step(&mut self.back, current)?;
+        self.forward.push(current);
+        Some(idx)
+    }
+
+    /// Position suivante (Ctrl+I), `current` repasse dans les précédentes
+    fn forward(&mut self, current: usize) -> Option<usize> {
+        let idx = Self::step(&mut self.forward, current)?;
+        self.back.push(current);
+        Some(idx)
+    }
+
+    /// Dépile jusqu'à une position différente de `current`
+    fn step(stack: &mut Vec<usize>, current: usize) -> Option<usize> {
+        while let Some(idx) = stack.pop() {
+            if idx != current {
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    fn clear(&mut self) {
+        self.back.clear();
+        self.forward.clear();
+    }
+
+    /// `count` nodes viennent d'être insérés après `idx` (--lazy) : les suivants se décalent
+    fn shift(&mut self, idx: usize, count: usize) {
+        for i in self.back.iter_mut().chain(&mut self.forward).filter(|i| **i > idx) {
+            *i += count;
+        }
+    }
+}
+
+/// État de sélection d'un node, tel que l'annulation (u) le restaure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MarkState {
+    mode: Mode,
+    mark: bool,
+    generic_mark: bool,
+}
+
+fn mark_states(nodes: &[Node]) -> Vec<MarkState> {
+    nodes
+        .iter()
+        .map(|n| MarkState { mode: n.mode, mark: n.mark, generic_mark: n.generic_mark })
+        .collect()
+}
+
+/// Écart du nombre de fichiers marqués depuis l'état `before`, ou None si aucun
+/// node n'a changé. On ne regarde que les nodes modifiés, sans tout recompter.
+fn marked_delta(nodes: &[Node], before: &[MarkState]) -> Option<isize> {
+    let mut changed = false;
+    let mut delta = 0;
+    for (n, b) in nodes.iter().zip(before) {
+        if (n.mode, n.mark, n.generic_mark) != (b.mode, b.mark, b.generic_mark) {
+            changed = true;
+            if !n.is_dir {
+                delta += isize::from(n.mark) - isize::from(b.mark);
+            }
+        }
+    }
+    changed.then_some(delta)
+}
+
+/// Historique d'annulation : l'état de tous les nodes avant chaque modification
+/// de la sélection, les plus anciens oubliés au-delà de UNDO_HISTORY_LEN.
+#[derive(Debug, Default)]
+struct UndoHistory {
+    undo: Vec<Vec<MarkState>>,
+    redo: Vec<Vec<MarkState>>,
+}
+
+impl UndoHistory {
+    /// Retient l'état d'avant une modification ; une nouvelle modification efface le redo
+    fn record(&mut self, before: Vec<MarkState>) {
+        self.undo.push(before);
+        if self.undo.len() > UNDO_HISTORY_LEN {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// État à restaurer pour u, `current` passe dans le redo
+    fn undo(&mut self, current: Vec<MarkState>) -> Option<Vec<MarkState>> {
+        let state = self.undo.pop()?;
+        self.redo.push(current);
+        Some(state)
+    }
+
+    /// État à restaurer pour Ctrl+R, `current` repasse dans l'undo
+    fn redo(&mut self, current: Vec<MarkState>) -> Option<Vec<MarkState>> {
+        let state = self.redo.pop()?;
+        self.undo.push(current);
+        Some(state)
+    }
+
+    fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Des nodes viennent d'être insérés après `idx` (--lazy) : chaque état retenu
+    /// les reçoit dans leur état actuel, rien de plus ne pouvant y être annulé
+    fn insert(&mut self, idx: usize, states: &[MarkState]) {
+        for state in self.undo.iter_mut().chain(&mut self.redo) {
+            state.splice(idx + 1..idx + 1, states.iter().copied());
+        }
+    }
+}
+
+/// Ce que la boucle principale doit faire après une touche
+enum Flow {
+    Continue,
+    Quit,
+    Saved,
+}
+
+/// État de l'interface
+struct App {
+    root: PathBuf,
+    config: Config,
+    scan_opts: ScanOptions,
+    filter: ViewFilter,
+    /// Liste à plat des fichiers au lieu de l'arbre
+    flat: bool,
+    /// Entrées marquées en tête de chaque répertoire (touche O)
+    marked_first: bool,
+    /// Chaînes de répertoires à enfant unique sur une seule ligne (touche c)
+    compact: bool,
+    /// Profondeur affichée de chaque node en mode compact (voir compact_depths)
+    compact_depths: Vec<usize>,
+    /// Colonne des permissions Unix (touche x)
+    show_permissions: bool,
+    /// Colonne des tailles (touche z)
+    show_sizes: bool,
+    /// Entrée sous le curseur quand les entrées ignorées ont été masquées (touche h),
+    /// retrouvée quand on les réaffiche
+    hidden_from: Option<PathBuf>,
+    /// Taille cumulée de chaque node (voir compute_sizes), calculée au chargement
+    sizes: Vec<u64>,
+    /// Intention "unignore" (touche t) : Enter ne fait que retirer des entrées de l'ignore
+    unignore: bool,
+    nodes: Vec<Node>,
+    visible: Vec<usize>,
+    cursor_pos: usize, // index dans les visibles
+    scroll_offset: usize,
+    flash: Option<Flash>,
+    prompt: Option<Prompt>,
+    /// Journal des incidents non bloquants de la session
+    log: Vec<String>,
+    show_log: bool,
+    popup: Option<Popup>,
+    /// Lignes libres à ajouter au .gitignore (touches + et Alt+M)
+    extra_rules: Vec<String>,
+    /// Lignes de --template pas encore enregistrées, lues comme la fin du .gitignore
+    seed: String,
+    /// Fichiers suivis par le VCS (None hors dépôt ou si la commande a échoué)
+    tracked: Option<HashSet<String>>,
+    /// Compteurs du .gitignore sur disque, recalculés au chargement et après sauvegarde
+    rule_counts: RuleCounts,
+    /// Règle dont les correspondances sont signalées dans l'arbre (touche W), et ces nodes
+    rule_highlight: Option<(String, HashSet<usize>)>,
+    /// Contenu du .gitignore d'où viennent les marks non modifiés (chargement, R)
+    loaded_content: String,
+    /// Nombre de fichiers de l'arbre, compté au chargement
+    total_files: usize,
+    /// Fichiers actuellement marqués, recomptés après chaque modification
+    marked_files: usize,
+    /// Historique de navigation (Ctrl+O / Ctrl+I)
+    jumps: JumpList,
+    /// Annulation des modifications de la sélection (u / Ctrl+R)
+    history: UndoHistory,
+    /// État des marks avant la touche en cours, retenu par begin_edit
+    undo_base: Option<Vec<MarkState>>,
+    /// Modifications non enregistrées : q demande alors confirmation
+    dirty: bool,
+    /// Premier "g" de "gg" reçu, en attente du second
+    pending_g: bool,
+    /// Dernière recherche validée (touche /), reprise par n / N
+    search: Option<String>,
+    /// --dry-run : `s` garde ici les fichiers à écrire, affichés à la sortie, sans rien écrire
+    dry_run: bool,
+    dry_run_output: Vec<(PathBuf, String)>,
+    /// Copie en .gitignore.bak avant d'écrire (désactivée par --no-backup)
+    backup: bool,
+    /// Problèmes non bloquants de la sauvegarde, affichés une fois le terminal rendu
+    save_warnings: Vec<String>,
+    theme: Theme,
+}
+
+impl App {
+    fn new(
+        root: &Path,
+        config: Config,
+        scan_opts: ScanOptions,
+        filter: ViewFilter,
+        nodes: Vec<Node>,
+        log: Vec<String>,
+    ) -> Self {
+        let visible = build_visible_indices(&nodes, &filter, false);
+        let (total_files, marked_files) = count_files(&nodes);
+        let sizes = compute_sizes(&nodes);
+        App {
+            root: root.to_path_buf(),
+            config,
+            scan_opts,
+            filter,
+            flat: false,
+            marked_first: false,
+            compact: false,
+            compact_depths: Vec::new(),
+            show_permissions: false,
+            show_sizes: false,
+            hidden_from: None,
+            sizes,
+            unignore: false,
+            nodes,
+            visible,
+            cursor_pos: 0,
+            scroll_offset: 0,
+            flash: None,
+            prompt: None,
+            log,
+            show_log: false,
+            popup: None,
+            extra_rules: Vec::new(),
+            seed: String::new(),
+            tracked: None,
+            rule_counts: RuleCounts::default(),
+            rule_highlight: None,
+            loaded_content: String::new(),
+            total_files,
+            marked_files,
+            jumps: JumpList::default(),
+            history: UndoHistory::default(),
+            undo_base: None,
+            dirty: false,
+            pending_g: false,
+            search: None,
+            dry_run: false,
+            backup: true,
+            save_warnings: Vec::new(),
+            theme: Theme::UNICODE,
+            dry_run_output: Vec::new(),
+        }
+    }
+
+    /// Recharge la liste des fichiers suivis par le VCS du projet, s'il y en a un
+    fn load_tracked(&mut self) {
+        self.tracked = Vcs::detect(&self.root).and_then(|vcs| match vcs.tracked_files(&self.root) {
+            Ok(files) => Some(files.into_iter().collect()),
+            Err(e) => {
+                self.log.push(format!("Tracked files unavailable: {:#}", e));
+                None
+            }
+        });
+    }
+
+    /// Relit le .gitignore sur disque : compteurs de règles, et contenu de
+    /// référence de l'état chargé (voir pull_gitignore)
+    fn load_gitignore_state(&mut self) {
+        match read_primary(&self.root, &self.scan_opts) {
+            Ok(content) => {
+                self.rule_counts = count_rules(&self.nodes, &content);
+                self.loaded_content = content;
+            }
+            Err(e) => self.log.push(format!("Rule count unavailable: {:#}", e)),
+        }
+    }
+
+    /// Le .gitignore racine a été modifié par un autre outil depuis son chargement
+    /// (ou depuis le dernier R) : l'écraser perdrait ces modifications.
+    fn changed_on_disk(&self) -> bool {
+        read_primary(&self.root, &self.scan_opts).is_ok_and(|content| content != self.loaded_content)
+    }
+
+    /// Écrit les .gitignore (s, ou o après un changement sur le disque)
+    fn save(&mut self) -> Result<Flow> {
+        self.save_warnings = save_gitignore(&self.nodes, &self.root, &self.generate_options(), self.backup)?;
+        // Le modèle fait maintenant partie du fichier
+        self.seed.clear();
+        self.dirty = false;
+        self.load_gitignore_state();
+        self.set_success_flash("Saved ✓");
+        Ok(Flow::Saved)
+    }
+
+    /// Fichier ignoré (règle ou pattern générique) mais toujours suivi par le VCS
+    fn is_conflict(&self, n: &Node) -> bool {
+        !n.is_dir && n.mark && self.tracked.as_ref().is_some_and(|t| t.contains(&n.rel))
+    }
+
+    /// Recalcule les lignes affichées selon le mode (arbre ou liste à plat)
+    fn rebuild_visible(&mut self) {
+        self.visible = if self.flat {
+            build_flat_indices(&self.nodes, &self.filter)
+        } else {
+            build_visible_indices(&self.nodes, &self.filter, self.compact)
+        };
+        if self.compact {
+            self.compact_depths = compact_depths(&self.nodes);
+        }
+        if self.marked_first {
+            self.visible = marked_first(&self.nodes, &self.visible, self.flat);
+        }
+    }
+
+    /// Déplie/replie le répertoire, et en mode compact toute la chaîne affichée sur sa ligne
+    fn set_chain_expanded(&mut self, idx: usize, expanded: bool) {
+        // --lazy : la chaîne s'allonge à mesure que ses répertoires sont lus
+        if expanded {
+            loop {
+                let chain = if self.compact { compact_chain(&self.nodes, idx) } else { vec![idx] };
+                match chain.into_iter().find(|&i| !self.nodes[i].loaded) {
+                    Some(i) if self.load_dir(i, false) => {}
+                    _ => break,
+                }
+            }
+        }
+        let chain = if self.compact { compact_chain(&self.nodes, idx) } else { vec![idx] };
+        for i in chain {
+            self.nodes[i].expanded = expanded;
+        }
+    }
+
+    /// Active/désactive l'affichage compact, en gardant le curseur sur le même node
+    /// ou, s'il est fusionné, sur la ligne qui le contient.
+    fn toggle_compact(&mut self) {
+        let Some(mut idx) = self.visible.get(self.cursor_pos).copied() else {
+            return;
+        };
+        self.compact = !self.compact;
+        if self.compact {
+            while is_compacted(&self.nodes, idx) {
+                idx = parent_of(&self.nodes, idx).unwrap_or(0);
+            }
+        }
+        self.rebuild_visible();
+        if let Some(row) = self.visible.iter().position(|&i| i == idx) {
+            self.cursor_pos = row;
+        }
+        if self.compact {
+            self.set_flash("compact paths on: single-child directories share one row");
+        } else {
+            self.set_flash("compact paths off");
+        }
+    }
+
+    /// Replie tout ce qui n'est pas sur le chemin du node courant : seuls ses ancêtres
+    /// (et lui-même s'il s'agit d'un répertoire) restent dépliés.
+    fn focus_current(&mut self) {
+        if self.flat {
+            self.set_flash("focus only applies to the tree view");
+            return;
+        }
+        let idx = self.visible[self.cursor_pos];
+        if self.nodes[idx].is_dir {
+            self.load_dir(idx, false);
+        }
+
+        // Ancêtres : on remonte le pré-ordre par profondeurs décroissantes
+        let mut on_path = vec![false; self.nodes.len()];
+        on_path[idx] = true;
+        let mut depth = self.nodes[idx].depth;
+        for p in (0..idx).rev() {
+            if self.nodes[p].depth < depth {
+                on_path[p] = true;
+                depth = self.nodes[p].depth;
+            }
+        }
+
+        for (n, keep) in self.nodes.iter_mut().zip(on_path) {
+            if n.is_dir {
+                n.expanded = keep;
+            }
+        }
+        self.rebuild_visible();
+        if let Some(row) = self.visible.iter().position(|&i| i == idx) {
+            self.cursor_pos = row;
+        }
+    }
+
+    /// E / C : déplie ou replie tout le contenu du répertoire courant
+    /// (pour un fichier, celui de son répertoire)
+    fn expand_subtree(&mut self, expanded: bool) {
+        if self.flat {
+            self.set_flash("expanding only applies to the tree view");
+            return;
+        }
+        let current = self.visible[self.cursor_pos];
+        let idx = if self.nodes[current].is_dir {
+            current
+        } else {
+            parent_of(&self.nodes, current).unwrap_or(0)
+        };
+        if expanded {
+            self.load_dir(idx, true);
+        }
+        set_subtree_expanded(&mut self.nodes, idx, expanded);
+        self.keep_cursor_on(current);
+    }
+
+    /// * : déplie tout l'arbre, ou le replie jusqu'aux entrées de la racine s'il l'est déjà
+    fn toggle_expand_all(&mut self) {
+        if self.flat {
+            self.set_flash("expanding only applies to the tree view");
+            return;
+        }
+        let current = self.visible[self.cursor_pos];
+        let expand = self.nodes.iter().any(|n| n.is_dir && (!n.expanded || !n.loaded));
+        if expand {
+            self.load_dir(0, true);
+        }
+        set_subtree_expanded(&mut self.nodes, 0, expand);
+        self.nodes[0].expanded = true;
+        self.keep_cursor_on(current);
+    }
+
+    /// --lazy : lit le contenu du répertoire `idx` s'il ne l'a pas encore été (voir
+    /// load_dir_contents), tout son sous-arbre avec `full`. Les indices retenus
+    /// ailleurs (historiques, correspondances de W) sont mis à jour.
+    /// Renvoie false si la lecture a échoué.
+    fn load_dir(&mut self, idx: usize, full: bool) -> bool {
+        let end = if full { idx + 1 + subtree_size(&self.nodes, idx) } else { idx + 1 };
+        let dirs: Vec<usize> = (idx..end).filter(|&i| self.nodes[i].is_dir && !self.nodes[i].loaded).collect();
+        if dirs.is_empty() {
+            return true;
+        }
+        let content = self.with_extra_rules(&self.loaded_content);
+        let result = load_dir_contents(&mut self.nodes, &dirs, &self.root, &content, &self.scan_opts, full, &mut self.log);
+        let ok = result.is_ok();
+        match result {
+            Ok(counts) => {
+                // Même ordre que les insertions : de la fin vers le début
+                let mut shift: usize = counts.iter().sum();
+                for (&i, &count) in dirs.iter().zip(&counts).rev() {
+                    shift -= count;
+                    self.jumps.shift(i, count);
+                    let start = i + shift + 1;
+                    self.history.insert(i, &mark_states(&self.nodes[start..start + count]));
+                }
+            }
+            Err(e) => {
+                // Arbre peut-être en partie complété : les indices retenus ne valent plus rien
+                self.set_flash(&format!("could not read '{}': {}", self.nodes[idx].rel, e));
+                self.jumps.clear();
+                self.history.clear();
+            }
+        }
+        self.rule_highlight = None;
+        (self.total_files, self.marked_files) = count_files(&self.nodes);
+        self.sizes = compute_sizes(&self.nodes);
+        ok
+    }
+
+    /// Reconstruit `visible` en gardant le curseur sur `idx`, ou sur le plus proche
+    /// de ses parents encore affiché s'il vient d'être replié.
+    fn keep_cursor_on(&mut self, idx: usize) {
+        self.rebuild_visible();
+        let mut node = Some(idx);
+        while let Some(i) = node {
+            if let Some(row) = self.visible.iter().position(|&v| v == i) {
+                self.cursor_pos = row;
+                return;
+            }
+            node = parent_of(&self.nodes, i);
+        }
+    }
+
+    /// Vrai si Enter doit déplier/replier la ligne courante (enter_expands, sur un répertoire)
+    fn enter_expands_here(&self) -> bool {
+        self.config.enter_expands && self.visible.get(self.cursor_pos).is_some_and(|&i| self.nodes[i].is_dir)
+    }
+
+    /// Active/désactive le tri des entrées marquées en tête, en gardant le curseur sur le même node
+    fn toggle_marked_first(&mut self) {
+        let current = self.visible.get(self.cursor_pos).copied();
+        self.marked_first = !self.marked_first;
+        self.rebuild_visible();
+        if let Some(row) = current.and_then(|c| self.visible.iter().position(|&i| i == c)) {
+            self.cursor_pos = row;
+        }
+        if self.marked_first {
+            self.set_flash("marked entries first");
+        } else {
+            self.set_flash("original order");
+        }
+    }
+
+    /// Masque/réaffiche les entrées déjà ignorées. Le curseur reste sur le même node
+    /// ou, s'il disparaît, sur la ligne affichée juste au-dessus ; en réaffichant,
+    /// il revient sur l'entrée où il était au moment de masquer.
+    fn toggle_hide_ignored(&mut self) {
+        let current = self.visible.get(self.cursor_pos).copied();
+        self.filter.hide_ignored = !self.filter.hide_ignored;
+        let target = if self.filter.hide_ignored {
+            self.hidden_from = current.map(|i| self.nodes[i].path.clone());
+            current
+        } else {
+            self.hidden_from
+                .take()
+                .and_then(|p| self.nodes.iter().position(|n| n.path == p))
+                .or(current)
+        };
+        self.rebuild_visible();
+
+        if let Some(idx) = target {
+            // Le node lui-même, sinon la ligne affichée qui le précède dans l'arbre
+            self.cursor_pos = self
+                .visible
+                .iter()
+                .position(|&i| i == idx)
+                .or_else(|| {
+                    (0..self.visible.len())
+                        .filter(|&row| self.visible[row] < idx)
+                        .max_by_key(|&row| self.visible[row])
+                })
+                .unwrap_or(0);
+        }
+        self.set_flash(if self.filter.hide_ignored { "ignored entries hidden" } else { "ignored entries shown" });
+    }
+
+    /// Bascule entre l'arbre et la liste à plat en gardant le curseur sur le même node,
+    /// ou à défaut sur son plus proche ancêtre affiché (ou le premier fichier d'un répertoire).
+    fn toggle_flat(&mut self) {
+        let current = self.visible.get(self.cursor_pos).copied();
+        self.flat = !self.flat;
+        self.rebuild_visible();
+
+        let Some(idx) = current else {
+            return;
+        };
+        let depth = self.nodes[idx].depth;
+        let mut row = self.visible.iter().position(|&i| i == idx);
+        if row.is_none() && self.flat {
+            // Répertoire : premier fichier de son sous-arbre
+            row = self.visible.iter().position(|&i| {
+                i > idx && self.nodes[idx + 1..=i].iter().all(|n| n.depth > depth)
+            });
+        }
+        let mut p = idx;
+        let mut d = depth;
+        while row.is_none() && p > 0 {
+            p -= 1;
+            if self.nodes[p].depth < d {
+                d = self.nodes[p].depth;
+                row = self.visible.iter().position(|&i| i == p);
+            }
+        }
+        self.cursor_pos = row.unwrap_or(0);
+        self.set_flash(if self.flat { "flat file list" } else { "tree view" });
+    }
+
+    fn set_flash(&mut self, text: &str) {
+        self.flash = Some(Flash {
+            text: text.to_string(),
+            shown_at: Instant::now(),
+            color: style::Color::Yellow,
+        });
+    }
+
+    /// Message de réussite, en vert
+    fn set_success_flash(&mut self, text: &str) {
+        self.set_flash(text);
+        if let Some(flash) = &mut self.flash {
+            flash.color = style::Color::Green;
+        }
+    }
+
+    /// Efface le message transitoire s'il a expiré. Renvoie true s'il faut redessiner.
+    fn expire_flash(&mut self) -> bool {
+        match &self.flash {
+            Some(f) if f.shown_at.elapsed() >= FLASH_DURATION => {
+                self.flash = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reconstruit l'arbre depuis le disque en conservant les marques en cours
+    /// pour les chemins qui existent toujours.
+    fn reload(&mut self) -> Result<()> {
+        let snapshot = snapshot_marks(&self.nodes);
+        let current = self
+            .visible
+            .get(self.cursor_pos)
+            .map(|&i| self.nodes[i].path.clone());
+
+        let mut nodes = load_tree(&self.root, &self.scan_opts, &mut self.log)?;
+        // --lazy : on relit les répertoires qui l'avaient été
+        let loaded: HashSet<String> =
+            self.nodes.iter().filter(|n| n.is_dir && n.loaded).map(|n| n.rel.clone()).collect();
+        load_dirs_where(&mut nodes, &self.root, &self.scan_opts, &mut self.log, |n| loaded.contains(&n.rel))?;
+        let lost = restore_marks(&mut nodes, &snapshot);
+        for rel in &lost {
+            self.log.push(format!("Reload: mark on '{}' lost, path no longer exists", rel));
+        }
+
+        self.nodes = nodes;
+        // Les indices des correspondances de W et de l'historique ne valent plus rien
+        self.rule_highlight = None;
+        self.jumps.clear();
+        self.history.clear();
+        (self.total_files, self.marked_files) = count_files(&self.nodes);
+        self.sizes = compute_sizes(&self.nodes);
+        self.load_tracked();
+        self.load_gitignore_state();
+        self.rebuild_visible();
+        // On garde le curseur sur le même chemin si possible
+        if let Some(path) = current {
+            if let Some(row) = self.visible.iter().position(|&i| self.nodes[i].path == path) {
+                self.cursor_pos = row;
+            }
+        }
+
+        if lost.is_empty() {
+            self.set_flash("reloaded from disk");
+        } else {
+            self.set_flash(&format!(
+                "reloaded, {} mark(s) lost (path gone): {}",
+                lost.len(),
+                lost.join(", ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Contenu de .gitignore suivi des lignes ajoutées avec +
+    fn with_extra_rules(&self, content: &str) -> String {
+        let mut content = merge_seed(content, &self.seed);
+        for line in &self.extra_rules {
+            content.push('\n');
+            content.push_str(line);
+        }
+        content
+    }
+
+    /// Rejoue le matching du .gitignore (et des lignes ajoutées avec +) avec la
+    /// sensibilité à la casse de `scan_opts`. Les marks modifiés depuis le
+    /// chargement sont conservés : on les repère en rejouant l'ancien mode.
+    fn rematch(&mut self, previous_ignore_case: bool) -> Result<()> {
+        self.begin_edit();
+        let content = self.with_extra_rules(&read_primary(&self.root, &self.scan_opts)?);
+
+        // Ce que donnait le .gitignore seul : tout écart est une modification de l'utilisateur
+        let mut baseline = self.nodes.clone();
+        match_gitignore_content(&mut baseline, &self.root, &content, previous_ignore_case, &mut self.log)?;
+        let edited: Vec<(usize, Mode, bool)> = self
+            .nodes
+            .iter()
+            .zip(&baseline)
+            .enumerate()
+            .filter(|(_, (n, b))| n.mode != b.mode || n.mark != b.mark)
+            .map(|(i, (n, _))| (i, n.mode, n.mark))
+            .collect();
+
+        let ignore_case = self.scan_opts.ignore_case;
+        match_gitignore_content(&mut self.nodes, &self.root, &content, ignore_case, &mut self.log)?;
+        for (i, mode, mark) in edited {
+            let n = &mut self.nodes[i];
+            n.mode = mode;
+            n.mark = mark;
+            n.generic_mark = false;
+        }
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        self.rebuild_visible();
+        Ok(())
+    }
+
+    /// Reprend le .gitignore modifié ailleurs pendant la session (touche R), sans
+    /// perdre le travail en cours. Politique de fusion :
+    /// - un node dont l'état diffère de ce que donnait le contenu chargé (ou épinglé)
+    ///   a été modifié à la main : il garde son état, même si le fichier dit autre chose ;
+    /// - tous les autres prennent l'état que donne le fichier actuel.
+    ///
+    /// Les marks manuels qui contredisent le nouveau fichier sont listés dans le journal.
+    fn pull_gitignore(&mut self) -> Result<()> {
+        self.begin_edit();
+        let ignore_case = self.scan_opts.ignore_case;
+        let on_disk = read_primary(&self.root, &self.scan_opts)?;
+        let before = self.with_extra_rules(&self.loaded_content);
+        let after = self.with_extra_rules(&on_disk);
+
+        let mut baseline = self.nodes.clone();
+        match_gitignore_content(&mut baseline, &self.root, &before, ignore_case, &mut self.log)?;
+        let mut fresh = self.nodes.clone();
+        match_gitignore_content(&mut fresh, &self.root, &after, ignore_case, &mut self.log)?;
+
+        let mut merged = 0;
+        let mut kept = Vec::new();
+        for (i, (b, f)) in baseline.iter().zip(&fresh).enumerate() {
+            let n = &mut self.nodes[i];
+            let edited = n.mode != b.mode || n.mark != b.mark || n.pinned;
+            let changed_on_disk = (f.mode, f.mark, f.generic_mark) != (b.mode, b.mark, b.generic_mark);
+            if !changed_on_disk {
+                continue;
+            }
+            if edited {
+                if (f.mode, f.mark) != (n.mode, n.mark) {
+                    kept.push(n.rel.clone());
+                }
+            } else {
+                n.mode = f.mode;
+                n.mark = f.mark;
+                n.generic_mark = f.generic_mark;
+                merged += 1;
+            }
+        }
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        self.load_gitignore_state();
+        self.rebuild_visible();
+
+        for rel in &kept {
+            self.log.push(format!("Pull: kept your mark on '{}', the .gitignore now says otherwise", rel));
+        }
+        if merged == 0 && kept.is_empty() {
+            self.set_flash("no new change in .gitignore");
+        } else if kept.is_empty() {
+            self.set_flash(&format!("pulled {} change(s) from .gitignore", merged));
+        } else {
+            self.set_flash(&format!(
+                "pulled {} change(s) from .gitignore, kept {} manual mark(s) (see log)",
+                merged,
+                kept.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Liste les répertoires plus gros que le seuil saisi (touche B) et demande confirmation
+    fn confirm_size_mark(&mut self, input: &str) {
+        let Some(threshold) = parse_size(input) else {
+            self.set_flash(&format!("invalid size '{}' (e.g. 100M, 1.5G)", input.trim()));
+            return;
+        };
+        let found = dirs_larger_than(&self.nodes, threshold);
+        if found.is_empty() {
+            self.set_flash(&format!("no directory larger than {} outside ignored ones", format_size(threshold)));
+            return;
+        }
+
+        let width = found.iter().map(|&(i, _)| self.nodes[i].rel.width() + 1).max().unwrap_or(0);
+        let choices = found
+            .iter()
+            .map(|&(i, size)| {
+                let name = format!("{}/", self.nodes[i].rel);
+                format!("{}{}  {:>9}", name, " ".repeat(width - name.width()), format_size(size))
+            })
+            .collect();
+        let label = format!("Mark {} director(ies) larger than {}? [y/N]", found.len(), format_size(threshold));
+        self.open_prompt(PromptKind::ConfirmSizeMark(threshold), &label, "");
+        if let Some(prompt) = &mut self.prompt {
+            prompt.choices = choices;
+        }
+    }
+
+    /// Marque d'une règle chaque répertoire plus gros que `threshold` (voir dirs_larger_than)
+    fn mark_larger_than(&mut self, threshold: u64) {
+        self.begin_edit();
+        let found = dirs_larger_than(&self.nodes, threshold);
+        for &(idx, _) in &found {
+            mark_file(&mut self.nodes[idx]);
+            apply_recursive_mark_on_dir(&mut self.nodes, idx, true);
+        }
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        self.set_flash(&format!("marked {} director(ies) larger than {}", found.len(), format_size(threshold)));
+    }
+
+    /// Propose les règles non gérées du .gitignore (touche W) ; une autre règle peut être tapée
+    fn open_rule_matches(&mut self) -> Result<()> {
+        let existing = read_primary(&self.root, &self.scan_opts)?;
+        let rules = passthrough_rules(&self.nodes, &existing).into_iter().map(|(line, _)| line);
+        let choices = rules.collect();
+        self.open_prompt(PromptKind::ShowRuleMatches, "Show entries matched by rule:", "");
+        if let Some(prompt) = &mut self.prompt {
+            prompt.choices = choices;
+        }
+        Ok(())
+    }
+
+    /// Signale dans l'arbre les entrées que `rule` ignore (ou ré-inclut, pour une négation)
+    /// d'après le matcher complet du crate `ignore`, sans toucher aux marks.
+    fn show_rule_matches(&mut self, rule: &str) -> Result<()> {
+        let rule = gitignore_line(rule);
+        if rule.is_empty() {
+            return Ok(());
+        }
+        let mut builder = GitignoreBuilder::new(&self.root);
+        builder.case_insensitive(self.scan_opts.ignore_case)?;
+        if let Err(e) = builder.add_line(None, rule) {
+            self.set_flash(&format!("invalid rule: {}", e));
+            return Ok(());
+        }
+        let gitignore = builder.build()?;
+
+        let matches: HashSet<usize> = (1..self.nodes.len())
+            .filter(|&i| {
+                let n = &self.nodes[i];
+                !gitignore.matched_path_or_any_parents(&n.path, n.is_dir).is_none()
+            })
+            .collect();
+        self.set_flash(&format!("'{}' matches {} entries (◆), Esc clears", rule, matches.len()));
+        self.rule_highlight = Some((rule.to_string(), matches));
+        Ok(())
+    }
+
+    /// Déplie les ancêtres du node et y place le curseur
+    /// (sur la ligne qui le contient en mode compact).
+    fn reveal(&mut self, idx: usize) {
+        let mut parent = parent_of(&self.nodes, idx);
+        while let Some(p) = parent {
+            self.nodes[p].expanded = true;
+            parent = parent_of(&self.nodes, p);
+        }
+        self.rebuild_visible();
+
+        let mut row_idx = idx;
+        if self.compact {
+            while is_compacted(&self.nodes, row_idx) {
+                row_idx = parent_of(&self.nodes, row_idx).unwrap_or(0);
+            }
+        }
+        match self.visible.iter().position(|&i| i == row_idx) {
+            Some(row) => self.cursor_pos = row,
+            None => self.set_flash(&format!("'{}' is hidden by the current filter", self.nodes[idx].rel)),
+        }
+    }
+
+    /// Retient la position du curseur avant un saut (voir JumpList)
+    fn record_jump(&mut self) {
+        if let Some(&idx) = self.visible.get(self.cursor_pos) {
+            self.jumps.record(idx);
+        }
+    }
+
+    /// Descend (ou remonte) de `rows` lignes, au moins une, en s'arrêtant aux extrémités.
+    /// Le scroll se décale d'autant, pour garder le curseur au même endroit de l'écran.
+    fn move_by(&mut self, rows: usize, down: bool) {
+        let rows = rows.max(1);
+        let last = self.visible.len() - 1;
+        if down {
+            self.cursor_pos = (self.cursor_pos + rows).min(last);
+            self.scroll_offset += rows;
+        } else {
+            self.cursor_pos = self.cursor_pos.saturating_sub(rows);
+            self.scroll_offset = self.scroll_offset.saturating_sub(rows);
+        }
+    }
+
+    /// Saut du curseur à la ligne `row` (gg, G), retenu dans l'historique
+    fn jump_to_row(&mut self, row: usize) {
+        if row != self.cursor_pos {
+            self.record_jump();
+            self.cursor_pos = row;
+        }
+    }
+
+    /// Ctrl+O (`forward` faux) / Ctrl+I : revient à une position de l'historique
+    fn jump_history(&mut self, forward: bool) {
+        let current = self.visible[self.cursor_pos];
+        let target = if forward { self.jumps.forward(current) } else { self.jumps.back(current) };
+        match target {
+            Some(idx) => self.reveal(idx),
+            None if forward => self.set_flash("no newer position (Ctrl+O goes back)"),
+            None => self.set_flash("no older position in the jump history"),
+        }
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind, label: &str, input: &str) {
+        self.prompt = Some(Prompt {
+            kind,
+            label: label.to_string(),
+            input: input.to_string(),
+            choices: Vec::new(),
+            selected: 0,
+        });
+    }
+
+    /// Touche reçue pendant une saisie
+    fn handle_prompt_key(&mut self, code: KeyCode) -> Result<()> {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return Ok(());
+        };
+
+        // Question y/N : une seule touche suffit, tout sauf y vaut non
+        if let PromptKind::ConfirmSizeMark(threshold) = prompt.kind {
+            self.prompt = None;
+            if matches!(code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                self.mark_larger_than(threshold);
+            }
+            return Ok(());
+        }
+        if let PromptKind::CollapseToDir(idx) | PromptKind::ConfirmToggle(idx) = prompt.kind {
+            let kind = prompt.kind;
+            self.prompt = None;
+            if matches!(code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                match kind {
+                    PromptKind::ConfirmToggle(_) => self.toggle_node(idx),
+                    _ => self.collapse_to_dir(idx),
+                }
+            }
+            return Ok(());
+        }
+
+        match code {
+            KeyCode::Esc => {
+                let kind = prompt.kind;
+                self.prompt = None;
+                // Recherche abandonnée : retour au point de départ
+                if let PromptKind::Search(origin) = kind {
+                    self.reveal(origin);
+                }
+            }
+            KeyCode::Backspace => {
+                prompt.input.pop();
+                prompt.selected = 0;
+            }
+            KeyCode::Char(c) => {
+                prompt.input.push(c);
+                prompt.selected = 0;
+            }
+            KeyCode::Up if !prompt.choices.is_empty() => {
+                prompt.selected = prompt.selected.saturating_sub(1);
+            }
+            KeyCode::Down if !prompt.choices.is_empty() => {
+                let count = fuzzy_filter(&prompt.choices, &prompt.input).len();
+                prompt.selected = (prompt.selected + 1).min(count.saturating_sub(1));
+            }
+            KeyCode::Enter if !prompt.choices.is_empty() => {
+                let chosen = fuzzy_filter(&prompt.choices, &prompt.input)
+                    .get(prompt.selected)
+                    .map(|&i| prompt.choices[i].clone());
+                let kind = prompt.kind;
+                let input = prompt.input.clone();
+                self.prompt = None;
+                match (kind, chosen) {
+                    (PromptKind::JumpToDir, Some(dir)) => {
+                        let rel = dir.trim_end_matches('/');
+                        if let Some(idx) = self.nodes.iter().position(|n| n.rel == rel) {
+                            self.record_jump();
+                            self.reveal(idx);
+                        }
+                    }
+                    (PromptKind::JumpToDir, None) => self.set_flash("no matching directory"),
+                    // Aucune proposition ne correspond : la saisie est prise comme règle
+                    (_, chosen) => self.show_rule_matches(chosen.as_deref().unwrap_or(&input))?,
+                }
+            }
+            KeyCode::Enter => {
+                let kind = prompt.kind;
+                let input = prompt.input.clone();
+                self.prompt = None;
+                match kind {
+                    PromptKind::MarkGlob => self.mark_glob(&input),
+                    PromptKind::GlobRule => self.add_glob_rule(&input),
+                    PromptKind::EditRule(idx) => self.set_override(idx, &input),
+                    PromptKind::MarkOlderThan => self.mark_older_than(&input),
+                    PromptKind::AddRule => self.toggle_extra_rule(&input),
+                    PromptKind::SaveTemplate(idx) => self.save_template(idx, &input),
+                    PromptKind::ApplyTemplate(idx) => self.apply_template(idx, &input),
+                    PromptKind::ShowRuleMatches => self.show_rule_matches(&input)?,
+                    PromptKind::MarkLargerThan => self.confirm_size_mark(&input),
+                    PromptKind::Search(origin) => self.confirm_search(origin, &input),
+                    PromptKind::CollapseToDir(_)
+                    | PromptKind::ConfirmToggle(_)
+                    | PromptKind::ConfirmSizeMark(_)
+                    | PromptKind::ConfirmQuit
+                    | PromptKind::ConfirmOverwrite
+                    | PromptKind::JumpToDir => {}
+                }
+            }
+            _ => {}
+        }
+
+        // Recherche incrémentale : le curseur suit la saisie
+        if let Some(Prompt { kind: PromptKind::Search(origin), input, .. }) = &self.prompt {
+            if matches!(code, KeyCode::Char(_) | KeyCode::Backspace) {
+                let (origin, input) = (*origin, input.clone());
+                self.search_from(origin, &input);
+            }
+        }
+        Ok(())
+    }
+
+    /// Prochain node après `from` (`from` lui-même en dernier) dont le nom contient
+    /// `query` sans tenir compte de la casse, en bouclant sur l'arbre.
+    /// Seuls les nodes que le filtre (et la liste à plat) peuvent afficher sont candidats.
+    fn find_by_name(&self, query: &str, from: usize, forward: bool) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        let query = query.to_lowercase();
+        let hidden = self.filter.hidden_dirs(&self.nodes);
+        let len = self.nodes.len();
+        (1..=len)
+            .map(|step| if forward { (from + step) % len } else { (from + len * 2 - step) % len })
+            .find(|&i| {
+                let n = &self.nodes[i];
+                i != 0
+                    && !(self.flat && n.is_dir)
+                    && self.filter.shows(n)
+                    && !hidden[i]
+                    && n.name.to_lowercase().contains(&query)
+            })
+    }
+
+    /// Place le curseur sur la première correspondance de `query` après `origin`, ou le
+    /// ramène sur `origin` : la recherche incrémentale et sa validation passent par ici.
+    fn search_from(&mut self, origin: usize, query: &str) -> Option<usize> {
+        let found = self.find_by_name(query, origin, true);
+        self.reveal(found.unwrap_or(origin));
+        found
+    }
+
+    /// Enter dans la recherche : on reste sur la correspondance, retenue pour n / N
+    fn confirm_search(&mut self, origin: usize, query: &str) {
+        if query.is_empty() {
+            return;
+        }
+        self.search = Some(query.to_string());
+        match self.search_from(origin, query) {
+            Some(idx) => {
+                if idx != origin {
+                    self.jumps.record(origin);
+                }
+            }
+            None => self.set_flash(&format!("no entry name contains '{}'", query)),
+        }
+    }
+
+    /// n / N : correspondance suivante / précédente de la dernière recherche
+    fn search_next(&mut self, forward: bool) {
+        let Some(query) = self.search.clone() else {
+            self.set_flash("no search yet (/ to search)");
+            return;
+        };
+        let current = self.visible[self.cursor_pos];
+        match self.find_by_name(&query, current, forward) {
+            Some(idx) => {
+                let wrapped = if forward { idx <= current } else { idx >= current };
+                self.record_jump();
+                self.reveal(idx);
+                if wrapped {
+                    self.set_flash(&format!("search for '{}' wrapped around", query));
+                }
+            }
+            None => self.set_flash(&format!("no entry name contains '{}'", query)),
+        }
+    }
+
+    /// Bascule ignore/n'ignore plus pour le node, récursivement pour un répertoire
+    fn toggle_node(&mut self, idx: usize) {
+        self.begin_edit();
+        if self.nodes[idx].pinned {
+            self.set_flash(&format!("'{}' is pinned, press ! to unpin it", self.nodes[idx].rel));
+            return;
+        }
+        // Les fichiers marqués par une règle générique (*.png, etc.) ne sont pas cliquables
+        if self.nodes[idx].generic_mark && !self.nodes[idx].is_dir {
+            self.set_flash("generic-ignored file is not toggleable");
+            return;
+        }
+        // Les règles des fichiers --also ne sont pas enregistrées : rien à basculer ici
+        if let Some(file) = self.nodes[idx].also.filter(|_| !self.nodes[idx].mark) {
+            let file = self.scan_opts.also[file].display().to_string();
+            self.set_flash(&format!("ignored by {} (--also), read-only", file));
+            return;
+        }
+
+        let was_marked = self.nodes[idx].mark;
+        toggle_entry(&mut self.nodes, idx);
+        if !was_marked {
+            self.offer_collapse_to_dir(idx);
+        }
+    }
+
+    /// Inverse la sélection dans le répertoire `idx` (touche i, Alt+i pour tout le sous-arbre)
+    fn invert_selection(&mut self, idx: usize, recursive: bool) {
+        self.begin_edit();
+        if !self.nodes[idx].is_dir {
+            self.set_flash("i inverts the selection inside a directory");
+            return;
+        }
+        let count = invert_selection(&mut self.nodes, idx, recursive);
+        let name = if idx == 0 { "/" } else { self.nodes[idx].rel.as_str() };
+        let scope = if recursive { "file(s) under" } else { "entries in" };
+        self.set_flash(&format!("inverted {} {} '{}'", count, scope, name));
+    }
+
+    /// Épingle / désépingle le node (touche !). Un node épinglé n'est plus ignoré
+    /// (comme après Enter) et reste une exception quand un parent est ignoré ensuite.
+    fn toggle_pin(&mut self, idx: usize) {
+        if idx == 0 {
+            self.set_flash("the root cannot be pinned");
+            return;
+        }
+        if self.nodes[idx].pinned {
+            self.nodes[idx].pinned = false;
+            self.set_flash(&format!("'{}' unpinned", self.nodes[idx].rel));
+            return;
+        }
+        if self.nodes[idx].generic_mark && !self.nodes[idx].is_dir {
+            self.set_flash("generic-ignored file cannot be pinned");
+            return;
+        }
+        if self.nodes[idx].mark {
+            self.toggle_node(idx);
+        }
+        self.nodes[idx].pinned = true;
+        self.set_flash(&format!("'{}' pinned: it will never be ignored", self.nodes[idx].rel));
+    }
+
+    /// Après un marquage, propose de remplacer les marks un par un du répertoire
+    /// parent par une seule règle sur ce répertoire, s'il est entièrement marqué.
+    fn offer_collapse_to_dir(&mut self, idx: usize) {
+        if let Some(parent) = fully_marked_parent(&self.nodes, idx) {
+            let label = format!("'{}' is fully ignored, mark entire directory instead? [y/N]", self.nodes[parent].rel);
+            self.open_prompt(PromptKind::CollapseToDir(parent), &label, "");
+        }
+    }
+
+    /// Marque le répertoire (règle C) à la place de ses enfants, dont les marks sont effacés.
+    fn collapse_to_dir(&mut self, idx: usize) {
+        self.begin_edit();
+        mark_file(&mut self.nodes[idx]);
+        apply_recursive_mark_on_dir(&mut self.nodes, idx, true);
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        self.set_flash(&format!("'{}' is now ignored as a whole", self.nodes[idx].rel));
+        // Le répertoire parent peut à son tour être entièrement marqué
+        self.offer_collapse_to_dir(idx);
+    }
+
+    /// Ouvre l'édition de la ligne de .gitignore du node, pré-remplie avec sa ligne
+    /// actuelle (ou "/chemin" s'il n'en produit pas exactement une).
+    fn edit_rule(&mut self, idx: usize) {
+        let n = &self.nodes[idx];
+        let entry = &n.rel;
+        if entry.is_empty() {
+            self.set_flash("the root rule can't be edited");
+            return;
+        }
+
+        let current = match &n.override_line {
+            Some(line) => line.clone(),
+            None => match node_rule_lines(n, entry).as_slice() {
+                [line] => line.clone(),
+                _ => format!("/{entry}"),
+            },
+        };
+        self.open_prompt(PromptKind::EditRule(idx), "Rule line (empty = generated):", &current);
+    }
+
+    /// Passe au .gitignore suivant pour la règle du node : racine, puis celui
+    /// de l'ancêtre le plus proche, puis des ancêtres plus lointains, puis retour à la racine.
+    fn cycle_target(&mut self, idx: usize) {
+        if idx == 0 {
+            self.set_flash("the root rule always goes to the root .gitignore");
+            return;
+        }
+        let dirs = nested_gitignore_dirs(&self.nodes, idx);
+        if dirs.is_empty() {
+            self.set_flash("no .gitignore in the parent directories");
+            return;
+        }
+
+        let next = match &self.nodes[idx].target {
+            None => Some(0),
+            Some(t) => dirs.iter().position(|d| d == t).map(|p| p + 1).filter(|&p| p < dirs.len()),
+        };
+        self.nodes[idx].target = next.map(|p| dirs[p].clone());
+        self.dirty = true;
+
+        let msg = match &self.nodes[idx].target {
+            Some(dir) => {
+                let rel = dir.strip_prefix(&self.root).unwrap_or(dir);
+                format!("rule will be written to {}/.gitignore", rel.to_string_lossy())
+            }
+            None => "rule will be written to the root .gitignore".to_string(),
+        };
+        self.set_flash(&msg);
+    }
+
+    /// Ajoute une ligne libre au .gitignore, ou la retire si elle y était déjà.
+    fn toggle_extra_rule(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.dirty = true;
+        if let Some(pos) = self.extra_rules.iter().position(|r| r == line) {
+            self.extra_rules.remove(pos);
+            self.set_flash(&format!("custom rule '{}' removed", line));
+        } else {
+            self.extra_rules.push(line.to_string());
+            self.set_flash(&format!("custom rule '{}' will be added on save", line));
+        }
+    }
+
+    /// Enregistre la ligne saisie pour le node ; une ligne vide rend la main à la génération.
+    fn set_override(&mut self, idx: usize, line: &str) {
+        let line = line.trim();
+        self.dirty = true;
+        if line.is_empty() {
+            self.nodes[idx].override_line = None;
+            self.set_flash("rule override removed");
+        } else {
+            self.nodes[idx].override_line = Some(line.to_string());
+            self.set_flash("rule override set, written verbatim on save");
+        }
+    }
+
+    /// Enregistre les règles du répertoire comme modèle `name` dans la configuration
+    fn save_template(&mut self, idx: usize, name: &str) {
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) || name.contains('=') {
+            self.set_flash("template names can't be empty or contain spaces or '='");
+            return;
+        }
+        let template = Template::capture(&self.nodes, idx);
+        if template.entries.is_empty() {
+            self.set_flash("no rule inside this directory to save");
+            return;
+        }
+
+        match self.config.save_template(name, &template) {
+            Ok(path) => {
+                self.set_flash(&format!(
+                    "template '{}' saved ({} rule(s)) to {}",
+                    name,
+                    template.entries.len(),
+                    path.display()
+                ));
+                self.config.templates.insert(name.to_string(), template);
+            }
+            Err(e) => self.set_flash(&format!("could not save template: {:#}", e)),
+        }
+    }
+
+    /// Applique le modèle `name` au répertoire, seulement si tous ses chemins y existent
+    fn apply_template(&mut self, idx: usize, name: &str) {
+        self.begin_edit();
+        let name = name.trim();
+        let Some(template) = self.config.templates.get(name) else {
+            self.set_flash(&format!("no template named '{}'", name));
+            return;
+        };
+
+        let resolved = match template.resolve(&self.nodes, idx) {
+            Ok(resolved) => resolved,
+            Err(missing) => {
+                for rel in &missing {
+                    self.log.push(format!("Template '{}': '{}' not found under '{}'", name, rel, self.nodes[idx].rel));
+                }
+                self.set_flash(&format!(
+                    "template '{}' doesn't fit this directory: {} path(s) missing (see log)",
+                    name,
+                    missing.len()
+                ));
+                return;
+            }
+        };
+
+        // Pré-ordre : la règle d'un répertoire passe avant les exceptions de son contenu
+        for &(i, mode) in &resolved {
+            let mark = mode == Mode::C;
+            self.nodes[i].mode = mode;
+            self.nodes[i].mark = mark;
+            if self.nodes[i].is_dir {
+                apply_recursive_mark_on_dir(&mut self.nodes, i, mark);
+            }
+        }
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        self.set_flash(&format!("applied template '{}' ({} rule(s))", name, resolved.len()));
+    }
+
+    /// Marque (en règle classique C) les fichiers affichés qui correspondent
+    /// au pattern (voir glob_matcher), eux ou l'un de leurs répertoires parents.
+    fn mark_glob(&mut self, pattern: &str) {
+        self.begin_edit();
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return;
+        }
+
+        let visible = self.visible.clone();
+        match mark_matching(&mut self.nodes, &self.root, pattern, false, &visible) {
+            Ok(count) => self.set_flash(&format!("marked {} file(s) matching '{}'", count, pattern)),
+            Err(e) => self.set_flash(&format!("{:#}", e)),
+        }
+    }
+
+    /// Ajoute le pattern tel quel comme une seule règle (Alt+M) : les fichiers
+    /// qu'il couvre apparaissent comme ceux d'un pattern générique.
+    fn add_glob_rule(&mut self, pattern: &str) {
+        self.begin_edit();
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return;
+        }
+        let matcher = match glob_matcher(&self.root, pattern) {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.set_flash(&format!("{:#}", e));
+                return;
+            }
+        };
+        if !self.extra_rules.iter().any(|r| r == pattern) {
+            self.extra_rules.push(pattern.to_string());
+            self.dirty = true;
+        }
+        let before = self.nodes.iter().filter(|n| n.generic_mark).count();
+        mark_generic_matches(&mut self.nodes, &self.root, Some(&matcher));
+        recompute_cpt_exception(&mut self.nodes);
+        let matched = self.nodes.iter().filter(|n| n.generic_mark).count() - before;
+        self.set_flash(&format!("rule '{}' will be added on save ({} file(s) matched)", pattern, matched));
+    }
+
+    /// Bascule d'un coup les fichiers frères du node (même parent), sans descendre
+    /// dans les sous-répertoires : tout est marqué, sauf si tout l'était déjà.
+    fn toggle_sibling_files(&mut self, idx: usize) {
+        self.begin_edit();
+        if idx == 0 {
+            self.set_flash("the root has no siblings");
+            return;
+        }
+        let files: Vec<usize> = sibling_indices(&self.nodes, idx)
+            .into_iter()
+            .filter(|&i| !self.nodes[i].is_dir && !self.nodes[i].generic_mark)
+            .collect();
+        if files.is_empty() {
+            self.set_flash("no toggleable file at this level");
+            return;
+        }
+
+        let mark = !files.iter().all(|&i| self.nodes[i].mark);
+        for &i in &files {
+            if mark {
+                mark_file(&mut self.nodes[i]);
+            } else {
+                unmark_file(&mut self.nodes[i]);
+            }
+        }
+
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        let action = if mark { "marked" } else { "unmarked" };
+        self.set_flash(&format!("{} {} sibling file(s)", action, files.len()));
+        if mark {
+            self.offer_collapse_to_dir(idx);
+        }
+    }
+
+    /// Marque (en règle classique C) tous les fichiers non modifiés depuis `days` jours.
+    /// Les fichiers dont la date de modification est illisible sont signalés dans le journal.
+    fn mark_older_than(&mut self, days: &str) {
+        self.begin_edit();
+        let days = days.trim();
+        let Ok(max_age) = days.parse::<u64>() else {
+            self.set_flash(&format!("expected a number of days, got '{}'", days));
+            return;
+        };
+        let cutoff = SystemTime::now()
+            .checked_sub(Duration::from_secs(max_age.saturating_mul(24 * 60 * 60)))
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let mut count = 0;
+        let mut skipped = 0;
+        for n in self.nodes.iter_mut() {
+            if n.is_dir || n.generic_mark || n.mark {
+                continue;
+            }
+            match n.mtime {
+                Some(mtime) if mtime < cutoff => {
+                    mark_file(n);
+                    count += 1;
+                }
+                Some(_) => {}
+                None => {
+                    self.log.push(format!(
+                        "Age: skipped {}, modification time unavailable",
+                        n.path.display()
+                    ));
+                    skipped += 1;
+                }
+            }
+        }
+
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        if skipped > 0 {
+            self.set_flash(&format!(
+                "marked {} file(s) older than {} day(s), {} skipped (see log)",
+                count, days, skipped
+            ));
+        } else {
+            self.set_flash(&format!("marked {} file(s) older than {} day(s)", count, days));
+        }
+    }
+
+    /// Traite une touche ; si elle a changé la sélection (Enter, a, M, une confirmation...),
+    /// l'état d'avant est retenu pour u.
+    fn handle_key(&mut self, key: KeyEvent, available_height: usize) -> Result<Flow> {
+        if self.prompt.is_none() && self.popup.is_none() {
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Char('u') if !ctrl => return self.undo(false, available_height),
+                KeyCode::Char('r') if ctrl => return self.undo(true, available_height),
+                // --lazy : i lit d'abord le contenu du répertoire, pour que l'inversion
+                // elle-même s'annule d'un bloc (l'historique suit les insertions)
+                KeyCode::Char('i') if !ctrl => {
+                    if let Some(&idx) = self.visible.get(self.cursor_pos).filter(|&&i| self.nodes[i].is_dir) {
+                        self.load_dir(idx, key.modifiers.contains(KeyModifiers::ALT));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let flow = self.dispatch_key(key, available_height);
+        // Seules les touches passées par begin_edit peuvent avoir changé les marks
+        if let Some(before) = self.undo_base.take() {
+            if before.len() != self.nodes.len() {
+                // Contenu lu en cours de route (--lazy) : on recompte tout
+                self.marked_files = count_files(&self.nodes).1;
+            } else if let Some(delta) = marked_delta(&self.nodes, &before) {
+                self.marked_files = self.marked_files.saturating_add_signed(delta);
+                self.history.record(before);
+                self.dirty = true;
+            }
+        }
+        flow
+    }
+
+    /// À appeler par chaque action qui modifie les marks, avant de les toucher :
+    /// handle_key en fait une étape de u / Ctrl+R si elle a changé quelque chose.
+    fn begin_edit(&mut self) {
+        if self.undo_base.is_none() {
+            self.undo_base = Some(mark_states(&self.nodes));
+        }
+    }
+
+    /// u (ou Ctrl+R si `redo`) : remet la sélection dans l'état d'avant (ou d'après)
+    /// la dernière modification.
+    fn undo(&mut self, redo: bool, available_height: usize) -> Result<Flow> {
+        let current = mark_states(&self.nodes);
+        let state = if redo { self.history.redo(current) } else { self.history.undo(current) };
+        let Some(state) = state else {
+            self.set_flash(if redo { "nothing to redo" } else { "nothing to undo" });
+            return Ok(Flow::Continue);
+        };
+
+        // L'état rétabli a autant de fichiers marqués en moins que l'actuel en a en plus
+        let delta = marked_delta(&self.nodes, &state).unwrap_or(0);
+        for (n, s) in self.nodes.iter_mut().zip(state) {
+            (n.mode, n.mark, n.generic_mark) = (s.mode, s.mark, s.generic_mark);
+        }
+        recompute_cpt_exception(&mut self.nodes);
+        recompute_cpt_mixed_marks(&mut self.nodes);
+        self.dirty = true;
+        self.marked_files = self.marked_files.saturating_add_signed(-delta);
+        self.rebuild_visible();
+        self.clamp_scroll(available_height);
+        self.set_flash(if redo { "redone" } else { "undone" });
+        Ok(Flow::Continue)
+    }
+
+    fn dispatch_key(&mut self, key: KeyEvent, available_height: usize) -> Result<Flow> {
+        let code = key.code;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        // Un "g" seul attend le suivant ; toute autre touche l'annule
+        let pending_g = std::mem::take(&mut self.pending_g);
+        debug!(
+            "key {:?} (cursor {}/{}, prompt: {}, popup: {})",
+            code,
+            self.cursor_pos,
+            self.visible.len(),
+            self.prompt.is_some(),
+            self.popup.is_some()
+        );
+        // Questions qui peuvent terminer la session : traitées ici, où l'on rend un Flow
+        match self.prompt.as_ref().map(|p| p.kind) {
+            Some(PromptKind::ConfirmQuit) => {
+                self.prompt = None;
+                return Ok(match code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => Flow::Quit,
+                    _ => Flow::Continue,
+                });
+            }
+            Some(PromptKind::ConfirmOverwrite) => {
+                self.prompt = None;
+                match code {
+                    KeyCode::Char('o') | KeyCode::Char('O') => return self.save(),
+                    KeyCode::Char('r') | KeyCode::Char('R') => self.pull_gitignore()?,
+                    _ => self.set_flash("save cancelled"),
+                }
+                return Ok(Flow::Continue);
+            }
+            _ => {}
+        }
+        if self.prompt.is_some() {
+            self.handle_prompt_key(code)?;
+            // La recherche de répertoire (f) peut avoir déplacé le curseur
+            self.clamp_scroll(available_height);
+            return Ok(Flow::Continue);
+        }
+        if self.popup.is_some() {
+            self.handle_popup_key(code, available_height);
+            return Ok(Flow::Continue);
+        }
+        // --strict : l'état affiché est celui de git, il n'y a rien à modifier ni sauvegarder
+        // (Ctrl+I, le saut en avant, n'est pas l'inversion de i)
+        if self.scan_opts.strict
+            && edits_selection(code)
+            && !ctrl
+            && !(code == KeyCode::Enter && self.enter_expands_here())
+        {
+            self.set_flash("read-only with --strict (restart without it to edit)");
+            return Ok(Flow::Continue);
+        }
+
+        // Liste des visibles AVANT de traiter la touche
+        self.rebuild_visible();
+        if self.visible.is_empty() {
+            self.cursor_pos = 0;
+            self.scroll_offset = 0;
+            return Ok(Flow::Continue);
+        }
+        if self.cursor_pos >= self.visible.len() {
+            self.cursor_pos = self.visible.len().saturating_sub(1);
+        }
+
+        let mut jump_to_idx: Option<usize> = None;
+
+        match code {
+            KeyCode::Char('o') if ctrl => self.jump_history(false),
+            // Sans protocole clavier étendu, les terminaux envoient Ctrl+I comme Tab
+            KeyCode::Char('i') if ctrl => self.jump_history(true),
+            KeyCode::Tab => self.jump_history(true),
+            KeyCode::Char('q') if self.dirty => {
+                self.open_prompt(PromptKind::ConfirmQuit, "Unsaved changes — quit without saving? (y/n)", "");
+            }
+            KeyCode::Char('q') => return Ok(Flow::Quit),
+            // gg / G : première / dernière ligne, le scroll suit dans clamp_scroll
+            KeyCode::Char('g') if pending_g => self.jump_to_row(0),
+            KeyCode::Char('g') => self.pending_g = true,
+            KeyCode::Char('G') => self.jump_to_row(self.visible.len() - 1),
+            // Le scroll suit le curseur dans clamp_scroll, après la touche
+            KeyCode::Up if self.cursor_pos > 0 => {
+                self.cursor_pos -= 1;
+            }
+            KeyCode::Down if self.cursor_pos + 1 < self.visible.len() => {
+                self.cursor_pos += 1;
+            }
+            // Page entière / demi-page, sans boucler aux extrémités
+            KeyCode::PageDown => self.move_by(available_height, true),
+            KeyCode::PageUp => self.move_by(available_height, false),
+            KeyCode::Char('d') if ctrl => self.move_by(available_height / 2, true),
+            KeyCode::Char('u') if ctrl => self.move_by(available_height / 2, false),
+            KeyCode::Right => {
+                let idx = self.visible[self.cursor_pos];
+                if !self.nodes[idx].is_dir {
+                    self.set_flash("can't expand a file");
+                } else if !self.nodes[idx].expanded {
+                    self.set_chain_expanded(idx, true);
+                }
+            }
+            KeyCode::Left if self.flat => {
+                self.set_flash("no directories in the flat list (F for the tree)");
+            }
+            KeyCode::Left => {
+                let idx = self.visible[self.cursor_pos];
+                if self.nodes[idx].is_dir && self.nodes[idx].expanded {
+                    self.set_chain_expanded(idx, false);
+                } else {
+                    // Aller au parent si possible (en mode compact, la ligne qui le contient)
+                    let mut parent = parent_of(&self.nodes, idx);
+                    while let Some(p) = parent.filter(|&p| self.compact && is_compacted(&self.nodes, p)) {
+                        parent = parent_of(&self.nodes, p);
+                    }
+                    if parent.is_some() {
+                        self.record_jump();
+                    }
+                    jump_to_idx = parent;
+                }
+            }
+            KeyCode::Enter if self.enter_expands_here() => {
+                let idx = self.visible[self.cursor_pos];
+                let expanded = !self.nodes[idx].expanded;
+                self.set_chain_expanded(idx, expanded);
+            }
+            KeyCode::Enter | KeyCode::Char(' ')
+                if self.unignore && !self.nodes[self.visible[self.cursor_pos]].mark =>
+            {
+                self.set_flash("not ignored, nothing to unignore (t switches back to ignore)");
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                let idx = self.visible[self.cursor_pos];
+                let size = subtree_size(&self.nodes, idx);
+                let threshold = self.config.confirm_threshold;
+                if self.nodes[idx].is_dir && threshold > 0 && size > threshold {
+                    let verb = if self.nodes[idx].mark { "unmark" } else { "mark" };
+                    let label = format!("{} {} entries under '{}'? [y/N]", verb, size, self.nodes[idx].name);
+                    self.open_prompt(PromptKind::ConfirmToggle(idx), &label, "");
+                } else {
+                    self.toggle_node(idx);
+                }
+            }
+            KeyCode::Char('r') => {
+                self.reload()?;
+            }
+            KeyCode::Char(']') | KeyCode::Char('[') => {
+                let forward = code == KeyCode::Char(']');
+                match self.find_row(forward, |n| n.is_dir) {
+                    Some(row) => self.cursor_pos = row,
+                    None => self.set_flash("no other directory to jump to"),
+                }
+            }
+            KeyCode::Char('L') => {
+                self.show_log = !self.show_log;
+            }
+            KeyCode::Char('a') if self.flat => {
+                self.set_flash("sibling selection is only available in the tree (F)");
+            }
+            KeyCode::Char('a') => {
+                self.toggle_sibling_files(self.visible[self.cursor_pos]);
+            }
+            KeyCode::Char('+') => {
+                self.open_prompt(PromptKind::AddRule, "Add .gitignore line (again to remove):", "");
+            }
+            KeyCode::Char('A') => {
+                self.open_prompt(PromptKind::MarkOlderThan, "Mark files not modified for (days):", "");
+            }
+            KeyCode::Char('F') => {
+                self.toggle_flat();
+            }
+            KeyCode::Char('O') => {
+                self.toggle_marked_first();
+            }
+            KeyCode::Char('P') => {
+                self.open_passthrough()?;
+            }
+            KeyCode::Char('p') => {
+                self.open_untrack_preview()?;
+            }
+            KeyCode::Char('Z') => {
+                self.focus_current();
+            }
+            KeyCode::Char('E') => self.expand_subtree(true),
+            KeyCode::Char('C') => self.expand_subtree(false),
+            KeyCode::Char('*') => self.toggle_expand_all(),
+            KeyCode::Char('i') => self.invert_selection(self.visible[self.cursor_pos], alt),
+            KeyCode::Char('!') => {
+                self.toggle_pin(self.visible[self.cursor_pos]);
+            }
+            KeyCode::Char('R') => {
+                self.pull_gitignore()?;
+            }
+            KeyCode::Char('f') => {
+                if self.flat {
+                    self.set_flash("directory jump only applies to the tree view");
+                } else {
+                    self.open_prompt(PromptKind::JumpToDir, "Jump to directory:", "");
+                    let dirs = self.nodes.iter().skip(1).filter(|n| n.is_dir).map(|n| format!("{}/", n.rel));
+                    if let Some(prompt) = &mut self.prompt {
+                        prompt.choices = dirs.collect();
+                    }
+                }
+            }
+            KeyCode::Char('W') => {
+                self.open_rule_matches()?;
+            }
+            KeyCode::Char('B') => {
+                self.open_prompt(PromptKind::MarkLargerThan, "Mark directories larger than (e.g. 100M):", "");
+            }
+            KeyCode::Char('/') => {
+                let origin = self.visible[self.cursor_pos];
+                self.open_prompt(PromptKind::Search(origin), "Search:", "");
+            }
+            KeyCode::Char('n') => self.search_next(true),
+            KeyCode::Char('N') => self.search_next(false),
+            KeyCode::Esc => {
+                self.rule_highlight = None;
+            }
+            KeyCode::Char('c') => {
+                self.toggle_compact();
+            }
+            KeyCode::Char('x') => {
+                self.show_permissions = !self.show_permissions;
+            }
+            KeyCode::Char('z') => {
+                self.show_sizes = !self.show_sizes;
+            }
+            KeyCode::Char('h') => self.toggle_hide_ignored(),
+            KeyCode::Char('t') => {
+                self.unignore = !self.unignore;
+                if self.unignore {
+                    self.set_flash("intent: unignore, Space only creates exceptions for ignored entries");
+                } else {
+                    self.set_flash("intent: ignore, Space toggles entries");
+                }
+            }
+            KeyCode::Char('V') => {
+                self.open_status_preview()?;
+            }
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                let idx = self.visible[self.cursor_pos];
+                if !self.nodes[idx].is_dir {
+                    self.set_flash("templates apply to directories");
+                } else if code == KeyCode::Char('y') {
+                    self.open_prompt(PromptKind::SaveTemplate(idx), "Save rules of this directory as template:", "");
+                } else if self.config.templates.is_empty() {
+                    self.set_flash("no template yet, press y on a directory to save one");
+                } else {
+                    let names: Vec<&str> = self.config.templates.keys().map(String::as_str).collect();
+                    let label = format!("Apply template ({}):", names.join(", "));
+                    self.open_prompt(PromptKind::ApplyTemplate(idx), &label, "");
+                }
+            }
+            KeyCode::Char('M') if key.modifiers.contains(KeyModifiers::ALT) => {
+                self.open_prompt(PromptKind::GlobRule, "Add a rule ignoring files matching:", "");
+            }
+            KeyCode::Char('M') => {
+                self.open_prompt(PromptKind::MarkGlob, "Mark files matching:", "");
+            }
+            KeyCode::Char('D') => {
+                self.open_diff()?;
+            }
+            KeyCode::Char('?') => {
+                let lines = HELP_LINES.iter().map(|l| (l.to_string(), None)).collect();
+                self.popup = Some(Popup::new("Keyboard shortcuts", lines));
+            }
+            // Les cibles imbriquées écrivent des .gitignore committés, hors de --exclude
+            KeyCode::Char('T') if self.scan_opts.exclude_file.is_some() => {
+                self.set_flash("T is not available with --exclude");
+            }
+            KeyCode::Char('T') => {
+                self.cycle_target(self.visible[self.cursor_pos]);
+            }
+            KeyCode::Char('I') => {
+                let previous = self.scan_opts.ignore_case;
+                self.scan_opts.ignore_case = !previous;
+                self.rematch(previous)?;
+                if self.scan_opts.ignore_case {
+                    self.set_flash("matching is now case-insensitive");
+                } else {
+                    self.set_flash("matching is now case-sensitive");
+                }
+            }
+            KeyCode::Char('e') => {
+                self.edit_rule(self.visible[self.cursor_pos]);
+            }
+            KeyCode::Char('s') if self.dry_run => {
+                self.dry_run_output = gitignore_files(&self.nodes, &self.root, &self.generate_options())?;
+                self.set_success_flash("Dry run ✓ nothing written");
+                return Ok(Flow::Saved);
+            }
+            KeyCode::Char('s') if self.changed_on_disk() => {
+                self.open_prompt(
+                    PromptKind::ConfirmOverwrite,
+                    ".gitignore changed on disk since it was loaded — r: reload it, o: overwrite, other: cancel",
+                    "",
+                );
+            }
+            KeyCode::Char('s') => return self.save(),
+            _ => {}
+        }
+
+        // Après modification, on recalcule les visibles et on corrige le curseur / scroll
+        self.rebuild_visible();
+        if self.visible.is_empty() {
+            self.cursor_pos = 0;
+            self.scroll_offset = 0;
+        } else {
+            if let Some(target_idx) = jump_to_idx {
+                if let Some(new_row) = self.visible.iter().position(|&i| i == target_idx) {
+                    self.cursor_pos = new_row;
+                }
+            }
+
+            self.clamp_scroll(available_height);
+        }
+
+        Ok(Flow::Continue)
+    }
+
+    fn generate_options(&self) -> GenerateOptions {
+        GenerateOptions {
+            collapse_extensions: self.config.collapse_extensions,
+            extra_rules: self.extra_rules.clone(),
+            exclude_file: self.scan_opts.exclude_file.clone(),
+            seed: self.seed.clone(),
+        }
+    }
+
+    /// Ouvre un popup qui simule l'effet de la sélection (non sauvegardée) sur `git status` :
+    /// fichiers non suivis qui disparaîtraient ou réapparaîtraient, fichiers suivis à désindexer.
+    fn open_status_preview(&mut self) -> Result<()> {
+        let Some(tracked) = &self.tracked else {
+            self.set_flash("no git or jj repository found");
+            return Ok(());
+        };
+
+        // État selon le .gitignore actuellement sur le disque
+        let existing = read_primary(&self.root, &self.scan_opts)?;
+        let mut on_disk = self.nodes.clone();
+        match_gitignore_content(&mut on_disk, &self.root, &existing, self.scan_opts.ignore_case, &mut self.log)?;
+
+        let mut hidden = Vec::new();
+        let mut shown = Vec::new();
+        let mut untrack = Vec::new();
+        for (n, old) in self.nodes.iter().zip(&on_disk) {
+            // Les métadonnées du VCS n'apparaissent jamais dans son status
+            if n.is_dir || n.rel.starts_with(".git/") || n.rel.starts_with(".jj/") {
+                continue;
+            }
+            if tracked.contains(&n.rel) {
+                if n.mark {
+                    untrack.push(n.rel.clone());
+                }
+            } else if n.mark && !old.mark {
+                hidden.push(n.rel.clone());
+            } else if !n.mark && old.mark {
+                shown.push(n.rel.clone());
+            }
+        }
+
+        if hidden.is_empty() && shown.is_empty() && untrack.is_empty() {
+            self.set_flash("the selection doesn't change git status");
+            return Ok(());
+        }
+
+        let mut lines = Vec::new();
+        let sections = [
+            ("Untracked files that would disappear from status (newly ignored):", hidden, style::Color::DarkGrey),
+            ("Untracked files that would appear in status (no longer ignored):", shown, style::Color::Green),
+            ("Tracked files that are ignored and still need untracking:", untrack, style::Color::Red),
+        ];
+        for (title, files, color) in sections {
+            if files.is_empty() {
+                continue;
+            }
+            if !lines.is_empty() {
+                lines.push((String::new(), None));
+            }
+            lines.push((format!("{} ({})", title, files.len()), None));
+            lines.extend(files.into_iter().map(|f| (format!("  {}", f), Some(color))));
+        }
+        self.popup = Some(Popup::new("Simulated git status (nothing is changed)", lines));
+        Ok(())
+    }
+
+    /// Ouvre un popup listant les règles du .gitignore que l'outil ne gère pas
+    fn open_passthrough(&mut self) -> Result<()> {
+        let existing = read_primary(&self.root, &self.scan_opts)?;
+        let rules = passthrough_rules(&self.nodes, &existing);
+        if rules.is_empty() {
+            self.set_flash("every rule of .gitignore is managed by the tree");
+            return Ok(());
+        }
+
+        let width = rules.iter().map(|(line, _)| line.width()).max().unwrap_or(0);
+        let lines = rules
+            .into_iter()
+            .map(|(line, reason)| {
+                let pad = " ".repeat(width - line.width());
+                (format!("{}{}  # {}", line, pad, reason), None)
+            })
+            .collect();
+        self.popup = Some(Popup::new("Rules kept verbatim on save (not managed)", lines));
+        Ok(())
+    }
+
+    /// Ouvre un popup avec les fichiers suivis qu'une sauvegarde maintenant ferait
+    /// désindexer par -j / --git (touche p), sans rien désindexer.
+    fn open_untrack_preview(&mut self) -> Result<()> {
+        let Some(vcs) = Vcs::detect(&self.root) else {
+            self.set_flash("no git or jj repository found");
+            return Ok(());
+        };
+        let tracked = match vcs.tracked_files(&self.root) {
+            Ok(files) => files,
+            Err(e) => {
+                self.set_flash(&format!("{:#}", e));
+                return Ok(());
+            }
+        };
+        let generated = build_gitignore_content(&self.nodes, &self.root, &self.generate_options())?;
+        let ignore_case = self.scan_opts.ignore_case;
+        let mut files = untrack_matches(&self.root, &generated, &tracked, ignore_case, &mut self.log)?;
+        if files.is_empty() {
+            self.set_flash("a save would leave every tracked file tracked");
+            return Ok(());
+        }
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let lines = files
+            .iter()
+            .map(|(file, kind)| {
+                let (label, color) = match kind {
+                    UntrackMatch::Rule => ("rule   ", None),
+                    UntrackMatch::Generic => ("pattern", Some(style::Color::DarkGrey)),
+                };
+                (format!("{}  {}", label, file), color)
+            })
+            .collect();
+        let title = format!("{} tracked file(s) -j/--git would untrack after a save", files.len());
+        self.popup = Some(Popup::new(&title, lines));
+        Ok(())
+    }
+
+    /// Ouvre un popup avec le diff entre le .gitignore de la dernière révision
+    /// committée et celui que produirait une sauvegarde maintenant. Avec --exclude,
+    /// qui n'est jamais committé, on compare au fichier actuel.
+    fn open_diff(&mut self) -> Result<()> {
+        let Some(vcs) = Vcs::detect(&self.root) else {
+            self.set_flash("no git or jj repository found");
+            return Ok(());
+        };
+
+        let existing = read_primary(&self.root, &self.scan_opts)?;
+        let label = primary_label(self.scan_opts.exclude_file.as_deref());
+        let (old, old_label) = if self.scan_opts.exclude_file.is_some() {
+            (existing.clone(), format!("current {}", label))
+        } else {
+            // Pas de .gitignore committé (ou pas encore de commit) : on compare à un fichier vide
+            match vcs.committed_gitignore(&self.root) {
+                Ok(content) => (content, vcs.committed_label().to_string()),
+                Err(e) => {
+                    self.log.push(format!("Diff: {:#}", e));
+                    (String::new(), format!("{} (not committed)", vcs.committed_label()))
+                }
+            }
+        };
+        let generated = generate_gitignore(&self.nodes, &existing, &self.generate_options());
+
+        let diff = unified_diff(&old, &generated, &old_label, &format!("generated {}", label));
+        if diff.is_empty() {
+            self.set_flash(&format!("no changes against {}", old_label));
+            return Ok(());
+        }
+
+        let lines = diff
+            .into_iter()
+            .map(|line| {
+                let color = if line.starts_with("+++") || line.starts_with("---") {
+                    None
+                } else if line.starts_with('+') {
+                    Some(style::Color::Green)
+                } else if line.starts_with('-') {
+                    Some(style::Color::Red)
+                } else if line.starts_with("@@") {
+                    Some(style::Color::Cyan)
+                } else {
+                    None
+                };
+                (line, color)
+            })
+            .collect();
+        self.popup = Some(Popup::new(&format!("Diff against {}", old_label), lines));
+        Ok(())
+    }
+
+    /// Défilement et fermeture du popup ouvert
+    fn handle_popup_key(&mut self, code: KeyCode, available_height: usize) {
+        let Some(popup) = &mut self.popup else {
+            return;
+        };
+        // Le cadre prend une ligne en haut et une en bas
+        let page = available_height.saturating_sub(2).max(1);
+        let max_scroll = popup.lines.len().saturating_sub(page);
+        let max_hscroll = popup
+            .lines
+            .iter()
+            .map(|(l, _)| l.width())
+            .max()
+            .unwrap_or(0)
+            .saturating_sub(1);
+
+        match code {
+            KeyCode::Esc | KeyCode::Char('q') => self.popup = None,
+            KeyCode::Left => popup.hscroll = popup.hscroll.saturating_sub(HSCROLL_STEP),
+            KeyCode::Right => popup.hscroll = (popup.hscroll + HSCROLL_STEP).min(max_hscroll),
+            KeyCode::Up => popup.scroll = popup.scroll.saturating_sub(1),
+            KeyCode::Down => popup.scroll = (popup.scroll + 1).min(max_scroll),
+            KeyCode::PageUp => popup.scroll = popup.scroll.saturating_sub(page),
+            KeyCode::PageDown => popup.scroll = (popup.scroll + page).min(max_scroll),
+            KeyCode::Home => popup.scroll = 0,
+            KeyCode::End => popup.scroll = max_scroll,
+            _ => {}
+        }
+    }
+
+    /// Ramène le curseur dans la liste des visibles et ajuste le scroll
+    /// pour que la ligne du curseur soit affichée.
+    fn clamp_scroll(&mut self, available_height: usize) {
+        (self.cursor_pos, self.scroll_offset) =
+            clamp_cursor_and_scroll(self.cursor_pos, self.scroll_offset, self.visible.len(), available_height);
+    }
+
+    /// Souris : un clic amène le curseur sur la ligne, un clic sur sa case "[ ]" la
+    /// coche comme Entrée ; la molette agit comme les flèches (listes du popup comprises).
+    fn handle_mouse(&mut self, mouse: MouseEvent, available_height: usize) -> Result<Flow> {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        match mouse.kind {
+            _ if self.prompt.is_some() => Ok(Flow::Continue),
+            MouseEventKind::ScrollUp => self.handle_key(key(KeyCode::Up), available_height),
+            MouseEventKind::ScrollDown => self.handle_key(key(KeyCode::Down), available_height),
+            MouseEventKind::Down(MouseButton::Left) if self.popup.is_none() => {
+                self.rebuild_visible();
+                let Some(row) = row_at(mouse.row, self.scroll_offset, self.visible.len(), available_height) else {
+                    return Ok(Flow::Continue);
+                };
+                self.cursor_pos = row;
+                let col = mark_column(self, self.visible[row]);
+                if (col..col + self.theme.unmarked.width()).contains(&(mouse.column as usize)) {
+                    return self.handle_key(key(KeyCode::Char(' ')), available_height);
+                }
+                Ok(Flow::Continue)
+            }
+            _ => Ok(Flow::Continue),
+        }
+    }
+
+    /// Prochaine ligne visible (après le curseur, ou avant si `forward` est faux)
+    /// dont le node vérifie `pred`, en bouclant aux extrémités.
+    fn find_row(&self, forward: bool, pred: impl Fn(&Node) -> bool) -> Option<usize> {
+        let len = self.visible.len();
+        (1..len)
+            .map(|step| {
+                if forward {
+                    (self.cursor_pos + step) % len
+                } else {
+                    (self.cursor_pos + len - step) % len
+                }
+            })
+            .find(|&row| pred(&self.nodes[self.visible[row]]))
+    }
+}
+
+/// Touches qui modifient la sélection ou le .gitignore, refusées avec --strict
+fn edits_selection(code: KeyCode) -> bool {
+    matches!(
+        code,
+        KeyCode::Enter
+            | KeyCode::Char(' ' | 'i' | 'a' | 'M' | 'A' | 'e' | 'T' | 'I' | '+' | 'Y' | '!' | 'R' | 'B' | 's')
+    )
+}
+
+/// --template : fusionne les modèles `names` avec le fichier édité et refait le
+/// matching de l'arbre avec le résultat, ce qui marque ce qu'ils ignorent.
+/// Renvoie les lignes ajoutées (voir GenerateOptions::seed).
+fn apply_templates(
+    nodes: &mut [Node],
+    root: &Path,
+    opts: &ScanOptions,
+    names: &[String],
+    log: &mut Vec<String>,
+) -> Result<String> {
+    if names.is_empty() {
+        return Ok(String::new());
+    }
+    let existing = read_primary(root, opts)?;
+    let mut seed = String::new();
+    for name in names {
+        // Un second modèle ne répète pas les règles du premier
+        let lines = template_seed(name, &merge_seed(&existing, &seed))?;
+        seed = merge_seed(&seed, &lines);
+    }
+    match_tree(nodes, root, &merge_seed(&existing, &seed), opts, log)?;
+    recompute_cpt_exception(nodes);
+    recompute_cpt_mixed_marks(nodes);
+    Ok(seed)
+}
+
+/// Applique les patterns de --mark (voir mark_matching)
+fn apply_mark_patterns(nodes: &mut [Node], root: &Path, patterns: &[String]) -> Result<()> {
+    let all: Vec<usize> = (0..nodes.len()).collect();
+    for pattern in patterns {
+        let count = mark_matching(nodes, root, pattern, true, &all).context("--mark")?;
+        debug!("--mark {:?}: {} entries marked", pattern, count);
+    }
+    Ok(())
+}
+
+/// --print : chargement, --mark puis génération, comme une sauvegarde, mais le
+/// contenu part sur stdout (et seulement lui, pour pouvoir le rediriger) sans
+/// toucher au .gitignore.
+fn print_generated(
+    root: &Path,
+    opts: &ScanOptions,
+    config: &Config,
+    mark_patterns: &[String],
+    templates: &[String],
+) -> Result<()> {
+    let mut log = Vec::new();
+    let mut nodes = load_tree(root, opts, &mut log)?;
+    let seed = apply_templates(&mut nodes, root, opts, templates, &mut log)?;
+    for entry in &log {
+        debug!("{}", entry);
+    }
+    apply_mark_patterns(&mut nodes, root, mark_patterns)?;
+
+    let options = GenerateOptions {
+        collapse_extensions: config.collapse_extensions,
+        extra_rules: Vec::new(),
+        exclude_file: opts.exclude_file.clone(),
+        seed,
+    };
+    let content = build_gitignore_content(&nodes, root, &options)?;
+    let mut out = stdout();
+    out.write_all(content.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Logger minimal de --log : une ligne horodatée par entrée, dans un fichier
+/// (jamais sur le terminal, qui appartient à l'interface).
+struct FileLogger {
+    file: Mutex<fs::File>,
+}
+
+impl log::Log for FileLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{}.{:03} {:<5} {}",
+                now.as_secs(),
+                now.subsec_millis(),
+                record.level(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Installe le FileLogger sur `path` (ajout en fin de fichier).
+fn init_file_logger(path: &Path) -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Opening log file {:?}", path))?;
+    log::set_boxed_logger(Box::new(FileLogger {
+        file: Mutex::new(file),
+    }))
+    .context("Installing the logger")?;
+    log::set_max_level(log::LevelFilter::Debug);
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    // Sous-commande non interactive : git-ignore rm <path>...
+    if args.get(1).map(String::as_str) == Some("rm") {
+        return remove_paths(Path::new("."), &args[2..]);
+    }
+
+    let mut root_path = ".";
+    // -j / --git : VCS dont on désindexe les fichiers ignorés après la sauvegarde
+    let mut untrack: Option<Vcs> = None;
+    let mut dry_run = false;
+    let mut backup = true;
+    let mut interactive = false;
+    let mut confirm = false;
+    let mut since: Option<String> = None;
+    let mut config_path: Option<PathBuf> = None;
+    let mut scan_opts = ScanOptions::default();
+    let mut lazy = false;
+    let mut filter = ViewFilter::default();
+    let mut log_path: Option<PathBuf> = None;
+    let mut expand_depth: Option<usize> = None;
+    let mut stats = false;
+    let mut list_untrack = false;
+    let mut print = false;
+    let mut exclude = false;
+    let mut templates: Vec<String> = Vec::new();
+    let mut mark_patterns: Vec<String> = Vec::new();
+    
+    // Parse des arguments
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-j" | "--jj" | "--git" => {
+                let vcs = if args[i] == "--git" { Vcs::Git } else { Vcs::Jj };
+                if untrack.is_some_and(|v| v != vcs) {
+                    bail!("-j/--jj and --git can't be combined");
+                }
+                untrack = Some(vcs);
+            }
+            "-n" | "--dry-run" => {
+                dry_run = true;
+            }
+            "--no-backup" => {
+                backup = false;
+            }
+            "-i" | "--interactive" => {
+                interactive = true;
+            }
+            "--confirm" => {
+                confirm = true;
+            }
+            "-a" | "--all" => {
+                scan_opts.all = true;
+            }
+            "--no-hidden" => {
+                scan_opts.no_hidden = true;
+            }
+            "--lazy" => {
+                lazy = true;
+            }
+            "--exclude" => {
+                exclude = true;
+            }
+            "--template" => {
+                i += 1;
+                let Some(name) = args.get(i) else {
+                    bail!("--template expects a template name (e.g. Rust, Node, Python)");
+                };
+                templates.push(name.clone());
+            }
+            "--ignore-case" => {
+                scan_opts.ignore_case = true;
+            }
+            "--strict" => {
+                scan_opts.strict = true;
+            }
+            "--stats" => {
+                stats = true;
+            }
+            "--list-untrack" => {
+                list_untrack = true;
+            }
+            "--print" => {
+                print = true;
+            }
+            "--also" => {
+                i += 1;
+                let Some(path) = args.get(i) else {
+                    bail!("--also expects an ignore file path");
+                };
+                scan_opts.also.push(PathBuf::from(path));
+            }
+            "--mark" => {
+                i += 1;
+                let Some(pattern) = args.get(i) else {
+                    bail!("--mark expects a pattern");
+                };
+                mark_patterns.push(pattern.clone());
+            }
+            "--expand-depth" => {
+                i += 1;
+                let Some(depth) = args.get(i).and_then(|d| d.parse().ok()) else {
+                    bail!("--expand-depth expects a number");
+                };
+                expand_depth = Some(depth);
+            }
+            "--log" => {
+                i += 1;
+                let Some(path) = args.get(i) else {
+                    bail!("--log expects a file path");
+                };
+                log_path = Some(PathBuf::from(path));
+            }
+            "--config" => {
+                i += 1;
+                let Some(path) = args.get(i) else {
+                    bail!("--config expects a file path");
+                };
+                config_path = Some(PathBuf::from(path));
+            }
+            "--since" => {
+                i += 1;
+                let Some(rev) = args.get(i) else {
+                    bail!("--since expects a commit or ref");
+                };
+                since = Some(rev.clone());
+            }
+            "--ext" => {
+                i += 1;
+                let Some(list) = args.get(i) else {
+                    bail!("--ext expects a comma-separated list of extensions");
+                };
+                filter.extensions = Some(parse_extensions(list));
+            }
+            arg if !arg.starts_with('-') => {
+                root_path = arg;
+            }
+            _ => {
+                bail!("Unknown argument: {}", args[i]);
+            }
+        }
+        i += 1;
+    }
+    
+    if interactive && untrack.is_none() {
+        bail!("--interactive only applies to the untrack step of -j/--jj or --git");
+    }
+    if confirm && untrack.is_none() {
+        bail!("--confirm only applies to the untrack step of -j/--jj or --git");
+    }
+    if scan_opts.strict && (print || untrack.is_some() || !mark_patterns.is_empty() || !templates.is_empty()) {
+        bail!("--strict is read-only: it can't be combined with --print, --mark, --template, -j or --git");
+    }
+
+    if let Some(path) = &log_path {
+        init_file_logger(path)?;
+    }
+    debug!("git-ignore started with {:?}", &args[1..]);
+
+    let root = Path::new(root_path);
+
+    if !root.exists() || !root.is_dir() {
+        bail!("Path '{}' does not exist or is not a directory", root_path);
+    }
+
+    if let Some(rev) = &since {
+        let Some(vcs) = Vcs::detect(root) else {
+            bail!("--since needs a git or jj repository");
+        };
+        let added = vcs.added_since(root, rev).context("--since")?;
+        debug!("--since {}: {} new file(s)", rev, added.len());
+        filter.since = Some(added);
+    }
+
+    // --exclude : les règles vont dans .git/info/exclude, celles du .gitignore
+    // partagé restent visibles, en lecture seule, comme un fichier --also
+    if exclude {
+        scan_opts.exclude_file = Some(git_exclude_file(root)?);
+        let shared = root.join(".gitignore");
+        if shared.is_file() {
+            scan_opts.also.insert(0, shared);
+        }
+    }
+    let edited = primary_label(scan_opts.exclude_file.as_deref());
+
+    // Ce que git ignore pour tout l'utilisateur, signalé à part dans l'arbre
+    if Vcs::detect(root).is_some() {
+        scan_opts.excludes_file = global_excludes_file(root);
+        debug!("global excludes: {:?}", scan_opts.excludes_file);
+    }
+
+    // Statistiques à la place de l'interface (utilisable sans terminal)
+    if stats {
+        return print_stats(root, &scan_opts);
+    }
+
+    // Aperçu de l'untrack, sans toucher au dépôt
+    if list_untrack {
+        return print_untrack_list(root, &scan_opts);
+    }
+
+    // Contenu généré sur stdout, sans interface ni écriture du fichier
+    if print {
+        let config = Config::load(config_path)?;
+        return print_generated(root, &scan_opts, &config, &mark_patterns, &templates);
+    }
+
+    // Sans terminal, read() échouerait ou boucle sur EOF : on refuse proprement
+    if !io::stdin().is_terminal() {
+        bail!(
+            "git-ignore needs an interactive terminal (stdin is not a TTY). \
+             Use `git-ignore rm <path>...` to remove rules from scripts."
+        );
+    }
+
+    let config = Config::load(config_path)?;
+    // Seule l'interface sait lire un répertoire à son ouverture : --print, --stats, etc.
+    // ont besoin de tout l'arbre
+    scan_opts.lazy = lazy;
+    // Tenu jusqu'à la fin, untrack de -j compris
+    let _lock = LockGuard::acquire(root)?;
+    let mut log = Vec::new();
+    // Ctrl+C annule le parcours initial (le terminal n'est pas encore en mode brut)
+    let cancel = ScanCancel::install()?;
+    let initial_scan = ScanOptions { cancel: Some(cancel.flag()), ..scan_opts.clone() };
+    let mut nodes = match load_tree(root, &initial_scan, &mut log) {
+        Ok(nodes) => nodes,
+        Err(e) if e.is::<ScanCancelled>() => {
+            eprintln!("Scan cancelled: `{}` was not changed.", edited);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    cancel.finish();
+    // L'option de la ligne de commande l'emporte sur la configuration
+    let expand_depth = expand_depth.or(config.expand_depth);
+    // --lazy : les répertoires dépliés au départ doivent être lus
+    if let Some(depth) = expand_depth {
+        load_dirs_where(&mut nodes, root, &initial_scan, &mut log, |n| n.depth < depth)?;
+    }
+    // --template : à relire avant de sauvegarder, comme le reste de la sélection
+    let seed = apply_templates(&mut nodes, root, &scan_opts, &templates, &mut log)?;
+    // --mark : sélection préparée, modifiable ensuite comme n'importe quel mark
+    apply_mark_patterns(&mut nodes, root, &mark_patterns)?;
+    if let Some(depth) = expand_depth {
+        expand_to_depth(&mut nodes, depth);
+    }
+
+    let repo_name = root
+        .canonicalize()
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| root_path.to_string());
+    let guard = TerminalGuard::enter(&format!("gitignore-tui — {}", repo_name))?;
+
+    let mut app = App::new(root, config, scan_opts, filter, nodes, log);
+    app.dry_run = dry_run;
+    app.backup = backup;
+    app.theme = Theme::from_env();
+    app.load_tracked();
+    app.load_gitignore_state();
+    if !templates.is_empty() {
+        let added = seed.lines().filter(|l| !l.starts_with('#') && !l.is_empty()).count();
+        app.set_flash(&format!("template: {} new rule(s) to review, saved with s", added));
+        app.dirty = added > 0;
+        app.seed = seed;
+    }
+    let mut screen = Screen::new();
+    render(&app, &mut screen)?;
+
+    let saved = loop {
+        // Boucle à base de poll : permet de faire expirer le message transitoire
+        if !event::poll(POLL_INTERVAL)? {
+            if app.expire_flash() {
+                render(&app, &mut screen)?;
+            }
+            continue;
+        }
+
+        match read()? {
+            // Déplacements de la souris : rien à redessiner, le message reste affiché
+            Event::Mouse(m) if matches!(m.kind, MouseEventKind::Moved | MouseEventKind::Drag(_) | MouseEventKind::Up(_)) => {}
+            event @ (Event::Key(_) | Event::Mouse(_)) => {
+                // Le message précédent disparaît à la touche suivante
+                app.flash = None;
+                let flow = match event {
+                    Event::Mouse(m) => app.handle_mouse(m, viewport_rows(&app)?)?,
+                    Event::Key(k) => app.handle_key(k, viewport_rows(&app)?)?,
+                    _ => Flow::Continue,
+                };
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Quit => break false,
+                    Flow::Saved => {
+                        // Le temps de voir la confirmation, sauf si une touche arrive avant
+                        render(&app, &mut screen)?;
+                        if event::poll(SAVED_DELAY)? {
+                            read()?;
+                        }
+                        break true;
+                    }
+                }
+                render(&app, &mut screen)?;
+            }
+            Event::Resize(_, _) => {
+                app.rebuild_visible();
+                screen.invalidate();
+                render(&app, &mut screen)?;
+            }
+            _ => {}
+        }
+    };
+
+    drop(guard);
+
+    if saved && dry_run {
+        print_dry_run(root, &app.dry_run_output);
+        println!("Dry run: `{}` was not changed.", edited);
+    } else if saved {
+        println!("Saved ✓ The `{}` file has been updated in '{}'.", edited, root_path);
+        for warning in &app.save_warnings {
+            eprintln!("Warning: {}", warning);
+        }
+    } else {
+        println!("Quit without saving: `{}` was not changed.", edited);
+    }
+
+    if saved && !dry_run {
+        if let Some(command) = &app.config.on_save {
+            run_on_save_hook(command, &primary_file(root, app.scan_opts.exclude_file.as_deref()))?;
+        }
+    }
+
+    // Avec -j ou --git, on désindexe les fichiers ignorés
+    if let Some(vcs) = untrack {
+        // --confirm ou confirm_untrack dans la configuration
+        let confirm = confirm || app.config.confirm_untrack;
+        println!("\nChecking tracked files with {}...", vcs.program());
+        if let Err(e) = untrack_ignored_files(root, vcs, &app.scan_opts, dry_run, interactive, confirm) {
+            eprintln!("Error while untracking files: {}", e);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Mode plein écran de l'interface (raw mode, écran alternatif, titre de la fenêtre).
+/// Le terminal est remis en état au drop, donc aussi sur erreur ou panic.
+struct TerminalGuard;
+
+/// Séquences xterm qui empilent / dépilent le titre de la fenêtre : le titre
+/// précédent revient à la sortie (les terminaux qui ne les gèrent pas les ignorent).
+const PUSH_TITLE: &str = "\x1b[22;0t";
+const POP_TITLE: &str = "\x1b[23;0t";
+
+/// Vrai tant que l'interface occupe le terminal (voir restore_terminal)
+static TERMINAL_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Rend le terminal au shell. Appelé par le hook de panic, avant que le message soit
+/// affiché (sinon il partirait dans l'écran alternatif), puis par le drop : seul le
+/// premier appel agit.
+fn restore_terminal() {
+    if !TERMINAL_ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let _ = execute!(
+        stdout(),
+        cursor::Show,
+        DisableMouseCapture,
+        terminal::EnableLineWrap,
+        terminal::LeaveAlternateScreen,
+        style::Print(POP_TITLE)
+    );
+    let _ = disable_raw_mode();
+}
+
+impl TerminalGuard {
+    fn enter(title: &str) -> Result<TerminalGuard> {
+        enable_raw_mode()?;
+        TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
+        let guard = TerminalGuard;
+
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+
+        execute!(
+            stdout(),
+            style::Print(PUSH_TITLE),
+            terminal::SetTitle(title),
+            terminal::EnterAlternateScreen,
+            // Une ligne trop longue (pied de page) est coupée au lieu de faire défiler l'écran
+            terminal::DisableLineWrap,
+            // Clics et molette (handle_mouse)
+            EnableMouseCapture,
+            cursor::Hide
+        )?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Au-delà, un verrou dont on ne peut pas vérifier le processus est considéré abandonné
+const STALE_LOCK_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Verrou tenu jusqu'au drop, donc retiré aussi sur erreur ou panic.
+struct LockGuard {
+    path: PathBuf,
+}
+
+impl LockGuard {
+    /// Crée le fichier de verrou (création exclusive) avec notre PID.
+    /// Un verrou laissé par un processus disparu est remplacé ; sinon on refuse de démarrer.
+    fn acquire(root: &Path) -> Result<LockGuard> {
+        let path = root.join(LOCK_FILE);
+        for _ in 0..2 {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id()).context("Writing the lock file")?;
+                    return Ok(LockGuard { path });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let pid = fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|c| c.trim().parse::<u32>().ok());
+                    if !lock_is_stale(&path, pid) {
+                        bail!(
+                            "Another gitignore-tui{} is already editing '{}'. \
+                             If it is not running anymore, delete {}.",
+                            pid.map(|p| format!(" (pid {})", p)).unwrap_or_default(),
+                            root.display(),
+                            path.display()
+                        );
+                    }
+                    debug!("removing stale lock {:?} (pid {:?})", path, pid);
+                    fs::remove_file(&path).context("Removing a stale lock file")?;
+                }
+                Err(e) => return Err(e).context(format!("Creating {}", path.display())),
+            }
+        }
+        bail!("Could not acquire {}", path.display())
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Verrou abandonné : son processus n'existe plus, ou, faute de pouvoir le vérifier,
+/// le fichier est plus vieux que STALE_LOCK_AGE.
+fn lock_is_stale(path: &Path, pid: Option<u32>) -> bool {
+    if let Some(alive) = pid.and_then(process_alive) {
+        return !alive;
+    }
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|age| age > STALE_LOCK_AGE)
+}
+
+/// Le processus `pid` tourne-t-il encore ? None si on ne sait pas le dire.
+#[cfg(unix)]
+fn process_alive(pid: u32) -> Option<bool> {
+    if Path::new("/proc/self").exists() {
+        return Some(Path::new("/proc").join(pid.to_string()).exists());
+    }
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .ok()
+        .map(|s| s.success())
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> Option<bool> {
+    None
+}
+
+/// Répertoire temporaire des tests, partagé avec tests/ et benches/
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gitignore_tui::{compute_cpt_exception, compute_cpt_mixed_marks};
+    use crate::common::TempDir;
+
+    fn remove(content: &str, paths: &[&str]) -> String {
+        let to_remove = build_to_remove(paths.iter().map(|p| normalize_entry(p)));
+        join_lines(&retain_unmanaged_lines(content, &to_remove))
+    }
+
+    /// Construit un arbre en pré-ordre à partir de chemins relatifs ("dir/" = répertoire),
+    /// précédé du noeud racine virtuel.
+    fn tree(root: &Path, entries: &[&str]) -> Vec<Node> {
+        let mut nodes = vec![Node {
+            path: root.to_path_buf(),
+            rel: String::new(),
+            name: "/".to_string(),
+            is_dir: true,
+            depth: 0,
+            expanded: true,
+            mode: Mode::N,
+            mark: false,
+            cpt_exception: 0,
+            cpt_mixed_marks: 0,
+            generic_mark: false,
+            override_line: None,
+            mtime: None,
+            permissions: None,
+            size: 0,
+            target: None,
+            pinned: false,
+            also: None,
+            global: false,
+            unreadable: false,
+            loaded: true,
+        }];
+        for entry in entries {
+            let is_dir = entry.ends_with('/');
+            let rel = entry.trim_end_matches('/');
+            nodes.push(Node {
+                path: root.join(rel),
+                rel: rel.to_string(),
+                name: rel.rsplit('/').next().unwrap().to_string(),
+                is_dir,
+                depth: rel.split('/').count(),
+                expanded: false,
+                mode: Mode::N,
+                mark: false,
+                cpt_exception: 0,
+                cpt_mixed_marks: 0,
+                generic_mark: false,
+                override_line: None,
+                mtime: None,
+                permissions: None,
+                size: 0,
+                target: None,
+                pinned: false,
+                also: None,
+                global: false,
+                unreadable: false,
+                loaded: true,
+            });
+        }
+        nodes
+    }
+
+    /// Marque les chemins donnés comme le ferait Enter sur des fichiers.
+    fn mark(root: &Path, nodes: &mut [Node], rels: &[&str]) {
+        for n in nodes.iter_mut() {
+            if rels.iter().any(|r| n.path == root.join(r)) {
+                mark_file(n);
+            }
+        }
+        recompute_cpt_exception(nodes);
+        recompute_cpt_mixed_marks(nodes);
+    }
+
+    /// Passe les nodes donnés en exception (mode E, non marqués).
+    fn except(root: &Path, nodes: &mut [Node], rels: &[&str]) {
+        for n in nodes.iter_mut() {
+            if rels.iter().any(|r| n.path == root.join(r)) {
+                n.mode = Mode::E;
+                n.mark = false;
+            }
+        }
+    }
+
+    #[test]
+    fn parents_follow_pre_order_depths() {
+        let root = Path::new("/p");
+        let nodes = tree(root, &["a/", "a/b/", "a/b/f", "a/g", "c/", "c/h"]);
+        assert_eq!(
+            parent_indices(&nodes),
+            vec![None, Some(0), Some(1), Some(2), Some(1), Some(0), Some(5)]
+        );
+    }
+
+    #[test]
+    fn exception_count_is_not_double_counted_through_nested_dirs() {
+        let root = Path::new("/p");
+        let mut nodes = tree(root, &["a/", "a/b/", "a/b/f", "a/b/g"]);
+        except(root, &mut nodes, &["a/b/f"]);
+        assert_eq!(compute_cpt_exception(&nodes), vec![1, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn counters_when_a_dir_ends_the_slice() {
+        // Le dernier descendant de "b" est le dernier élément du vecteur
+        let root = Path::new("/p");
+        let mut nodes = tree(root, &["a", "b/", "b/c/", "b/c/d"]);
+        except(root, &mut nodes, &["b/c/d"]);
+        mark(root, &mut nodes, &["a"]);
+        assert_eq!(compute_cpt_exception(&nodes), vec![1, 0, 1, 1, 1]);
+        assert_eq!(compute_cpt_mixed_marks(&nodes), vec![1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn counters_stay_within_sibling_dirs() {
+        let root = Path::new("/p");
+        let mut nodes = tree(root, &["a/", "a/x", "a/y", "b/", "b/z"]);
+        nodes[1].mark = true;
+        nodes[2].mark = true;
+        nodes[2].mode = Mode::C;
+        except(root, &mut nodes, &["b/z"]);
+        // "a" marqué avec un enfant non marqué, "b" sans enfant marqué
+        assert_eq!(compute_cpt_mixed_marks(&nodes), vec![2, 1, 0, 0, 0, 0]);
+        assert_eq!(compute_cpt_exception(&nodes), vec![1, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn counters_work_on_a_partial_tree() {
+        // Sous-arbre "a" seul, sans le noeud racine
+        let root = Path::new("/p");
+        let mut nodes = tree(root, &["a/", "a/x", "a/y/", "a/y/z", "b"]);
+        nodes[1].mark = true;
+        except(root, &mut nodes, &["a/y/z"]);
+        let sub = &nodes[1..5];
+        assert_eq!(compute_cpt_exception(sub), vec![1, 0, 1, 1]);
+        assert_eq!(compute_cpt_mixed_marks(sub), vec![3, 0, 0, 0]);
+    }
+
+    /// Descend jusqu'en bas de la liste en corrigeant le scroll après chaque touche,
+    /// comme handle_key ; renvoie (curseur, scroll) final.
+    fn scroll_to_bottom(len: usize, height: usize) -> (usize, usize) {
+        let (mut cursor, mut scroll) = (0, 0);
+        while cursor + 1 < len {
+            cursor += 1;
+            (cursor, scroll) = clamp_cursor_and_scroll(cursor, scroll, len, height);
+            assert!(scroll <= len.saturating_sub(height));
+            assert!(cursor >= scroll && cursor < scroll + height);
+        }
+        (cursor, scroll)
+    }
+
+    #[test]
+    fn scroll_when_content_is_shorter_than_viewport() {
+        assert_eq!(scroll_to_bottom(3, 10), (2, 0));
+        // Un scroll resté d'un contenu plus long est ramené à 0
+        assert_eq!(clamp_cursor_and_scroll(2, 5, 3, 10), (2, 0));
+        assert_eq!(clamp_cursor_and_scroll(7, 0, 3, 10), (2, 0));
+    }
+
+    #[test]
+    fn scroll_when_content_exactly_fills_viewport() {
+        assert_eq!(scroll_to_bottom(10, 10), (9, 0));
+        assert_eq!(clamp_cursor_and_scroll(9, 1, 10, 10), (9, 0));
+    }
+
+    #[test]
+    fn scroll_when_content_is_just_over_viewport() {
+        assert_eq!(scroll_to_bottom(11, 10), (10, 1));
+        // En remontant, le scroll suit le curseur
+        assert_eq!(clamp_cursor_and_scroll(0, 1, 11, 10), (0, 0));
+        assert_eq!(clamp_cursor_and_scroll(5, 1, 11, 10), (5, 1));
+    }
+
+    #[test]
+    fn scroll_with_empty_list_or_no_room() {
+        assert_eq!(clamp_cursor_and_scroll(4, 2, 0, 10), (0, 0));
+        assert_eq!(clamp_cursor_and_scroll(3, 0, 5, 0), (3, 3));
+    }
+
+    #[test]
+    fn parse_drops_trailing_spaces_unless_escaped() {
+        let rules = parse_gitignore_content("/build   \n/notes\\ \n/two\\ \\ \n");
+        let patterns: Vec<&str> = rules.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, vec!["build", "notes ", "two  "]);
+    }
+
+    #[test]
+    fn generate_escapes_a_file_name_ending_in_a_space() {
+        let root = Path::new("/p");
+        let mut nodes = tree(root, &["notes ", "notes"]);
+        mark(root, &mut nodes, &["notes "]);
+        assert_eq!(generate_gitignore(&nodes, "", &GenerateOptions::default()), "/notes\\ \n");
+
+        // La règle échappée déjà présente est reconnue : pas de doublon, et elle
+        // disparaît si le fichier n'est plus marqué
+        let rules = parse_gitignore_content("/notes\\ \n");
+        let mut nodes = tree(root, &["notes ", "notes"]);
+        apply_rules_to_nodes(&mut nodes, &rules, false);
+        assert!(nodes[1].mark && !nodes[2].mark);
+        assert_eq!(generate_gitignore(&nodes, "/notes\\ \n", &GenerateOptions::default()), "/notes\\ \n");
+        unmark_file(&mut nodes[1]);
+        assert_eq!(generate_gitignore(&nodes, "/notes\\ \n", &GenerateOptions::default()), "");
+    }
+
+    #[test]
+    fn generate_writes_each_rule_once() {
+        let root = Path::new("/p");
+        let mut nodes = tree(root, &["a.log", "b.txt"]);
+        mark(root, &mut nodes, &["a.log"]);
+        let existing = "# logs\n*.tmp\n*.tmp\n\n# logs\n/a.log\n/a.log\n";
+        let opts = GenerateOptions {
+            extra_rules: vec!["*.tmp".to_string(), "/a.log".to_string(), "*.bak".to_string()],
+            ..Default::default()
+        };
+        // Les lignes du fichier restent telles quelles, seules les règles générées
+        // ("/a.log" réécrite une fois) et libres en double disparaissent
+        assert_eq!(
+            generate_gitignore(&nodes, existing, &opts),
+            "# logs\n*.tmp\n*.tmp\n\n# logs\n/a.log\n*.bak\n"
+        );
+    }
+
+    #[test]
+    fn dedup_keeps_a_copy_that_follows_an_opposite_rule() {
+        let lines = |text: &str| -> Vec<String> { text.lines().map(String::from).collect() };
+        // La 2e copie de "*.log" ré-ignore keep.log : la retirer changerait ce que git ignore
+        let existing = "*.log\n!keep.log\n*.log\n";
+        assert_eq!(generate_gitignore(&[], existing, &GenerateOptions::default()), existing);
+
+        let kept = lines("*.log\n!keep.log");
+        assert_eq!(dedup_rules(&kept, lines("*.log\n!keep.log\n*.bak")), lines("*.log\n!keep.log\n*.bak"));
+        assert_eq!(dedup_rules(&kept, lines("!keep.log\n*.bak\n*.bak")), lines("*.bak"));
+        assert_eq!(dedup_rules(&[], lines("/a\n# a\n/b\n# a\n/a")), lines("/a\n# a\n/b\n# a"));
+    }
+
+    #[test]
+    fn size_column_shows_directory_totals_and_shifts_the_marks() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 << 20), "3.0 MB");
+        assert_eq!(format_size(5 << 30), "5.0 GB");
+        // La valeur la plus large avant l'unité suivante tient dans la colonne
+        assert_eq!(format_size((1 << 30) - 1).width(), SIZE_COLUMN_WIDTH);
+
+        let mut nodes = tree(Path::new("/repo"), &["a/", "a/b/", "a/b/x", "a/y", "z.txt"]);
+        for (n, size) in nodes.iter_mut().zip([0, 0, 0, 1000, 2000, 4096]) {
+            n.size = size;
+        }
+        // Un répertoire vaut la somme de tout son contenu, la racine celle de l'arbre
+        assert_eq!(compute_sizes(&nodes), [7096, 3000, 1000, 1000, 2000, 4096]);
+
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut app = App::new(Path::new("/repo"), Config::default(), ScanOptions::default(), ViewFilter::default(), nodes, Vec::new());
+        assert_eq!(app.sizes[1], 3000);
+        let before: Vec<usize> = (0..app.nodes.len()).map(|i| mark_column(&app, i)).collect();
+        app.handle_key(key(KeyCode::Char('z')), 10).unwrap();
+        assert!(app.show_sizes);
+        // La colonne décale chaque case d'autant, quelle que soit la profondeur
+        for (i, col) in before.into_iter().enumerate() {
+            assert_eq!(mark_column(&app, i), col + SIZE_COLUMN_WIDTH + 1);
+        }
+    }
+
+    #[test]
+    fn space_toggles_and_enter_expands_with_enter_expands() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let new_app = |config: Config, strict: bool| {
+            let nodes = tree(Path::new("/repo"), &["a/", "a/x", "b.txt"]);
+            let opts = ScanOptions { strict, ..Default::default() };
+            App::new(Path::new("/repo"), config, opts, ViewFilter::default(), nodes, Vec::new())
+        };
+        assert!(Config::parse("enter_expands = true\n").unwrap().enter_expands);
+        assert!(!Config::parse("").unwrap().enter_expands);
+        assert!(Config::parse("enter_expands = maybe\n").is_err());
+
+        // Par défaut, Enter et Space basculent, répertoires compris
+        let mut app = new_app(Config::default(), false);
+        app.cursor_pos = 2;
+        app.handle_key(key(KeyCode::Char(' ')), 10).unwrap();
+        assert!(app.nodes[3].mark);
+        app.cursor_pos = 1;
+        app.handle_key(key(KeyCode::Enter), 10).unwrap();
+        assert!(app.nodes[1].mark && !app.nodes[1].expanded);
+
+        // enter_expands : Enter déplie le répertoire, Space le bascule, Enter bascule un fichier
+        let config = Config { enter_expands: true, ..Config::default() };
+        let mut app = new_app(config.clone(), false);
+        app.cursor_pos = 1;
+        app.handle_key(key(KeyCode::Enter), 10).unwrap();
+        assert!(app.nodes[1].expanded && !app.nodes[1].mark);
+        app.handle_key(key(KeyCode::Char(' ')), 10).unwrap();
+        assert!(app.nodes[1].mark);
+        app.cursor_pos = 3;
+        app.handle_key(key(KeyCode::Enter), 10).unwrap();
+        assert!(app.nodes[3].mark);
+
+        // --strict : déplier reste permis, basculer non
+        let mut app = new_app(config, true);
+        app.cursor_pos = 1;
+        app.handle_key(key(KeyCode::Enter), 10).unwrap();
+        app.handle_key(key(KeyCode::Char(' ')), 10).unwrap();
+        assert!(app.nodes[1].expanded && !app.nodes[1].mark);
+    }
+
+    #[test]
+    fn template_seed_skips_rules_already_in_the_file() {
+        // "/target" ne couvre que la racine : "target/", valable à toute profondeur, reste
+        let seed = template_seed("rust", "/target\n*.pdb\n").unwrap();
+        assert_eq!(seed, "# Rust template\ndebug/\ntarget/\n**/*.rs.bk\n");
+        assert_eq!(template_seed("rust", "target\n*.pdb\n").unwrap(), "# Rust template\ndebug/\n**/*.rs.bk\n");
+        assert_eq!(template_seed("Rust", &merge_seed("/target\n*.pdb\n", &seed)).unwrap(), "");
+        assert!(template_seed("Cobol", "").is_err());
+
+        let merged = generate_gitignore(&[], "*.log\n", &GenerateOptions { seed, ..Default::default() });
+        assert_eq!(merged, "*.log\n\n# Rust template\ndebug/\ntarget/\n**/*.rs.bk\n");
+    }
+
+    #[test]
+    fn normalize_entry_strips_prefixes_and_suffixes() {
+        assert_eq!(normalize_entry("./target/"), "target");
+        assert_eq!(normalize_entry("/build/out"), "build/out");
+        assert_eq!(normalize_entry("src\\main.rs"), "src/main.rs");
+        assert_eq!(normalize_entry("."), "");
+        assert_eq!(normalize_entry("/"), "");
+    }
+
+    #[test]
+    fn rm_removes_anchored_and_unanchored_forms() {
+        let content = "/target\ntarget\n/target/*\ntarget/*\n/src\n";
+        assert_eq!(remove(content, &["target"]), "/src\n");
+    }
+
+    #[test]
+    fn rm_removes_exception_forms() {
+        let content = "/build/*\n!/build/keep.txt\n!build/keep.txt\n!/build/keep.txt/*\n";
+        assert_eq!(remove(content, &["build/keep.txt"]), "/build/*\n");
+    }
+
+    #[test]
+    fn rm_keeps_comments_blank_lines_and_other_rules() {
+        let content = "# Build\n/target\n\n*.log\n/targets\n";
+        assert_eq!(remove(content, &["./target/"]), "# Build\n\n*.log\n/targets\n");
+    }
+
+    #[test]
+    fn rm_root_removes_global_patterns() {
+        let content = "/*\n!/src\n*\n!*\n";
+        assert_eq!(remove(content, &["/"]), "!/src\n");
+    }
+
+    #[test]
+    fn collapse_extensions_when_every_file_of_the_extension_is_marked() {
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &["logs/", "logs/a.log", "logs/b.log", "logs/notes.txt"]);
+        mark(root, &mut nodes, &["logs/a.log", "logs/b.log"]);
+
+        let opts = GenerateOptions { collapse_extensions: true, ..Default::default() };
+        assert_eq!(generate_gitignore(&nodes, "", &opts), "/logs/*.log\n");
+    }
+
+    #[test]
+    fn collapse_extensions_keeps_enumeration_on_partial_coverage() {
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &["logs/", "logs/a.log", "logs/b.log", "logs/c.log"]);
+        mark(root, &mut nodes, &["logs/a.log", "logs/b.log"]);
+
+        let opts = GenerateOptions { collapse_extensions: true, ..Default::default() };
+        assert_eq!(
+            generate_gitignore(&nodes, "", &opts),
+            "/logs/a.log\n/logs/b.log\n"
+        );
+    }
+
+    #[test]
+    fn collapse_extensions_only_looks_at_direct_children() {
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &["a.tmp", "b.tmp", "sub/", "sub/c.tmp"]);
+        mark(root, &mut nodes, &["a.tmp", "b.tmp"]);
+
+        let opts = GenerateOptions { collapse_extensions: true, ..Default::default() };
+        assert_eq!(generate_gitignore(&nodes, "", &opts), "/*.tmp\n");
+    }
+
+    #[test]
+    fn collapse_extensions_disabled_by_default() {
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &["logs/", "logs/a.log", "logs/b.log"]);
+        mark(root, &mut nodes, &["logs/a.log", "logs/b.log"]);
+
+        assert_eq!(
+            generate_gitignore(&nodes, "", &GenerateOptions::default()),
+            "/logs/a.log\n/logs/b.log\n"
+        );
+    }
+
+    #[test]
+    fn parse_root_forms() {
+        let rules = parse_gitignore_content("/*\n*\n!/*\n!*\n");
+        let parsed: Vec<(&str, Mode)> = rules.iter().map(|r| (r.pattern.as_str(), r.mode)).collect();
+        assert_eq!(
+            parsed,
+            vec![
+                (ROOT_PATTERN, Mode::C),
+                (ROOT_PATTERN, Mode::C),
+                (ROOT_PATTERN, Mode::E),
+                (ROOT_PATTERN, Mode::E),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_skips_patterns_that_match_nothing() {
+        assert!(parse_gitignore_content("/\n.\n./\n!/\n").is_empty());
+    }
+
+    #[test]
+    fn root_rule_agrees_across_apply_should_be_ignored_and_generate() {
+        let root = Path::new("/repo");
+        let content = "/*\n!/src\n";
+        let rules = parse_gitignore_content(content);
+        let mut nodes = tree(
+            root,
+            &["src/", "src/main.rs", "target/", "target/x", "README.md"],
+        );
+        apply_rules_to_nodes(&mut nodes, &rules, false);
+
+        // La racine virtuelle porte la règle "/*"
+        assert_eq!((nodes[0].mode, nodes[0].mark), (Mode::C, true));
+        for n in &nodes[1..] {
+            assert_eq!(n.mark, should_be_ignored(&n.rel, &rules, false), "{}", n.rel);
+        }
+
+        let generated = generate_gitignore(&nodes, content, &GenerateOptions::default());
+        assert_eq!(generated, "/*\n!/src\n");
+    }
+
+    #[test]
+    fn unanchored_star_is_the_root_rule() {
+        let root = Path::new("/repo");
+        let rules = parse_gitignore_content("*\n");
+        let mut nodes = tree(root, &["a.txt"]);
+        apply_rules_to_nodes(&mut nodes, &rules, false);
+
+        assert!(nodes.iter().all(|n| n.mark));
+        assert!(should_be_ignored("a.txt", &rules, false));
+        // Règle inchangée : réécrite telle qu'elle était
+        assert_eq!(
+            generate_gitignore(&nodes, "*\n", &GenerateOptions::default()),
+            "*\n"
+        );
+    }
+
+    #[test]
+    fn toggle_node_unignores_a_marked_node() {
+        // Ignoré par un parent : exception
+        assert_eq!(toggle_node(Mode::N, true), (Mode::E, false));
+        // Ignoré par sa propre règle : la règle disparaît
+        assert_eq!(toggle_node(Mode::C, true), (Mode::N, false));
+        assert_eq!(toggle_node(Mode::E, true), (Mode::E, false));
+    }
+
+    #[test]
+    fn toggle_node_ignores_an_unmarked_node() {
+        // Aucune règle : on en crée une
+        assert_eq!(toggle_node(Mode::N, false), (Mode::C, true));
+        // Exception sous un parent ignoré : elle disparaît
+        assert_eq!(toggle_node(Mode::E, false), (Mode::N, true));
+        assert_eq!(toggle_node(Mode::C, false), (Mode::C, true));
+    }
+
+    #[test]
+    fn toggle_node_twice_restores_consistent_states() {
+        for (mode, mark) in [(Mode::N, false), (Mode::N, true), (Mode::C, true), (Mode::E, false)] {
+            let (m, k) = toggle_node(mode, mark);
+            assert_eq!(toggle_node(m, k), (mode, mark), "{:?} {}", mode, mark);
+        }
+    }
+
+    #[test]
+    fn marking_a_directory_saves_a_single_rule_unless_a_child_is_kept() {
+        let root = Path::new("/p");
+        let saved = |nodes: &[Node]| generate_gitignore(nodes, "", &GenerateOptions::default());
+
+        let mut nodes = tree(root, &["empty/"]);
+        toggle_entry(&mut nodes, 1);
+        assert_eq!((nodes[1].mode, nodes[1].mark), (Mode::C, true));
+        assert_eq!(saved(&nodes), "/empty\n");
+
+        // Un enfant ré-inclus ensuite reste une exception sous "/d/*"
+        let mut nodes = tree(root, &["d/", "d/a", "d/keep"]);
+        toggle_entry(&mut nodes, 1);
+        toggle_entry(&mut nodes, 3);
+        assert_eq!((nodes[3].mode, nodes[3].mark), (Mode::E, false));
+        assert_eq!(saved(&nodes), "/d/*\n!/d/keep\n");
+
+        // Contenu déjà marqué un par un, dont un fichier générique resté en exception :
+        // tout est repris par la seule règle du répertoire
+        let mut nodes = tree(root, &["d/", "d/a", "d/b.png"]);
+        mark(root, &mut nodes, &["d/a"]);
+        nodes[3].mode = Mode::E;
+        nodes[3].mark = true;
+        nodes[3].generic_mark = true;
+        toggle_entry(&mut nodes, 1);
+        assert_eq!(nodes[1].cpt_exception, 0);
+        assert!(nodes[3].mark);
+        assert_eq!(saved(&nodes), "/d\n");
+    }
+
+    #[test]
+    fn generate_keeps_the_anchoring_of_unchanged_rules() {
+        let root = Path::new("/repo");
+        let content = "target\nlogs/\n";
+        let rules = parse_gitignore_content(content);
+        let mut nodes = tree(root, &["logs/", "logs/a.log", "notes.txt", "target/", "target/out"]);
+        apply_rules_to_nodes(&mut nodes, &rules, false);
+
+        let generated = generate_gitignore(&nodes, content, &GenerateOptions::default());
+        assert_eq!(generated, "target\nlogs/\n");
+
+        // Une règle nouvelle prend la forme ancrée de l'outil
+        let notes = nodes.iter().position(|n| n.rel == "notes.txt").unwrap();
+        nodes[notes].mode = Mode::C;
+        nodes[notes].mark = true;
+        let generated = generate_gitignore(&nodes, content, &GenerateOptions::default());
+        assert_eq!(generated, "target\nlogs/\n/notes.txt\n");
+    }
+
+    #[test]
+    fn root_like_empty_patterns_are_kept_and_match_nothing() {
+        let root = Path::new("/repo");
+        let content = "/\n.\n/target\n";
+        let rules = parse_gitignore_content(content);
+        let mut nodes = tree(root, &["target/", "a.txt"]);
+        apply_rules_to_nodes(&mut nodes, &rules, false);
+
+        assert!(!nodes[0].mark);
+        assert!(!should_be_ignored("a.txt", &rules, false));
+        assert_eq!(
+            generate_gitignore(&nodes, content, &GenerateOptions::default()),
+            "/\n.\n/target\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_matches_diff_u() {
+        let lines = |range: std::ops::RangeInclusive<usize>| range.map(|i| format!("{i}\n")).collect::<String>();
+        let diff = |old: &str, new: &str| unified_diff(old, new, "a", "b");
+
+        assert!(diff("", "").is_empty());
+        assert!(diff("x\ny\n", "x\ny").is_empty());
+        // Un côté vide : numéro de la ligne précédente, soit 0
+        assert_eq!(diff("", "a\nb\n"), ["--- a", "+++ b", "@@ -0,0 +1,2 @@", "+a", "+b"]);
+        assert_eq!(diff("a\nb\n", ""), ["--- a", "+++ b", "@@ -1,2 +0,0 @@", "-a", "-b"]);
+
+        let ten = lines(1..=10);
+        let inserted = format!("{}x\n{}", lines(1..=5), lines(6..=10));
+        assert_eq!(
+            diff(&ten, &inserted)[2..],
+            ["@@ -3,6 +3,7 @@", " 3", " 4", " 5", "+x", " 6", " 7", " 8"]
+        );
+        let replaced = format!("{}five\n{}", lines(1..=4), lines(6..=10));
+        assert_eq!(
+            diff(&ten, &replaced)[2..],
+            ["@@ -2,7 +2,7 @@", " 2", " 3", " 4", "-5", "+five", " 6", " 7", " 8"]
+        );
+
+        // Modifications éloignées : un hunk chacune
+        let twenty = lines(1..=20);
+        let both = format!("1\ntwo\n{}eighteen\n{}", lines(3..=17), lines(19..=20));
+        let hunks: Vec<String> = diff(&twenty, &both).into_iter().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(hunks, ["@@ -1,5 +1,5 @@", "@@ -15,6 +15,6 @@"]);
+    }
+
+    #[test]
+    fn clip_columns_counts_display_columns() {
+        assert_eq!(clip_columns("abc", 0, 5), ("abc".to_string(), 3));
+        assert_eq!(clip_columns("abcdef", 0, 4), ("abc…".to_string(), 4));
+        assert_eq!(clip_columns("abcdef", 2, 10), ("…cdef".to_string(), 5));
+        assert_eq!(clip_columns("abcdef", 2, 4), ("…cd…".to_string(), 4));
+        assert_eq!(clip_columns("abc", 0, 0), (String::new(), 0));
+
+        // Caractères larges : deux colonnes chacun, jamais coupés à droite
+        assert_eq!(clip_columns("日本語", 0, 6), ("日本語".to_string(), 6));
+        assert_eq!(clip_columns("日本語", 0, 4), ("日…".to_string(), 3));
+        // Décalage au milieu d'un caractère large : sa moitié visible devient un espace
+        assert_eq!(clip_columns("日本語", 1, 10), ("… 本語".to_string(), 6));
+        assert_eq!(clip_columns("日本語", 1, 4), ("… …".to_string(), 3));
+
+        // Tabulation jusqu'au taquet suivant, caractère de contrôle remplacé
+        let tab = " ".repeat(TAB_WIDTH - 1);
+        assert_eq!(clip_columns("a\tb\x07", 0, 20), (format!("a{tab}b?"), TAB_WIDTH + 2));
+    }
+
+    #[test]
+    fn mark_older_than_marks_stale_files_and_logs_unknown_times() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        let mut nodes = tree(Path::new("/repo"), &["out/", "out/old.o", "new.rs", "unknown.bin", "done.log"]);
+        for (n, mtime) in nodes.iter_mut().zip([None, None, Some(now - day * 40), Some(now), None, Some(now - day * 90)]) {
+            n.mtime = mtime;
+        }
+        nodes[5].mark = true;
+        let mut app = App::new(Path::new("/repo"), Config::default(), ScanOptions::default(), ViewFilter::default(), nodes, Vec::new());
+
+        app.mark_older_than("abc");
+        assert!(app.flash.as_ref().is_some_and(|f| f.text == "expected a number of days, got 'abc'"));
+        assert_eq!(app.nodes.iter().filter(|n| n.mark).count(), 1);
+
+        app.mark_older_than(" 30 ");
+        let marked: Vec<&str> = app.nodes.iter().filter(|n| n.mark).map(|n| n.name.as_str()).collect();
+        assert_eq!(marked, ["old.o", "done.log"]);
+        assert_eq!(app.nodes[2].mode, Mode::C);
+        // Sans date de modification : ni marqué ni compté, mais signalé dans le journal
+        assert_eq!(app.log, ["Age: skipped /repo/unknown.bin, modification time unavailable"]);
+        assert_eq!(
+            app.flash.as_ref().map(|f| f.text.as_str()),
+            Some("marked 1 file(s) older than 30 day(s), 1 skipped (see log)")
+        );
+    }
+
+    #[test]
+    fn should_be_ignored_follows_the_case_setting() {
+        let rules = parse_gitignore_content("/build\n");
+        let mut nodes = tree(Path::new("/repo"), &["Build/", "Build/out"]);
+        apply_rules_to_nodes(&mut nodes, &rules, true);
+        // --ignore-case (ou I) : ce que l'arbre marque est aussi désindexé par -j
+        assert!(nodes[2].mark && should_be_ignored("Build/out", &rules, true));
+        assert!(!should_be_ignored("Build/out", &rules, false));
+    }
+
+    #[test]
+    fn rule_templates_round_trip_through_the_config() {
+        let template = Template::parse(" C dist ; E dist/keep.txt;;C a\\;b; E \\ lead\\ ").unwrap();
+        let expected = [("dist", Mode::C), ("dist/keep.txt", Mode::E), ("a;b", Mode::C), (" lead ", Mode::E)];
+        let entries: Vec<(&str, Mode)> = template.entries.iter().map(|(r, m)| (r.as_str(), *m)).collect();
+        assert_eq!(entries, expected);
+
+        // La valeur écrite survit à la lecture de la ligne, espaces de fin compris
+        let config = Config::parse(&format!("template.t = {}  \n", template.format())).unwrap();
+        assert_eq!(config.templates["t"].entries, template.entries);
+        assert_eq!(Template { entries: vec![("a\\b".to_string(), Mode::C)] }.format(), "C a\\\\b");
+
+        assert!(Template::parse("X dist").is_err());
+        assert!(Template::parse("C ").is_err());
+    }
+
+    #[test]
+    fn rule_templates_apply_only_to_the_same_layout() {
+        let nodes = tree(
+            Path::new("/repo"),
+            &["pkg/", "pkg/dist/", "pkg/dist/keep.txt", "other/", "other/build/"],
+        );
+        let template = Template::capture(
+            &{
+                let mut nodes = nodes.clone();
+                (nodes[2].mode, nodes[3].mode) = (Mode::C, Mode::E);
+                nodes
+            },
+            1,
+        );
+        assert_eq!(template.format(), "C dist; E dist/keep.txt");
+
+        assert_eq!(template.resolve(&nodes, 1), Ok(vec![(2, Mode::C), (3, Mode::E)]));
+        // Autre structure : rien n'est appliqué, les chemins absents sont rendus
+        assert_eq!(
+            template.resolve(&nodes, 4),
+            Err(vec!["dist".to_string(), "dist/keep.txt".to_string()])
+        );
+    }
+
+    #[test]
+    fn compact_rows_stop_at_the_first_collapsed_directory() {
+        let mut nodes = tree(Path::new("/repo"), &["a/", "a/b/", "a/b/c/", "a/b/c/x.rs", "d.rs"]);
+        for n in &mut nodes[1..4] {
+            n.expanded = true;
+        }
+        let rels = |nodes: &[Node]| -> Vec<String> {
+            build_visible_indices(nodes, &ViewFilter::default(), true).iter().map(|&i| nodes[i].rel.clone()).collect()
+        };
+        assert_eq!(rels(&nodes), ["", "a", "a/b/c/x.rs", "d.rs"]);
+        assert_eq!(compact_depths(&nodes)[4], 2);
+
+        nodes[2].expanded = false;
+        assert_eq!(rels(&nodes), ["", "a", "d.rs"]);
+        assert_eq!(shown_chain(&nodes, 1), [1, 2]);
+        assert!(is_compacted(&nodes, 2) && !is_compacted(&nodes, 3));
+    }
+
+    #[test]
+    fn mark_matching_only_marks_candidates_and_follows_parents() {
+        let mut nodes = tree(Path::new("/repo"), &["build/", "build/out.bin", "a.tmp", "src/", "src/b.tmp"]);
+        // src/ replié : seul ce qui est affiché compte
+        let visible = build_visible_indices(&nodes, &ViewFilter::default(), false);
+        let count = mark_matching(&mut nodes, Path::new("/repo"), "*.tmp", false, &visible).unwrap();
+        assert_eq!(count, 1);
+        assert!(nodes[3].mark && !nodes[5].mark);
+
+        // Le fichier correspond par son répertoire parent
+        let all: Vec<usize> = (0..nodes.len()).collect();
+        assert_eq!(mark_matching(&mut nodes, Path::new("/repo"), "build/", false, &all).unwrap(), 1);
+        assert!(nodes[2].mark && nodes[2].mode == Mode::C && !nodes[1].mark);
+        assert!(mark_matching(&mut nodes, Path::new("/repo"), "{a", false, &all).is_err());
+    }
+
+    #[test]
+    fn pinned_entries_are_left_out_of_bulk_marks() {
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &["logs/", "logs/a.tmp", "logs/b.tmp", "c.tmp"]);
+        let at = |nodes: &[Node], rel: &str| nodes.iter().position(|n| n.rel == rel).unwrap();
+        let (logs, a, b) = (at(&nodes, "logs"), at(&nodes, "logs/a.tmp"), at(&nodes, "logs/b.tmp"));
+
+        // Marquer un répertoire laisse son contenu épinglé en exception
+        nodes[b].pinned = true;
+        apply_recursive_mark_on_dir(&mut nodes, logs, true);
+        assert!(nodes[logs].mark && nodes[a].mark);
+        assert!(!nodes[b].mark && nodes[b].mode == Mode::E);
+
+        // Un pattern ne marque pas un fichier épinglé
+        apply_recursive_mark_on_dir(&mut nodes, logs, false);
+        let all: Vec<usize> = (0..nodes.len()).collect();
+        assert_eq!(mark_matching(&mut nodes, root, "*.tmp", false, &all).unwrap(), 2);
+        assert!(nodes[a].mark && !nodes[b].mark);
+
+        // Un répertoire épinglé n'est pas proposé pour remplacer les règles de son contenu
+        nodes[b].pinned = false;
+        mark(root, &mut nodes, &["logs/b.tmp"]);
+        assert_eq!(fully_marked_parent(&nodes, b), Some(logs));
+        nodes[logs].pinned = true;
+        assert_eq!(fully_marked_parent(&nodes, b), None);
+    }
+
+    #[test]
+    fn marked_delta_counts_only_changed_files() {
+        let mut nodes = tree(Path::new("/repo"), &["a/", "a/x.txt", "a/y.txt", "b.txt"]);
+        mark_file(&mut nodes[4]);
+        let before = mark_states(&nodes);
+        assert_eq!(marked_delta(&nodes, &before), None);
+
+        // Le répertoire compte comme un changement, pas comme un fichier
+        mark_file(&mut nodes[1]);
+        apply_recursive_mark_on_dir(&mut nodes, 1, true);
+        unmark_file(&mut nodes[4]);
+        assert_eq!(marked_delta(&nodes, &before), Some(1));
+        assert_eq!(count_files(&nodes).1, 1 + 1);
+    }
+
+    #[test]
+    fn parse_size_accepts_binary_units() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("100M"), Some(100 * 1024 * 1024));
+        assert_eq!(parse_size("1.5 GB"), Some(3 * 512 * 1024 * 1024));
+        assert_eq!(parse_size("2k"), Some(2048));
+        assert_eq!(parse_size("big"), None);
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn jump_list_goes_back_and_forward() {
+        let mut jumps = JumpList::default();
+        jumps.record(1);
+        jumps.record(5);
+        // Curseur en 9 après le second saut
+        assert_eq!(jumps.back(9), Some(5));
+        assert_eq!(jumps.back(5), Some(1));
+        assert_eq!(jumps.back(1), None);
+        assert_eq!(jumps.forward(1), Some(5));
+        assert_eq!(jumps.forward(5), Some(9));
+        assert_eq!(jumps.forward(9), None);
+
+        // Un nouveau saut oublie les positions suivantes
+        jumps.back(9);
+        jumps.record(5);
+        assert_eq!(jumps.forward(7), None);
+    }
+
+    #[test]
+    fn jump_list_is_bounded() {
+        let mut jumps = JumpList::default();
+        for idx in 0..JUMP_HISTORY_LEN + 10 {
+            jumps.record(idx);
+        }
+        assert_eq!(jumps.back.len(), JUMP_HISTORY_LEN);
+        assert_eq!(jumps.back[0], 10);
+    }
+
+    #[test]
+    fn generic_negations_apply_in_file_order() {
+        let root = Path::new("/repo");
+        let mut log = Vec::new();
+        let matched = |content: &str, rel: &str, log: &mut Vec<String>| {
+            let gi = generic_gitignore_from_content(root, content, false, log).unwrap().unwrap();
+            let m = gi.matched(rel, false);
+            (m.is_ignore(), m.is_whitelist())
+        };
+
+        assert_eq!(matched("*.log\n!keep.log\n", "keep.log", &mut log), (false, true));
+        assert_eq!(matched("*.log\n!keep.log\n", "logs/keep.log", &mut log), (false, true));
+        assert_eq!(matched("*.log\n!keep.log\n", "debug.log", &mut log), (true, false));
+        assert_eq!(matched("*.log\n!*.keep.log\n", "a.keep.log", &mut log), (false, true));
+        // Exception avant le pattern : le pattern l'emporte
+        assert_eq!(matched("!keep.log\n*.log\n", "keep.log", &mut log), (true, false));
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn generic_negations_unmark_the_nodes_they_reinclude() {
+        let tmp = TempDir::new("negation");
+        let root = tmp.path();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        for file in ["debug.log", "keep.log", "logs/keep.log"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let mut nodes = tree(root, &["logs/", "logs/keep.log", "debug.log", "keep.log"]);
+        let mut log = Vec::new();
+        let mut apply = |nodes: &mut [Node], content: &str| {
+            let gi = generic_gitignore_from_content(root, content, false, &mut log).unwrap();
+            mark_generic_matches(nodes, root, gi.as_ref());
+        };
+        let marked = |nodes: &[Node]| -> Vec<(String, bool, bool)> {
+            nodes[1..].iter().filter(|n| !n.is_dir).map(|n| (n.rel.clone(), n.generic_mark, n.mark)).collect()
+        };
+
+        apply(&mut nodes, "*.log\n");
+        assert!(marked(&nodes).iter().all(|&(_, generic, mark)| generic && mark));
+
+        // L'exception placée après le pattern ré-inclut keep.log à toute profondeur
+        apply(&mut nodes, "*.log\n!keep.log\n");
+        assert_eq!(
+            marked(&nodes),
+            [
+                ("logs/keep.log".to_string(), false, false),
+                ("debug.log".to_string(), true, true),
+                ("keep.log".to_string(), false, false),
+            ]
+        );
+
+        // Placée avant, elle n'a pas d'effet : le pattern l'emporte
+        apply(&mut nodes, "!keep.log\n*.log\n");
+        assert!(marked(&nodes).iter().all(|&(_, generic, mark)| generic && mark));
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn double_star_rules_mark_directory_contents_and_survive_a_save() {
+        let tmp = TempDir::new("globstar");
+        let root = tmp.path();
+        fs::create_dir_all(root.join("app/node_modules/lib")).unwrap();
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+        for file in ["app/node_modules/lib/x.js", "app/main.js", "node_modules/y.js"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let content = "**/node_modules\n";
+        let mut nodes = tree(
+            root,
+            &["app/", "app/node_modules/", "app/node_modules/lib/", "app/node_modules/lib/x.js", "app/main.js"],
+        );
+        nodes.extend(tree(root, &["node_modules/", "node_modules/y.js"]).into_iter().skip(1));
+        let mut log = Vec::new();
+        let matched = match_gitignore_content(&mut nodes, root, content, false, &mut log);
+        matched.unwrap();
+
+        let generic: Vec<&str> = nodes.iter().filter(|n| n.generic_mark).map(|n| n.rel.as_str()).collect();
+        assert_eq!(generic, ["app/node_modules/lib/x.js", "node_modules/y.js"]);
+        assert_eq!(generate_gitignore(&nodes, content, &GenerateOptions::default()), content);
+
+        // Une sélection explicite s'ajoute sans toucher à la règle
+        mark(root, &mut nodes, &["app/main.js"]);
+        assert_eq!(
+            generate_gitignore(&nodes, content, &GenerateOptions::default()),
+            "**/node_modules\n/app/main.js\n"
+        );
+    }
+
+    #[test]
+    fn untrack_keeps_spaces_and_unicode_in_tracked_paths() {
+        // Sortie de `git ls-files -z` : ni guillemets ni nettoyage des espaces
+        let tracked = split_paths(" café notes.log\0src/été 2024/a b.txt \0main.rs\0", '\0');
+        assert_eq!(tracked, [" café notes.log", "src/été 2024/a b.txt ", "main.rs"]);
+
+        let rules = parse_gitignore_content("/ café notes.log\n");
+        assert!(should_be_ignored(&tracked[0], &rules, false));
+        assert!(!should_be_ignored("café notes.log", &rules, false));
+        let mut log = Vec::new();
+        let files = untrack_matches(Path::new("/repo"), "*.log\n", &tracked, false, &mut log).unwrap();
+        assert_eq!(files, [(" café notes.log".to_string(), UntrackMatch::Generic)]);
+    }
+
+    #[test]
+    fn untrack_matches_follow_the_given_content() {
+        let root = Path::new("/repo");
+        let tracked: Vec<String> = ["a.log", "build/out", "main.rs"].iter().map(|f| f.to_string()).collect();
+        let mut log = Vec::new();
+        let files = untrack_matches(root, "/build\n*.log\n", &tracked, false, &mut log).unwrap();
+        assert_eq!(
+            files,
+            [("a.log".to_string(), UntrackMatch::Generic), ("build/out".to_string(), UntrackMatch::Rule)]
+        );
+        // La sélection en cours, pas le fichier sur disque : "/main.rs" ajouté
+        let files = untrack_matches(root, "/build\n/main.rs\n", &tracked, false, &mut log).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn untrack_matches_follow_the_case_setting() {
+        let root = Path::new("/repo");
+        let tracked: Vec<String> = ["A.LOG", "Build/out", "main.rs"].iter().map(|f| f.to_string()).collect();
+        let content = "/build\n*.log\n";
+        let mut log = Vec::new();
+        assert!(untrack_matches(root, content, &tracked, false, &mut log).unwrap().is_empty());
+        // --ignore-case (ou I) : la prévisualisation p suit la casse de l'arbre
+        assert_eq!(
+            untrack_matches(root, content, &tracked, true, &mut log).unwrap(),
+            [("A.LOG".to_string(), UntrackMatch::Generic), ("Build/out".to_string(), UntrackMatch::Rule)]
+        );
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn trailing_slash_rules_only_match_directories() {
+        let root = Path::new("/repo");
+        let content = "cache/\ndata/cache/\n";
+        let rules = parse_gitignore_content(content);
+        assert!(rules.iter().all(|r| r.dir_only));
+        let mut nodes = tree(root, &["cache", "data/", "data/cache/", "data/cache/blob"]);
+        apply_rules_to_nodes(&mut nodes, &rules, false);
+
+        let mark = |rel: &str| nodes.iter().find(|n| n.rel == rel).unwrap().mark;
+        assert!(!mark("cache"));
+        assert!(mark("data/cache"));
+        assert!(mark("data/cache/blob"));
+        assert!(!should_be_ignored("cache", &rules, false));
+        assert!(should_be_ignored("data/cache/blob", &rules, false));
+
+        // La règle du répertoire garde sa forme ; le fichier, s'il est marqué, prend la sienne
+        assert_eq!(generate_gitignore(&nodes, content, &GenerateOptions::default()), content);
+        nodes[1].mode = Mode::C;
+        nodes[1].mark = true;
+        assert_eq!(
+            generate_gitignore(&nodes, content, &GenerateOptions::default()),
+            "cache/\ndata/cache/\n/cache\n"
+        );
+    }
+
+    #[test]
+    fn build_gitignore_content_reads_the_existing_file() {
+        let tmp = TempDir::new("build");
+        let root = tmp.path();
+        fs::write(root.join(".gitignore"), "# build\n*.log\n/old\n").unwrap();
+
+        let mut nodes = tree(root, &["main.rs", "out/"]);
+        nodes[2].mode = Mode::C;
+        nodes[2].mark = true;
+        let content = build_gitignore_content(&nodes, root, &GenerateOptions::default());
+        let files = gitignore_files(&nodes, root, &GenerateOptions::default());
+
+        assert_eq!(content.unwrap(), "# build\n*.log\n/old\n\n# Added by gitignore-tui\n/out\n");
+        assert_eq!(files.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn nested_gitignore_rules_apply_below_their_directory() {
+        let tmp = TempDir::new("nested");
+        let root = tmp.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/.gitignore"), "build\n*.tmp\n").unwrap();
+
+        let mut nodes = tree(
+            root,
+            &["a.tmp", "build/", "sub/", "sub/.gitignore", "sub/b.tmp", "sub/build/", "sub/build/x"],
+        );
+        let mut log = Vec::new();
+        let matched = match_gitignore_content(&mut nodes, root, "", false, &mut log);
+        let files = gitignore_files(&nodes, root, &GenerateOptions::default());
+        matched.unwrap();
+
+        let marked: Vec<&str> = nodes.iter().filter(|n| n.mark).map(|n| n.rel.as_str()).collect();
+        assert_eq!(marked, ["sub/b.tmp", "sub/build", "sub/build/x"]);
+        assert_eq!(nodes[6].target.as_deref(), Some(root.join("sub").as_path()));
+        let files = files.unwrap();
+        assert_eq!(files[0].1, "");
+        assert_eq!(files[1].1, "build\n*.tmp\n");
+    }
+
+    #[test]
+    fn lazily_loaded_children_follow_rules_or_their_edited_directory() {
+        let tmp = TempDir::new("lazy");
+        let root = tmp.path();
+        fs::create_dir_all(root.join("a/sub")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::write(root.join("a/sub/x"), "").unwrap();
+        fs::write(root.join("b/y"), "").unwrap();
+
+        let opts = ScanOptions { lazy: true, ..Default::default() };
+        let content = "/b\n";
+        let mut log = Vec::new();
+        let mut nodes = build_full_tree(root, &opts, &mut log).unwrap();
+        match_tree(&mut nodes, root, content, &opts, &mut log).unwrap();
+        assert!(nodes.iter().all(|n| n.loaded != (n.is_dir && n.depth == 1)));
+        // "a" marqué avant d'être ouvert
+        toggle_entry(&mut nodes, 1);
+        let counts = load_dir_contents(&mut nodes, &[1, 2], root, content, &opts, false, &mut log);
+        let full = load_dir_contents(&mut nodes, &[2], root, content, &opts, true, &mut log);
+
+        assert_eq!(counts.unwrap(), [1, 1]);
+        assert_eq!(full.unwrap(), [1]);
+        let state: Vec<(&str, Mode, bool, bool)> =
+            nodes.iter().map(|n| (n.rel.as_str(), n.mode, n.mark, n.loaded)).collect();
+        assert_eq!(
+            state,
+            [
+                ("", Mode::N, false, true),
+                ("a", Mode::C, true, true),
+                ("a/sub", Mode::N, true, true),
+                ("a/sub/x", Mode::N, true, true),
+                ("b", Mode::C, true, true),
+                ("b/y", Mode::N, true, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn lazy_loading_only_matches_the_new_nodes_and_their_ancestors() {
+        let tmp = TempDir::new("lazy-context");
+        let root = tmp.path();
+        fs::create_dir_all(root.join("a/sub")).unwrap();
+        fs::create_dir_all(root.join("c")).unwrap();
+        fs::write(root.join("a/.gitignore"), "/sub/x.tmp\n").unwrap();
+        fs::write(root.join("a/sub/x.tmp"), "").unwrap();
+        fs::write(root.join("a/sub/y.rs"), "").unwrap();
+        fs::write(root.join("c/z"), "").unwrap();
+
+        let opts = ScanOptions { lazy: true, ..Default::default() };
+        let mut log = Vec::new();
+        let mut nodes = build_full_tree(root, &opts, &mut log).unwrap();
+        let loaded = load_dir_contents(&mut nodes, &[1], root, "", &opts, false, &mut log);
+        let sub = nodes.iter().position(|n| n.rel == "a/sub").unwrap();
+        let counts = load_dir_contents(&mut nodes, &[sub], root, "", &opts, false, &mut log);
+        let context = match_context(&nodes, &[sub], &[2]);
+
+        assert_eq!(loaded.unwrap(), [2]);
+        assert_eq!(counts.unwrap(), [2]);
+        // Le .gitignore de "a" vaut pour le contenu de a/sub ; "c" n'est pas relu
+        let rels: Vec<&str> = context.iter().map(|&i| nodes[i].rel.as_str()).collect();
+        assert_eq!(rels, ["", "a", "a/sub", "a/sub/x.tmp", "a/sub/y.rs", "a/.gitignore"]);
+        let marked: Vec<&str> = nodes.iter().filter(|n| n.mark).map(|n| n.rel.as_str()).collect();
+        assert_eq!(marked, ["a/sub/x.tmp"]);
+    }
+
+    #[test]
+    fn row_at_maps_screen_lines_to_visible_rows() {
+        // En-tête, puis 5 lignes d'arbre à partir de la ligne 7 de la liste
+        assert_eq!(row_at(0, 7, 20, 5), None);
+        assert_eq!(row_at(HEADER_ROWS, 7, 20, 5), Some(7));
+        assert_eq!(row_at(HEADER_ROWS + 4, 7, 20, 5), Some(11));
+        // Pied de page, et lignes vides après la fin de la liste
+        assert_eq!(row_at(HEADER_ROWS + 5, 7, 20, 5), None);
+        assert_eq!(row_at(HEADER_ROWS + 3, 0, 3, 5), None);
+    }
+
+    #[test]
+    fn undo_history_restores_marks_and_is_capped() {
+        let mut nodes = tree(Path::new("/repo"), &["a.txt"]);
+        let mut history = UndoHistory::default();
+        let before = mark_states(&nodes);
+        (nodes[1].mode, nodes[1].mark) = toggle_node(nodes[1].mode, nodes[1].mark);
+        history.record(before.clone());
+
+        let after = mark_states(&nodes);
+        assert_eq!(history.undo(after.clone()), Some(before.clone()));
+        assert_eq!(history.undo(before.clone()), None);
+        assert_eq!(history.redo(before.clone()), Some(after.clone()));
+        assert_eq!(history.redo(after.clone()), None);
+
+        // Une nouvelle modification efface le redo, et seules les dernières sont gardées
+        history.undo(after.clone());
+        for _ in 0..UNDO_HISTORY_LEN + 5 {
+            history.record(after.clone());
+        }
+        assert!(history.redo.is_empty());
+        assert_eq!(history.undo.len(), UNDO_HISTORY_LEN);
+    }
+
+    #[test]
+    fn ascii_theme_is_plain_ascii() {
+        let t = Theme::ASCII;
+        let glyphs = [t.connector, t.expanded, t.collapsed, t.marked, t.unmarked, t.generic, t.pinned, t.also, t.global];
+        assert!(glyphs.iter().all(|g| g.is_ascii()));
+    }
+
+    #[test]
+    fn invert_selection_toggles_children_like_enter() {
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &["d/", "d/sub/", "d/sub/x.rs", "d/a.rs", "d/b.png", "d/keep.rs"]);
+        mark(root, &mut nodes, &["d/keep.rs"]);
+        nodes[5].generic_mark = true;
+        nodes[5].mark = true;
+
+        assert_eq!(invert_selection(&mut nodes, 1, false), 3);
+        let marked: Vec<&str> = nodes.iter().filter(|n| n.mark).map(|n| n.rel.as_str()).collect();
+        assert_eq!(marked, ["d/sub", "d/sub/x.rs", "d/a.rs", "d/b.png"]);
+        assert_eq!((nodes[2].mode, nodes[4].mode, nodes[6].mode), (Mode::C, Mode::C, Mode::N));
+
+        // Récursif : les fichiers seulement, un fichier sous un répertoire ignoré devient une exception
+        assert_eq!(invert_selection(&mut nodes, 1, true), 3);
+        assert_eq!((nodes[3].mode, nodes[3].mark), (Mode::E, false));
+        assert!(!nodes[4].mark && nodes[5].mark && nodes[6].mark);
+    }
+
+    #[test]
+    fn global_excludes_are_shown_but_never_saved() {
+        let tmp = TempDir::new("global");
+        let dir = tmp.path();
+        let excludes = dir.join("ignore");
+        fs::write(&excludes, "*.swp\n.idea/\n").unwrap();
+
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &[".idea/", ".idea/ws.xml", "src/", "src/a.rs.swp", "src/a.rs"]);
+        let mut log = Vec::new();
+        mark_global_matches(&mut nodes, root, Some(&excludes), false, &mut log).unwrap();
+        let global: Vec<&str> = nodes.iter().filter(|n| n.global).map(|n| n.rel.as_str()).collect();
+        assert_eq!(global, [".idea", ".idea/ws.xml", "src/a.rs.swp"]);
+        assert!(nodes.iter().all(|n| !n.mark) && log.is_empty());
+
+        mark(root, &mut nodes, &["src/a.rs"]);
+        let content = generate_gitignore(&nodes, "", &GenerateOptions::default());
+        assert!(content.contains("/src/a.rs\n"));
+        assert!(!content.contains("swp") && !content.contains("idea"));
+    }
+
+    #[test]
+    fn hiding_ignored_entries_only_drops_fully_marked_subtrees() {
+        let root = Path::new("/repo");
+        let mut nodes = tree(root, &["a/", "a/b.o", "a/c.rs", "out/", "out/x.o", "y.o", "z.rs"]);
+        set_subtree_expanded(&mut nodes, 0, true);
+        mark(root, &mut nodes, &["a/b.o", "out", "out/x.o", "y.o"]);
+        let filter = ViewFilter { hide_ignored: true, ..Default::default() };
+        let hidden = filter.hidden_dirs(&nodes);
+        let hidden: Vec<&str> = nodes.iter().zip(hidden).filter(|(_, h)| *h).map(|(n, _)| n.rel.as_str()).collect();
+        assert_eq!(hidden, ["out"]);
+
+        let rels = |visible: Vec<usize>| -> Vec<String> { visible.iter().map(|&i| nodes[i].rel.clone()).collect() };
+        assert_eq!(rels(build_visible_indices(&nodes, &filter, false)), ["", "a", "a/c.rs", "z.rs"]);
+        assert_eq!(rels(build_flat_indices(&nodes, &filter)), ["a/c.rs", "z.rs"]);
+        assert_eq!(build_visible_indices(&nodes, &ViewFilter::default(), false).len(), nodes.len());
+    }
+
+    #[test]
+    fn set_subtree_expanded_stops_at_the_subtree() {
+        let mut nodes = tree(Path::new("/repo"), &["a/", "a/b/", "a/b/c/", "a/x.txt", "d/"]);
+        set_subtree_expanded(&mut nodes, 1, true);
+        let expanded: Vec<&str> = nodes.iter().filter(|n| n.expanded).map(|n| n.rel.as_str()).collect();
+        assert_eq!(expanded, ["", "a", "a/b", "a/b/c"]);
+
+        set_subtree_expanded(&mut nodes, 2, false);
+        assert!(nodes[1].expanded && !nodes[2].expanded && !nodes[3].expanded);
+    }
+
+    #[test]
+    fn new_rules_go_to_the_matching_section() {
+        let content = "# Build artifacts\n/target\n\n# Logs\n/logs/app.log\n*.tmp\n";
+        let mut nodes = tree(
+            Path::new("/repo"),
+            &["build/", "logs/", "logs/app.log", "logs/old.log", "notes.txt", "target/"],
+        );
+        apply_rules_to_nodes(&mut nodes, &parse_gitignore_content(content), false);
+        for rel in ["build", "logs/old.log", "notes.txt"] {
+            let n = nodes.iter_mut().find(|n| n.rel == rel).unwrap();
+            (n.mode, n.mark) = (Mode::C, true);
+        }
+
+        let generated = generate_gitignore(&nodes, content, &GenerateOptions::default());
+        assert_eq!(
+            generated,
+            "# Build artifacts\n/target\n/build\n\n# Logs\n/logs/app.log\n*.tmp\n/logs/old.log\n\n\
+             # Added by gitignore-tui\n/notes.txt\n"
+        );
+        // Sauvegarder de nouveau ne change plus rien
+        assert_eq!(generate_gitignore(&nodes, &generated, &GenerateOptions::default()), generated);
+    }
+
+    #[test]
+    fn new_rules_stay_after_rules_of_opposite_meaning() {
+        // Rangée sous /logs/x/app.log, la règle serait annulée par la négation qui suit
+        let content = "# Logs\n/logs/x/app.log\n\n# Keep\n!logs/*.keep.log\n";
+        let mut nodes = tree(Path::new("/repo"), &["logs/", "logs/x/", "logs/x/app.log", "logs/x/old.keep.log"]);
+        apply_rules_to_nodes(&mut nodes, &parse_gitignore_content(content), false);
+        (nodes[4].mode, nodes[4].mark) = (Mode::C, true);
+
+        let generated = generate_gitignore(&nodes, content, &GenerateOptions::default());
+        assert_eq!(generated, "# Logs\n/logs/x/app.log\n\n# Keep\n!logs/*.keep.log\n/logs/x/old.keep.log\n");
+    }
+
+    #[test]
+    fn backup_is_written_only_when_the_content_changes() {
+        let tmp = TempDir::new("backup");
+        let dir = tmp.path();
+        let path = dir.join(".gitignore");
+        let backup = dir.join(".gitignore.bak");
+
+        // Pas encore de .gitignore : rien à copier
+        let missing = backup_gitignore(&path, "/target\n");
+        fs::write(&path, "/target\n").unwrap();
+        let unchanged = backup_gitignore(&path, "/target\n");
+        let unchanged_exists = backup.exists();
+        let changed = backup_gitignore(&path, "/target\n/out\n");
+        let saved = fs::read_to_string(&backup);
+
+        assert!(missing.is_ok() && unchanged.is_ok() && changed.is_ok());
+        assert!(!unchanged_exists);
+        assert_eq!(saved.unwrap(), "/target\n");
     }
-    
-    Ok(())
 }
@@ -0,0 +1,156 @@
+mod common;
+
+use common::TempDir;
+use std::process::{Command, Stdio};
+
+#[test]
+fn exits_cleanly_without_a_terminal() {
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .arg(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("running git-ignore");
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("needs an interactive terminal"), "stderr: {stderr}");
+    // Rien n'a été dessiné : pas d'écran alternatif ni de mode raw laissé derrière
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn print_writes_only_the_generated_file_to_stdout() {
+    let tmp = TempDir::new("print");
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("target")).unwrap();
+    std::fs::write(dir.join(".gitignore"), "/target\n").unwrap();
+    std::fs::write(dir.join("debug.log"), "").unwrap();
+    std::fs::write(dir.join("main.rs"), "").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .args(["--print", "--mark", "*.log"])
+        .arg(dir)
+        .stdin(Stdio::null())
+        .output()
+        .expect("running git-ignore");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let gitignore = std::fs::read_to_string(dir.join(".gitignore")).unwrap();
+
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout, "/target\n/debug.log\n");
+    // Le fichier lui-même n'est pas modifié
+    assert_eq!(gitignore, "/target\n");
+}
+
+#[test]
+fn rm_backs_up_the_file_and_respects_the_session_lock() {
+    let tmp = TempDir::new("rm");
+    let dir = tmp.path();
+    std::fs::write(dir.join(".gitignore"), "# build\n/target\n*.log\n").unwrap();
+    let rm = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+            .arg("rm")
+            .args(args)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .output()
+            .expect("running git-ignore")
+    };
+
+    // Une session ouverte (ce processus est vivant) : rien n'est écrit
+    std::fs::write(dir.join(".gitignore-tui.lock"), format!("{}\n", std::process::id())).unwrap();
+    let locked = rm(&["target"]);
+    assert_eq!(locked.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&locked.stderr).contains("already editing"));
+    std::fs::remove_file(dir.join(".gitignore-tui.lock")).unwrap();
+
+    let output = rm(&["target"]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read_to_string(dir.join(".gitignore")).unwrap(), "# build\n*.log\n");
+    assert_eq!(std::fs::read_to_string(dir.join(".gitignore.bak")).unwrap(), "# build\n/target\n*.log\n");
+    // Le verrou est rendu à la fin
+    assert!(!dir.join(".gitignore-tui.lock").exists());
+
+    std::fs::remove_file(dir.join(".gitignore.bak")).unwrap();
+    assert!(rm(&["--no-backup", "*.log"]).status.success());
+    assert_eq!(std::fs::read_to_string(dir.join(".gitignore")).unwrap(), "# build\n");
+    assert!(!dir.join(".gitignore.bak").exists());
+}
+
+#[test]
+fn untrack_modes_cannot_be_combined() {
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .args(["-j", "--git"])
+        .arg(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("running git-ignore");
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("-j/--jj and --git can't be combined"), "stderr: {stderr}");
+}
+
+#[test]
+fn exclude_edits_the_local_exclude_file_instead_of_gitignore() {
+    let tmp = TempDir::new("exclude");
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("target")).unwrap();
+    std::fs::write(dir.join(".gitignore"), "/target\n").unwrap();
+    std::fs::write(dir.join("debug.log"), "").unwrap();
+    // Sans modèle : pas de .git/info/exclude au départ
+    let init = Command::new("git").args(["init", "-q", "--template="]).arg(dir).status();
+    if !init.is_ok_and(|s| s.success()) {
+        return;
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .args(["--print", "--exclude", "--mark", "*.log"])
+        .arg(dir)
+        .stdin(Stdio::null())
+        .output()
+        .expect("running git-ignore");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    // L'aperçu de l'untrack lit lui aussi le fichier de --exclude
+    Command::new("git").arg("-C").arg(dir).args(["add", "debug.log"]).status().unwrap();
+    std::fs::create_dir_all(dir.join(".git/info")).unwrap();
+    std::fs::write(dir.join(".git/info/exclude"), "/debug.log\n").unwrap();
+    let list = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .args(["--list-untrack", "--exclude"])
+        .arg(dir)
+        .stdin(Stdio::null())
+        .output()
+        .expect("running git-ignore");
+    let listed = String::from_utf8_lossy(&list.stdout).into_owned();
+
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    // Les règles du .gitignore partagé ne sont pas recopiées
+    assert_eq!(stdout, "/debug.log\n");
+    assert!(list.status.success(), "stderr: {}", String::from_utf8_lossy(&list.stderr));
+    assert!(listed.contains("debug.log"), "{}", listed);
+}
+
+#[test]
+fn template_rules_are_merged_with_the_existing_file() {
+    let tmp = TempDir::new("template");
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("target")).unwrap();
+    std::fs::write(dir.join(".gitignore"), "# mine\n/target\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_git-ignore"))
+        .args(["--print", "--template", "Rust"])
+        .arg(dir)
+        .stdin(Stdio::null())
+        .output()
+        .expect("running git-ignore");
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    // "/target" ne vaut qu'à la racine : le "target/" du modèle, valable à toute profondeur,
+    // est ajouté et "/target" reste tel quel à sa place
+    assert_eq!(stdout, "# mine\n/target\n\n# Rust template\ndebug/\ntarget/\n**/*.rs.bk\n*.pdb\n");
+}
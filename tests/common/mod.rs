@@ -0,0 +1,37 @@
+//! Répertoire temporaire des tests, partagé par tests/, benches/ et les tests de src/main.rs
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Répertoire temporaire d'un test, supprimé au drop, donc aussi quand le test panique.
+/// Son nom porte le binaire, le pid et un compteur : deux tests, même de binaires
+/// lancés en même temps, n'ont jamais le même.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "git-ignore-{}-{}-{}-{}",
+            env!("CARGO_CRATE_NAME"),
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Reste d'un processus tué avec le même pid
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("creating the temporary directory");
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
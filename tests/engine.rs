@@ -0,0 +1,113 @@
+mod common;
+
+use common::TempDir;
+use gitignore_tui::{apply_rules_to_nodes, build_full_tree, parse_gitignore, should_be_ignored, Mode, ScanOptions};
+
+#[test]
+fn computes_the_ignored_entries_without_the_tui() {
+    let tmp = TempDir::new("engine");
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("target/debug")).unwrap();
+    std::fs::write(dir.join(".gitignore"), "/target\n!/target/keep\n").unwrap();
+    std::fs::write(dir.join("target/debug/app"), "").unwrap();
+    std::fs::write(dir.join("target/keep"), "").unwrap();
+    std::fs::write(dir.join("main.rs"), "").unwrap();
+
+    let rules = parse_gitignore(dir).unwrap();
+    let mut log = Vec::new();
+    let mut nodes = build_full_tree(dir, &ScanOptions::default(), &mut log).unwrap();
+    apply_rules_to_nodes(&mut nodes, &rules, false);
+
+    let state = |rel: &str| {
+        let n = nodes.iter().find(|n| n.rel == rel).unwrap();
+        (n.mode, n.mark)
+    };
+    assert_eq!(state("target"), (Mode::C, true));
+    assert_eq!(state("target/debug/app"), (Mode::N, true));
+    assert_eq!(state("target/keep"), (Mode::E, false));
+    assert_eq!(state("main.rs"), (Mode::N, false));
+    // Même verdict pour un chemin isolé (fichiers suivis par le VCS)
+    assert!(should_be_ignored("target/debug/app", &rules, false));
+    assert!(!should_be_ignored("target/keep", &rules, false));
+    assert!(log.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_directories_are_kept_as_leaves() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new("unreadable");
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("locked/inner")).unwrap();
+    std::fs::create_dir_all(dir.join("open")).unwrap();
+    std::fs::write(dir.join("open/a.txt"), "").unwrap();
+    let locked = dir.join("locked");
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+    // root (ou CAP_DAC_OVERRIDE) lit quand même le répertoire : rien à vérifier
+    let privileged = std::fs::read_dir(&locked).is_ok();
+
+    let mut log = Vec::new();
+    let nodes = build_full_tree(dir, &ScanOptions::default(), &mut log);
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    if privileged {
+        return;
+    }
+
+    let nodes = nodes.unwrap();
+    let rels: Vec<&str> = nodes.iter().map(|n| n.rel.as_str()).collect();
+    assert_eq!(rels, ["", "locked", "open", "open/a.txt"]);
+    assert!(nodes[1].unreadable);
+    assert!(!nodes[2].unreadable);
+    assert_eq!(log.len(), 1);
+}
+
+#[test]
+fn no_hidden_skips_dot_entries_but_keeps_gitignore_files() {
+    let tmp = TempDir::new("hidden");
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join(".git/objects")).unwrap();
+    std::fs::create_dir_all(dir.join(".idea")).unwrap();
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join(".gitignore"), "").unwrap();
+    std::fs::write(dir.join(".env"), "").unwrap();
+    std::fs::write(dir.join("src/.gitignore"), "").unwrap();
+    std::fs::write(dir.join("src/main.rs"), "").unwrap();
+
+    let rels = |opts: &ScanOptions| -> Vec<String> {
+        let nodes = build_full_tree(dir, opts, &mut Vec::new()).unwrap();
+        nodes.into_iter().map(|n| n.rel).collect()
+    };
+    let default = rels(&ScanOptions::default());
+    let no_hidden = rels(&ScanOptions { no_hidden: true, ..Default::default() });
+    let all = rels(&ScanOptions { no_hidden: true, all: true, ..Default::default() });
+
+    // .git n'apparaît jamais, sauf avec --all qui l'emporte aussi sur --no-hidden
+    assert_eq!(default, ["", ".idea", "src", "src/.gitignore", "src/main.rs", ".env", ".gitignore"]);
+    assert_eq!(no_hidden, ["", "src", "src/.gitignore", "src/main.rs", ".gitignore"]);
+    assert!(all.iter().any(|r| r == ".git/objects"));
+    assert!(all.iter().any(|r| r == ".env"));
+}
+
+#[test]
+fn parallel_scan_keeps_the_sequential_pre_order() {
+    let tmp = TempDir::new("parallel");
+    let dir = tmp.path();
+    for sub in ["a/x/deep", "a/y", "b", "c/z"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    for file in ["a/x/deep/1", "a/x/2", "a/y/3", "a/4", "b/5", "c/z/6", "7"] {
+        std::fs::write(dir.join(file), "").unwrap();
+    }
+
+    let scan = |threads: usize| {
+        let opts = ScanOptions { threads, ..Default::default() };
+        let nodes = build_full_tree(dir, &opts, &mut Vec::new()).unwrap();
+        nodes.into_iter().map(|n| (n.rel, n.depth)).collect::<Vec<_>>()
+    };
+    let sequential = scan(1);
+    let parallel = scan(4);
+
+    assert_eq!(sequential.len(), 15);
+    assert_eq!(parallel, sequential);
+}